@@ -0,0 +1,3 @@
+// libchip8.so and libchip8.a for C, exporting the functions in the core's
+// ffi module as declared in include/chip8.h
+pub use core8::ffi::*;
@@ -0,0 +1,126 @@
+#ifndef CHIP8_H
+#define CHIP8_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef struct Chip8 Chip8;
+
+typedef struct Chip8Frame {
+  bool display_changed;
+  bool beep;
+  bool halted;
+  uintptr_t instructions_executed;
+} Chip8Frame;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/*
+ Creates a chip with the default configuration. Free it with chip8_free.
+ */
+struct Chip8 *chip8_new(void);
+
+/*
+ Frees a chip made by chip8_new, null is ignored.
+
+ # Safety
+ chip must be null or a pointer from chip8_new not already freed.
+ */
+void chip8_free(struct Chip8 *chip);
+
+/*
+ Loads len bytes of rom at the load address. Returns 0, or -1 if it
+ doesn't fit.
+
+ # Safety
+ chip must come from chip8_new and data must point at len readable bytes.
+ */
+int chip8_load_rom(struct Chip8 *chip, const uint8_t *data, uintptr_t len);
+
+/*
+ Restarts the loaded rom from the beginning.
+
+ # Safety
+ chip must come from chip8_new.
+ */
+void chip8_reset(struct Chip8 *chip);
+
+/*
+ Presses or releases key 0x0 to 0xF, other keys are ignored.
+
+ # Safety
+ chip must come from chip8_new.
+ */
+void chip8_set_key(struct Chip8 *chip, uint8_t key, bool down);
+
+/*
+ Runs one 60Hz frame. Returns 0 and fills out if it isn't null, or -1
+ if the rom failed, see chip8_last_error.
+
+ # Safety
+ chip must come from chip8_new and out must be null or writable.
+ */
+int chip8_frame(struct Chip8 *chip, struct Chip8Frame *out);
+
+/*
+ Pixels of a display plane, eight to a byte with the most significant bit
+ leftmost and rows chip8_width / 8 bytes apart. Valid until the next call
+ that runs the chip.
+
+ # Safety
+ chip must come from chip8_new.
+ */
+const uint8_t *chip8_display_ptr(const struct Chip8 *chip, uintptr_t plane);
+
+/*
+ Bytes in a display plane at the current resolution.
+
+ # Safety
+ chip must come from chip8_new.
+ */
+uintptr_t chip8_display_len(const struct Chip8 *chip);
+
+/*
+ Display width in pixels.
+
+ # Safety
+ chip must come from chip8_new.
+ */
+uintptr_t chip8_width(const struct Chip8 *chip);
+
+/*
+ Display height in pixels.
+
+ # Safety
+ chip must come from chip8_new.
+ */
+uintptr_t chip8_height(const struct Chip8 *chip);
+
+/*
+ Whether a tone should be playing.
+
+ # Safety
+ chip must come from chip8_new.
+ */
+bool chip8_tone(const struct Chip8 *chip);
+
+/*
+ Describes the last failure, or null if nothing has failed. Valid until
+ the next failure or chip8_free.
+
+ # Safety
+ chip must come from chip8_new.
+ */
+const char *chip8_last_error(const struct Chip8 *chip);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* CHIP8_H */
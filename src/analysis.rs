@@ -0,0 +1,178 @@
+use alloc::collections::btree_map::Entry;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
+
+use crate::instruction::{decode_at, Instruction};
+
+// What following every path from the entry point found out about a rom.
+// Addresses are where the rom sits in memory, starting at base
+#[derive (Debug, Clone, PartialEq)]
+pub struct Analysis {
+    pub base: usize,
+    pub code: Vec<bool>,                        // Each rom byte, true if reached as an instruction
+    pub instructions: BTreeSet<u16>,            // Where each instruction reached starts
+    pub calls: BTreeMap<u16, BTreeSet<u16>>,    // The entry point and every subroutine, with those it calls
+    pub sprites: BTreeMap<u16, usize>,          // Addresses drawn from through I, with the most bytes drawn
+    pub unmapped: Vec<(u16, u16)>,              // Jumps, calls and fall throughs that leave the rom, from and to
+    pub indirect: Vec<u16>,                     // BNNN jumps, whose targets depend on V0 and can't be followed
+}
+
+pub fn analyze(rom: &[u8], base: usize) -> Analysis {
+    // Walks each subroutine in turn from the entry point, tracking the I
+    // register along the way so the sprites drawn can be found. Code only
+    // reached through BNNN is left looking like data
+    let in_rom = |addr: usize| addr >= base && addr - base < rom.len();
+    let mut code = vec![false; rom.len()];
+    let mut instructions = BTreeSet::new();
+    let mut calls: BTreeMap<u16, BTreeSet<u16>> = BTreeMap::new();
+    let mut sprites: BTreeMap<u16, usize> = BTreeMap::new();
+    let mut unmapped = BTreeSet::new();
+    let mut indirect = BTreeSet::new();
+
+    let mut routines = vec![base as u16];
+    calls.insert(base as u16, BTreeSet::new());
+    while let Some(routine) = routines.pop() {
+        let mut callees = BTreeSet::new();
+        let mut visited = BTreeSet::new();
+        let mut pending = vec![(routine as usize, None::<u16>)];
+        while let Some((addr, mut i)) = pending.pop() {
+            if !in_rom(addr) || !visited.insert(addr) {
+                continue;
+            }
+            let offset = addr - base;
+            let (instruction, size) = decode_at(rom, offset);
+            instructions.insert(addr as u16);
+            for byte in &mut code[offset..(offset + size).min(rom.len())] {
+                *byte = true;
+            }
+            let next = addr + size;
+            let mut targets = Vec::new();
+            match instruction {
+                Instruction::Jp { nnn } => targets.push(nnn as usize),
+                Instruction::Call { nnn } => {
+                    callees.insert(nnn);
+                    if !in_rom(nnn as usize) {
+                        unmapped.insert((addr as u16, nnn));
+                    } else if let Entry::Vacant(entry) = calls.entry(nnn) {
+                        entry.insert(BTreeSet::new());
+                        routines.push(nnn);
+                    }
+                    targets.push(next);
+                }
+                Instruction::SeByte { .. } | Instruction::SneByte { .. } | Instruction::SeReg { .. }
+                | Instruction::SneReg { .. } | Instruction::Skp { .. } | Instruction::Sknp { .. } => {
+                    // A skip steps over a whole instruction, four bytes for F000
+                    let skipped = if in_rom(next) { decode_at(rom, next - base).1 } else { 2 };
+                    targets.extend([next, next + skipped]);
+                }
+                Instruction::JpV0 { .. } => {
+                    indirect.insert(addr as u16);
+                }
+                Instruction::Ret | Instruction::Exit | Instruction::Unknown(_) => {}
+                Instruction::LdI { nnn } => {
+                    i = Some(nnn);
+                    targets.push(next);
+                }
+                Instruction::LdILong { nnnn } => {
+                    i = Some(nnnn);
+                    targets.push(next);
+                }
+                Instruction::Drw { n, .. } => {
+                    // DXY0 draws a 16x16 sprite on SUPER-CHIP
+                    if let Some(i) = i {
+                        let bytes = if n == 0 { 32 } else { n as usize };
+                        let most = sprites.entry(i).or_insert(0);
+                        *most = bytes.max(*most);
+                    }
+                    targets.push(next);
+                }
+                Instruction::AddI { .. } | Instruction::LdF { .. } | Instruction::LdHf { .. }
+                | Instruction::LdMemVx { .. } | Instruction::LdVxMem { .. } => {
+                    i = None;
+                    targets.push(next);
+                }
+                _ => targets.push(next),
+            }
+            for target in targets {
+                if in_rom(target) {
+                    pending.push((target, i));
+                } else {
+                    unmapped.insert((addr as u16, target as u16));
+                }
+            }
+        }
+        calls.insert(routine, callees);
+    }
+
+    Analysis {
+        base,
+        code,
+        instructions,
+        calls,
+        sprites,
+        unmapped: unmapped.into_iter().collect(),
+        indirect: indirect.into_iter().collect(),
+    }
+}
+
+impl Analysis {
+    pub fn data_regions(&self) -> Vec<Range<usize>> {
+        // Runs of bytes never reached as code, by address
+        let mut regions: Vec<Range<usize>> = Vec::new();
+        for (offset, _) in self.code.iter().enumerate().filter(|(_, &code)| !code) {
+            let addr = self.base + offset;
+            match regions.last_mut() {
+                Some(region) if region.end == addr => region.end += 1,
+                _ => regions.push(addr..addr + 1),
+            }
+        }
+        regions
+    }
+
+    pub fn is_sprite(&self, addr: usize) -> bool {
+        self.sprites.iter().any(|(&start, &len)| (start as usize..start as usize + len).contains(&addr))
+    }
+}
+
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let code = self.code.iter().filter(|&&code| code).count();
+        writeln!(f, "{} bytes: {} code, {} data", self.code.len(), code, self.code.len() - code)?;
+
+        writeln!(f, "data regions:")?;
+        for region in self.data_regions() {
+            writeln!(f, "  0x{:03X}-0x{:03X} ({} bytes)", region.start, region.end - 1, region.len())?;
+        }
+        writeln!(f, "subroutines:")?;
+        for (routine, callees) in &self.calls {
+            let callees: Vec<String> = callees.iter().map(|addr| format!("0x{:03X}", addr)).collect();
+            if callees.is_empty() {
+                writeln!(f, "  0x{:03X}", routine)?;
+            } else {
+                writeln!(f, "  0x{:03X} calls {}", routine, callees.join(", "))?;
+            }
+        }
+        writeln!(f, "sprites:")?;
+        for (addr, len) in &self.sprites {
+            writeln!(f, "  0x{:03X} ({} bytes)", addr, len)?;
+        }
+        if !self.unmapped.is_empty() {
+            writeln!(f, "jumps outside the rom:")?;
+            for (from, to) in &self.unmapped {
+                writeln!(f, "  0x{:03X} -> 0x{:03X}", from, to)?;
+            }
+        }
+        if !self.indirect.is_empty() {
+            writeln!(f, "jumps through V0, not followed:")?;
+            for addr in &self.indirect {
+                writeln!(f, "  0x{:03X}", addr)?;
+            }
+        }
+        Ok(())
+    }
+}
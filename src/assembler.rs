@@ -0,0 +1,597 @@
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::symbols::Symbols;
+use crate::ROM_SIZE;
+
+// A rom assembled from Octo source, with the labels it defined
+#[derive (Debug, Clone, PartialEq)]
+pub struct Assembly {
+    pub rom: Vec<u8>,
+    pub symbols: Symbols,
+}
+
+// Why a source file didn't assemble, and the line it happened on counting from 1
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub struct AssembleError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+// The right hand side of a comparison or an arithmetic statement
+#[derive (Debug, Clone, Copy)]
+enum Operand {
+    Register(u8),
+    Value(u8),
+}
+
+// What a conditional skip tests, once any comparison has been worked out in VF
+#[derive (Debug, Clone, Copy)]
+enum Test {
+    Eq(u8, Operand),
+    Ne(u8, Operand),
+    Key(u8),
+    NotKey(u8),
+}
+
+// An open if, else or loop, with the jumps to fill in when it closes
+#[derive (Debug)]
+enum Block {
+    If(usize),
+    Else(usize),
+    Loop(usize, Vec<usize>),
+}
+
+// A name used as an address before it was defined
+#[derive (Debug)]
+struct Fixup<'a> {
+    at: usize,
+    long: bool,         // A 16 bit i := long operand rather than the low 12 bits
+    name: &'a str,
+    line: usize,
+}
+
+struct Assembler<'a> {
+    tokens: Vec<(usize, &'a str)>,
+    pos: usize,
+    line: usize,
+    rom: Vec<u8>,       // Everything emitted, starting at ROM_SIZE
+    here: usize,
+    labels: BTreeMap<&'a str, u16>,
+    consts: BTreeMap<&'a str, i32>,
+    aliases: BTreeMap<&'a str, u8>,
+    fixups: Vec<Fixup<'a>>,
+    blocks: Vec<Block>,
+}
+
+type Result<T> = core::result::Result<T, AssembleError>;
+
+pub fn assemble(source: &str) -> Result<Assembly> {
+    // Octo's core syntax: labels, :const, :alias, :org, :byte and bare numbers
+    // for sprite data, the register, i and timer statements, if/then,
+    // if/begin/else/end and loop/while/again. As in Octo the program starts
+    // at main, with a jump there unless it comes first
+    let tokens = source.lines().enumerate().flat_map(|(n, line)| {
+        line.split('#').next().unwrap_or("").split_whitespace().map(move |token| (n + 1, token))
+    }).collect();
+    let mut asm = Assembler {
+        tokens,
+        pos: 0,
+        line: 1,
+        rom: Vec::new(),
+        here: ROM_SIZE,
+        labels: BTreeMap::new(),
+        consts: BTreeMap::new(),
+        aliases: BTreeMap::new(),
+        fixups: Vec::new(),
+        blocks: Vec::new(),
+    };
+    asm.address_of(0x1000, "main")?;
+    while asm.pos < asm.tokens.len() {
+        asm.statement()?;
+    }
+    match asm.blocks.last() {
+        Some(Block::Loop(..)) => asm.error("loop without again"),
+        Some(_) => asm.error("if without end"),
+        None => asm.resolve(),
+    }
+}
+
+impl<'a> Assembler<'a> {
+    fn error<T>(&self, message: &str) -> Result<T> {
+        Err(AssembleError { line: self.line, message: message.to_string() })
+    }
+
+    fn next(&mut self) -> Result<&'a str> {
+        match self.tokens.get(self.pos) {
+            Some(&(line, token)) => {
+                self.pos += 1;
+                self.line = line;
+                Ok(token)
+            }
+            None => self.error("unexpected end of file"),
+        }
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<()> {
+        match self.next()? {
+            token if token == expected => Ok(()),
+            token => self.error(&format!("expected {}, found {}", expected, token)),
+        }
+    }
+
+    fn emit(&mut self, byte: u8) -> Result<()> {
+        let offset = self.here - ROM_SIZE;
+        if offset >= 0x10000 - ROM_SIZE {
+            return self.error("program doesn't fit in memory");
+        }
+        if offset >= self.rom.len() {
+            self.rom.resize(offset + 1, 0);
+        }
+        self.rom[offset] = byte;
+        self.here += 1;
+        Ok(())
+    }
+
+    fn word(&mut self, word: u16) -> Result<()> {
+        self.emit((word >> 8) as u8)?;
+        self.emit(word as u8)
+    }
+
+    fn lookup(&self, token: &str) -> Option<i32> {
+        parse_number(token)
+            .or_else(|| self.consts.get(token).copied())
+            .or_else(|| self.labels.get(token).map(|&addr| addr as i32))
+    }
+
+    fn value(&mut self) -> Result<i32> {
+        let token = self.next()?;
+        match self.lookup(token) {
+            Some(value) => Ok(value),
+            None => self.error(&format!("unknown value {}", token)),
+        }
+    }
+
+    fn byte_of(&self, token: &str) -> Result<u8> {
+        // Bytes may be written signed, so -1 is 0xFF
+        match self.lookup(token) {
+            Some(value) if (-128..=255).contains(&value) => Ok(value as u8),
+            Some(_) => self.error(&format!("{} doesn't fit in a byte", token)),
+            None => self.error(&format!("unknown value {}", token)),
+        }
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        let token = self.next()?;
+        self.byte_of(token)
+    }
+
+    fn nibble(&mut self) -> Result<u16> {
+        match self.value()? {
+            value if (0..=15).contains(&value) => Ok(value as u16),
+            _ => self.error("expected a value from 0 to 15"),
+        }
+    }
+
+    fn register(&self, token: &str) -> Option<u8> {
+        if let Some(&reg) = self.aliases.get(token) {
+            return Some(reg);
+        }
+        match token.strip_prefix('v').or_else(|| token.strip_prefix('V')) {
+            Some(digit) if digit.len() == 1 => u8::from_str_radix(digit, 16).ok(),
+            _ => None,
+        }
+    }
+
+    fn reg(&mut self) -> Result<u16> {
+        let token = self.next()?;
+        match self.register(token) {
+            Some(reg) => Ok(reg as u16),
+            None => self.error(&format!("expected a register, found {}", token)),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand> {
+        let token = self.next()?;
+        match self.register(token) {
+            Some(reg) => Ok(Operand::Register(reg)),
+            None => Ok(Operand::Value(self.byte_of(token)?)),
+        }
+    }
+
+    fn name(&mut self) -> Result<&'a str> {
+        let token = self.next()?;
+        if self.register(token).is_some() || parse_number(token).is_some() {
+            return self.error(&format!("{} can't be used as a name", token));
+        }
+        Ok(token)
+    }
+
+    fn address_of(&mut self, opcode: u16, token: &'a str) -> Result<()> {
+        // Names not defined yet are filled in once the whole file is read
+        match self.lookup(token) {
+            Some(addr) if (0..=0xFFF).contains(&addr) => self.word(opcode | addr as u16),
+            Some(_) => self.error(&format!("{} is out of reach of a 12 bit address", token)),
+            None => {
+                self.fixups.push(Fixup { at: self.here, long: false, name: token, line: self.line });
+                self.word(opcode)
+            }
+        }
+    }
+
+    fn address(&mut self, opcode: u16) -> Result<()> {
+        let token = self.next()?;
+        self.address_of(opcode, token)
+    }
+
+    fn long_address(&mut self) -> Result<()> {
+        self.word(0xF000)?;
+        let token = self.next()?;
+        match self.lookup(token) {
+            Some(addr) if (0..=0xFFFF).contains(&addr) => self.word(addr as u16),
+            Some(_) => self.error(&format!("{} is out of reach of a 16 bit address", token)),
+            None => {
+                self.fixups.push(Fixup { at: self.here, long: true, name: token, line: self.line });
+                self.word(0)
+            }
+        }
+    }
+
+    fn define(&mut self, name: &'a str) -> Result<()> {
+        if self.labels.contains_key(name) {
+            return self.error(&format!("{} is defined twice", name));
+        }
+        // The jump to main is only needed when something comes before it
+        if name == "main" && self.here == ROM_SIZE + 2 && self.rom.len() == 2 {
+            self.rom.clear();
+            self.here = ROM_SIZE;
+            self.fixups.retain(|fixup| fixup.at != ROM_SIZE);
+        }
+        self.labels.insert(name, self.here as u16);
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<()> {
+        let token = self.next()?;
+        match token {
+            ":" => {
+                let name = self.name()?;
+                self.define(name)
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.value()?;
+                self.consts.insert(name, value);
+                Ok(())
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let reg = self.reg()?;
+                self.aliases.insert(name, reg as u8);
+                Ok(())
+            }
+            ":org" => match self.value()? {
+                addr if (ROM_SIZE as i32..0x10000).contains(&addr) => {
+                    self.here = addr as usize;
+                    Ok(())
+                }
+                _ => self.error("can't :org outside the rom"),
+            },
+            ":byte" => {
+                let byte = self.byte()?;
+                self.emit(byte)
+            }
+            ":call" => self.address(0x2000),
+            "clear" => self.word(0x00E0),
+            "return" | ";" => self.word(0x00EE),
+            "scroll-right" => self.word(0x00FB),
+            "scroll-left" => self.word(0x00FC),
+            "exit" => self.word(0x00FD),
+            "lores" => self.word(0x00FE),
+            "hires" => self.word(0x00FF),
+            "audio" => self.word(0xF002),
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.word(0x00C0 | n)
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.word(0x00D0 | n)
+            }
+            "plane" => {
+                let n = self.nibble()?;
+                self.word(0xF001 | n << 8)
+            }
+            "bcd" => {
+                let x = self.reg()?;
+                self.word(0xF033 | x << 8)
+            }
+            "saveflags" => {
+                let x = self.reg()?;
+                self.word(0xF075 | x << 8)
+            }
+            "loadflags" => {
+                let x = self.reg()?;
+                self.word(0xF085 | x << 8)
+            }
+            "save" => self.save_load(0xF055, 0x5002),
+            "load" => self.save_load(0xF065, 0x5003),
+            "sprite" => {
+                let x = self.reg()?;
+                let y = self.reg()?;
+                let n = self.nibble()?;
+                self.word(0xD000 | x << 8 | y << 4 | n)
+            }
+            "jump" => self.address(0x1000),
+            "jump0" => self.address(0xB000),
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.reg()?;
+                let opcode = match token {
+                    "delay" => 0xF015,
+                    "buzzer" => 0xF018,
+                    _ => 0xF03A,
+                };
+                self.word(opcode | x << 8)
+            }
+            "i" => self.index(),
+            "if" => self.conditional(),
+            "else" => match self.blocks.pop() {
+                Some(Block::If(jump)) => {
+                    let end = self.placeholder()?;
+                    self.patch(jump, self.here)?;
+                    self.blocks.push(Block::Else(end));
+                    Ok(())
+                }
+                _ => self.error("else without if"),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If(jump)) | Some(Block::Else(jump)) => self.patch(jump, self.here),
+                _ => self.error("end without if"),
+            },
+            "loop" => {
+                self.blocks.push(Block::Loop(self.here, Vec::new()));
+                Ok(())
+            }
+            "while" => {
+                // Leaves the innermost loop when the condition fails
+                let test = self.condition()?;
+                self.skip(test, true)?;
+                let exit = self.placeholder()?;
+                match self.blocks.iter_mut().rev().find(|block| matches!(block, Block::Loop(..))) {
+                    Some(Block::Loop(_, exits)) => {
+                        exits.push(exit);
+                        Ok(())
+                    }
+                    _ => self.error("while outside a loop"),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop(start, exits)) => {
+                    let back = self.placeholder()?;
+                    self.patch(back, start)?;
+                    for exit in exits {
+                        self.patch(exit, self.here)?;
+                    }
+                    Ok(())
+                }
+                _ => self.error("again without loop"),
+            },
+            _ => {
+                if let Some(x) = self.register(token) {
+                    return self.arithmetic(x as u16);
+                }
+                // Numbers and constants are data, any other name is a call
+                if parse_number(token).is_some() || self.consts.contains_key(token) {
+                    let byte = self.byte_of(token)?;
+                    return self.emit(byte);
+                }
+                self.address_of(0x2000, token)
+            }
+        }
+    }
+
+    fn save_load(&mut self, single: u16, range: u16) -> Result<()> {
+        // Either v0 to vx, or XO-CHIP's vx - vy
+        let x = self.reg()?;
+        if self.tokens.get(self.pos).map(|&(_, token)| token) == Some("-") {
+            self.pos += 1;
+            let y = self.reg()?;
+            return self.word(range | x << 8 | y << 4);
+        }
+        self.word(single | x << 8)
+    }
+
+    fn index(&mut self) -> Result<()> {
+        match self.next()? {
+            "+=" => {
+                let x = self.reg()?;
+                self.word(0xF01E | x << 8)
+            }
+            ":=" => match self.next()? {
+                "hex" => {
+                    let x = self.reg()?;
+                    self.word(0xF029 | x << 8)
+                }
+                "bighex" => {
+                    let x = self.reg()?;
+                    self.word(0xF030 | x << 8)
+                }
+                "long" => self.long_address(),
+                token => self.address_of(0xA000, token),
+            },
+            token => self.error(&format!("expected := or += after i, found {}", token)),
+        }
+    }
+
+    fn arithmetic(&mut self, x: u16) -> Result<()> {
+        let op = self.next()?;
+        let alu = match op {
+            ":=" => {
+                let token = self.next()?;
+                return match token {
+                    "random" => {
+                        let mask = self.byte()?;
+                        self.word(0xC000 | x << 8 | mask as u16)
+                    }
+                    "delay" => self.word(0xF007 | x << 8),
+                    "key" => self.word(0xF00A | x << 8),
+                    _ => match self.register(token) {
+                        Some(y) => self.word(0x8000 | x << 8 | (y as u16) << 4),
+                        None => {
+                            let value = self.byte_of(token)?;
+                            self.word(0x6000 | x << 8 | value as u16)
+                        }
+                    },
+                };
+            }
+            "+=" | "-=" => {
+                return match (op, self.operand()?) {
+                    ("+=", Operand::Register(y)) => self.word(0x8004 | x << 8 | (y as u16) << 4),
+                    ("+=", Operand::Value(n)) => self.word(0x7000 | x << 8 | n as u16),
+                    (_, Operand::Register(y)) => self.word(0x8005 | x << 8 | (y as u16) << 4),
+                    (_, Operand::Value(n)) => self.word(0x7000 | x << 8 | n.wrapping_neg() as u16),
+                };
+            }
+            "|=" => 0x8001,
+            "&=" => 0x8002,
+            "^=" => 0x8003,
+            ">>=" => 0x8006,
+            "=-" => 0x8007,
+            "<<=" => 0x800E,
+            _ => return self.error(&format!("unknown operator {}", op)),
+        };
+        let y = self.reg()?;
+        self.word(alu | x << 8 | y << 4)
+    }
+
+    fn condition(&mut self) -> Result<Test> {
+        // Ordering comparisons subtract into VF and test the borrow flag left there
+        let x = self.reg()?;
+        let op = self.next()?;
+        match op {
+            "key" => Ok(Test::Key(x as u8)),
+            "-key" => Ok(Test::NotKey(x as u8)),
+            "==" => Ok(Test::Eq(x as u8, self.operand()?)),
+            "!=" => Ok(Test::Ne(x as u8, self.operand()?)),
+            "<" | ">" | "<=" | ">=" => {
+                match self.operand()? {
+                    Operand::Register(y) => self.word(0x8F00 | (y as u16) << 4)?,
+                    Operand::Value(n) => self.word(0x6F00 | n as u16)?,
+                }
+                // VF = vx - rhs leaves 1 when vx >= rhs, VF = rhs - vx when rhs >= vx
+                match op {
+                    "<" | ">=" => self.word(0x8F07 | x << 4)?,
+                    _ => self.word(0x8F05 | x << 4)?,
+                }
+                match op {
+                    "<" | ">" => Ok(Test::Eq(0xF, Operand::Value(0))),
+                    _ => Ok(Test::Ne(0xF, Operand::Value(0))),
+                }
+            }
+            _ => self.error(&format!("unknown comparison {}", op)),
+        }
+    }
+
+    fn skip(&mut self, test: Test, when: bool) -> Result<()> {
+        // Emits the instruction that skips the next one when test comes out as when
+        let (test, when) = match test {
+            Test::Ne(x, rhs) => (Test::Eq(x, rhs), !when),
+            Test::NotKey(x) => (Test::Key(x), !when),
+            test => (test, when),
+        };
+        let opcode = match (test, when) {
+            (Test::Eq(x, Operand::Value(n)), true) => 0x3000 | (x as u16) << 8 | n as u16,
+            (Test::Eq(x, Operand::Value(n)), false) => 0x4000 | (x as u16) << 8 | n as u16,
+            (Test::Eq(x, Operand::Register(y)), true) => 0x5000 | (x as u16) << 8 | (y as u16) << 4,
+            (Test::Eq(x, Operand::Register(y)), false) => 0x9000 | (x as u16) << 8 | (y as u16) << 4,
+            (Test::Key(x), true) => 0xE09E | (x as u16) << 8,
+            (Test::Key(x), false) => 0xE0A1 | (x as u16) << 8,
+            _ => unreachable!(),
+        };
+        self.word(opcode)
+    }
+
+    fn conditional(&mut self) -> Result<()> {
+        // then skips the one statement after it, begin jumps past the block
+        let test = self.condition()?;
+        match self.next()? {
+            "then" => self.skip(test, false),
+            "begin" => {
+                self.skip(test, true)?;
+                let jump = self.placeholder()?;
+                self.blocks.push(Block::If(jump));
+                Ok(())
+            }
+            token => self.error(&format!("expected then or begin, found {}", token)),
+        }
+    }
+
+    fn placeholder(&mut self) -> Result<usize> {
+        let at = self.here;
+        self.word(0x1000)?;
+        Ok(at)
+    }
+
+    fn patch(&mut self, at: usize, target: usize) -> Result<()> {
+        if target > 0xFFF {
+            return self.error("jump target is out of reach of a 12 bit address");
+        }
+        let offset = at - ROM_SIZE;
+        self.rom[offset] |= (target >> 8) as u8;
+        self.rom[offset + 1] = target as u8;
+        Ok(())
+    }
+
+    fn resolve(mut self) -> Result<Assembly> {
+        // Fills in the names used before their definitions
+        for fixup in core::mem::take(&mut self.fixups) {
+            self.line = fixup.line;
+            let offset = fixup.at - ROM_SIZE;
+            match (self.lookup(fixup.name), fixup.long) {
+                (Some(addr), true) if (0..=0xFFFF).contains(&addr) => {
+                    self.rom[offset] = (addr >> 8) as u8;
+                    self.rom[offset + 1] = addr as u8;
+                }
+                (Some(addr), false) if (0..=0xFFF).contains(&addr) => {
+                    self.rom[offset] |= (addr >> 8) as u8;
+                    self.rom[offset + 1] = addr as u8;
+                }
+                (Some(_), _) => return self.error(&format!("{} is out of reach", fixup.name)),
+                (None, _) => return self.error(&format!("{} is never defined", fixup.name)),
+            }
+        }
+        let mut symbols = Symbols::new();
+        for (name, &addr) in &self.labels {
+            symbols.insert(name, addr);
+        }
+        Ok(Assembly { rom: self.rom, symbols })
+    }
+}
+
+fn parse_number(token: &str) -> Option<i32> {
+    // Decimal, 0x hex or 0b binary, any of them negative
+    let (negative, digits) = match token.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, token),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i32::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+        i32::from_str_radix(binary, 2).ok()?
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
@@ -0,0 +1,141 @@
+use crate::chip::{Chip, IllegalPolicy, Variant};
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+
+// Collects configuration for a new chip, anything left unset keeps the
+// default Chip::new would give it
+#[derive (Clone, Default)]
+pub struct ChipBuilder {
+    platform: Option<Platform>,
+    variant: Option<Variant>,
+    quirks: Option<Quirks>,
+    clock: Option<usize>,
+    ipf: Option<usize>,
+    seed: Option<u64>,
+    fill: Option<u8>,
+    memory_size: Option<usize>,
+    stack_depth: Option<usize>,
+    load_address: Option<usize>,
+    illegal_policy: Option<IllegalPolicy>,
+    two_page: bool,
+    vip_cycle_timing: bool,
+}
+
+impl ChipBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn platform(mut self, platform: Platform) -> Self {
+        // Starts from a preset, the other options then override parts of it
+        self.platform = Some(platform);
+        self
+    }
+
+    pub fn variant(mut self, variant: Variant) -> Self {
+        self.variant = Some(variant);
+        self
+    }
+
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = Some(quirks);
+        self
+    }
+
+    pub fn clock(mut self, hz: usize) -> Self {
+        self.clock = Some(hz);
+        self
+    }
+
+    pub fn instructions_per_frame(mut self, ipf: usize) -> Self {
+        self.ipf = Some(ipf);
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn fill(mut self, byte: u8) -> Self {
+        self.fill = Some(byte);
+        self
+    }
+
+    pub fn memory_size(mut self, size: usize) -> Self {
+        self.memory_size = Some(size);
+        self
+    }
+
+    pub fn stack_depth(mut self, depth: usize) -> Self {
+        self.stack_depth = Some(depth);
+        self
+    }
+
+    pub fn load_address(mut self, addr: usize) -> Self {
+        self.load_address = Some(addr);
+        self
+    }
+
+    pub fn illegal_policy(mut self, policy: IllegalPolicy) -> Self {
+        self.illegal_policy = Some(policy);
+        self
+    }
+
+    pub fn two_page(mut self, enabled: bool) -> Self {
+        self.two_page = enabled;
+        self
+    }
+
+    pub fn vip_cycle_timing(mut self, enabled: bool) -> Self {
+        self.vip_cycle_timing = enabled;
+        self
+    }
+
+    pub fn build(self) -> Chip<'static> {
+        // Settings are applied from the broadest to the most specific, so
+        // e.g. a clock speed beats the one a platform preset brings
+        // Contradictory combinations show up in Chip::take_warnings
+        let mut chip = match self.fill {
+            Some(byte) => Chip::with_fill(byte),
+            None => Chip::new(),
+        };
+        if let Some(platform) = self.platform {
+            chip.set_platform(platform);
+        }
+        if let Some(variant) = self.variant {
+            chip.set_variant(variant);
+        }
+        if let Some(quirks) = self.quirks {
+            chip.set_quirks(quirks);
+        }
+        if let Some(hz) = self.clock {
+            chip.set_clock_speed(hz);
+        }
+        if let Some(ipf) = self.ipf {
+            chip.set_instructions_per_frame(ipf);
+        }
+        if let Some(addr) = self.load_address {
+            chip.set_load_address(addr);
+        }
+        if let Some(size) = self.memory_size {
+            chip.set_memory_size(size);
+        }
+        if let Some(depth) = self.stack_depth {
+            chip.set_stack_depth(depth);
+        }
+        if let Some(seed) = self.seed {
+            chip.set_seed(seed);
+        }
+        if let Some(policy) = self.illegal_policy {
+            chip.set_illegal_policy(policy);
+        }
+        if self.two_page {
+            chip.set_two_page(true);
+        }
+        if self.vip_cycle_timing {
+            chip.set_vip_cycle_timing(true);
+        }
+        chip
+    }
+}
@@ -1,22 +1,344 @@
 use rand;
-use rand::Rng;
-use fonts::FONT_SET;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::Cell;
+use core::f32::consts::SQRT_2;
+use core::fmt;
+use core::ops::Range;
+use core::time::Duration;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "std")]
+use std::time::Instant;
+use crate::fonts::{FONT_SET, BIG_FONT_SET, BIG_FONT_ADDR};
+use crate::instruction::{decode, Instruction};
+use crate::keypad::{KeyEvent, Keypad};
+use crate::quirks::Quirks;
+use crate::platform::Platform;
+use crate::builder::ChipBuilder;
+use crate::condition::Condition;
+use crate::observer::ChipObserver;
+use crate::snapshot::Snapshot;
+#[cfg(feature = "async")]
+use crate::stream::FrameStream;
 
-use CHIP8_WIDTH;
-use CHIP8_HEIGHT;
-use CHIP8_MEM;
-use ROM_SIZE;
-use OPCODE_SIZE;
-use FRAME_TIME;
+use crate::CHIP8_WIDTH;
+use crate::CHIP8_HEIGHT;
+use crate::SCHIP_WIDTH;
+use crate::SCHIP_HEIGHT;
+use crate::CHIP8_MEM;
+use crate::XO_CHIP_MEM;
+use crate::ROM_SIZE;
+use crate::OPCODE_SIZE;
+use crate::FRAME_TIME;
 
-#[derive (Debug)]
+const STEP_OVER_LIMIT: usize = 1_000_000;
+const DISP_SIZE: usize = SCHIP_WIDTH * SCHIP_HEIGHT / 8;
+const MAX_CYCLES_PER_FRAME: usize = 100_000;
+const DEFAULT_CLOCK_HZ: usize = 700;
+const DEFAULT_STACK_DEPTH: usize = 16;
+// The frame budget is tracked in nanoseconds so any clock speed divides it evenly enough
+const FRAME_NANOS: isize = FRAME_TIME * 1000;
+const VIP_CYCLE_NANOS: usize = 4544;    // One 1802 machine cycle at the VIP's 1.76MHz
+const VIP_FETCH_CYCLES: usize = 40;     // Machine cycles the VIP spends fetching and decoding
+
+// Keys as laid out on the COSMAC VIP hex keypad
+const KEYPAD_LAYOUT: [[usize; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xc],
+    [0x4, 0x5, 0x6, 0xd],
+    [0x7, 0x8, 0x9, 0xe],
+    [0xa, 0x0, 0xb, 0xf],
+];
+
+// 2^(n/16) and 2^(n/48), so the XO-CHIP pitch can be worked out without
+// powf, which core doesn't have
+const PITCH_SIXTEENTHS: [f32; 16] = [
+    1.0000000, 1.0442738, 1.0905077, 1.1387886, 1.1892071, 1.2418578, 1.2968396, 1.3542555,
+    SQRT_2, 1.4768261, 1.5422108, 1.6104903, 1.6817928, 1.7562522, 1.8340081, 1.9152066,
+];
+const PITCH_THIRDS: [f32; 3] = [1.0000000, 1.0145453, 1.0293022];
+
+// Every opcode exec dispatches on, as (pattern, mnemonic, description).
+// Entries are matched to instructions by decoding their pattern, so the
+// decoder is what says which instruction each one is
+const OPCODES: [(u16, &str, &str); 50] = [
+    (0x00E0, "CLS", "Clear the display"),
+    (0x00EE, "RET", "Return from a subroutine"),
+    (0x1000, "JP nnn", "Jump to nnn"),
+    (0x2000, "CALL nnn", "Call the subroutine at nnn"),
+    (0x3000, "SE Vx, kk", "Skip the next instruction if Vx == kk"),
+    (0x4000, "SNE Vx, kk", "Skip the next instruction if Vx != kk"),
+    (0x5000, "SE Vx, Vy", "Skip the next instruction if Vx == Vy"),
+    (0x6000, "LD Vx, kk", "Set Vx = kk"),
+    (0x7000, "ADD Vx, kk", "Set Vx = Vx + kk"),
+    (0x8000, "LD Vx, Vy", "Set Vx = Vy"),
+    (0x8001, "OR Vx, Vy", "Set Vx = Vx OR Vy"),
+    (0x8002, "AND Vx, Vy", "Set Vx = Vx AND Vy"),
+    (0x8003, "XOR Vx, Vy", "Set Vx = Vx XOR Vy"),
+    (0x8004, "ADD Vx, Vy", "Set Vx = Vx + Vy, VF = carry"),
+    (0x8005, "SUB Vx, Vy", "Set Vx = Vx - Vy, VF = NOT borrow"),
+    (0x8006, "SHR Vx", "Set Vx = Vx SHR 1, VF = shifted out bit"),
+    (0x8007, "SUBN Vx, Vy", "Set Vx = Vy - Vx, VF = NOT borrow"),
+    (0x800E, "SHL Vx", "Set Vx = Vx SHL 1, VF = shifted out bit"),
+    (0x9000, "SNE Vx, Vy", "Skip the next instruction if Vx != Vy"),
+    (0xA000, "LD I, nnn", "Set I = nnn"),
+    (0xB000, "JP V0, nnn", "Jump to nnn + V0"),
+    (0xC000, "RND Vx, kk", "Set Vx = random byte AND kk"),
+    (0xD000, "DRW Vx, Vy, n", "Draw an n byte sprite from I at (Vx, Vy), VF = collision"),
+    (0xE09E, "SKP Vx", "Skip the next instruction if key Vx is down"),
+    (0xE0A1, "SKNP Vx", "Skip the next instruction if key Vx is up"),
+    (0xF007, "LD Vx, DT", "Set Vx = delay timer"),
+    (0xF00A, "LD Vx, K", "Wait for a key press and store it in Vx"),
+    (0xF015, "LD DT, Vx", "Set delay timer = Vx"),
+    (0xF018, "LD ST, Vx", "Set sound timer = Vx"),
+    (0xF01E, "ADD I, Vx", "Set I = I + Vx"),
+    (0xF029, "LD F, Vx", "Set I to the font sprite for digit Vx"),
+    (0xF033, "LD B, Vx", "Store the BCD digits of Vx at I, I+1, I+2"),
+    (0xF055, "LD [I], Vx", "Store V0 to Vx in memory starting at I"),
+    (0xF065, "LD Vx, [I]", "Read V0 to Vx from memory starting at I"),
+    (0x00C0, "SCD n", "SCHIP: scroll the display down n pixels"),
+    (0x00FB, "SCR", "SCHIP: scroll the display right 4 pixels"),
+    (0x00FC, "SCL", "SCHIP: scroll the display left 4 pixels"),
+    (0x00FD, "EXIT", "SCHIP: stop the interpreter"),
+    (0x00FE, "LOW", "SCHIP: switch to 64x32 low resolution"),
+    (0x00FF, "HIGH", "SCHIP: switch to 128x64 high resolution"),
+    (0xF030, "LD HF, Vx", "SCHIP: set I to the big font sprite for digit Vx"),
+    (0xF075, "LD R, Vx", "SCHIP: store V0 to Vx in the RPL flags"),
+    (0xF085, "LD Vx, R", "SCHIP: read V0 to Vx from the RPL flags"),
+    (0x00D0, "SCU n", "XO-CHIP: scroll the display up n pixels"),
+    (0x5002, "SAVE Vx - Vy", "XO-CHIP: store Vx to Vy in memory starting at I"),
+    (0x5003, "LOAD Vx - Vy", "XO-CHIP: read Vx to Vy from memory starting at I"),
+    (0xF000, "LD I, nnnn", "XO-CHIP: set I to the 16 bit address in the next word"),
+    (0xF001, "PLANE n", "XO-CHIP: select the bitplanes drawing affects"),
+    (0xF002, "AUDIO", "XO-CHIP: load the 16 byte audio pattern from I"),
+    (0xF03A, "PITCH Vx", "XO-CHIP: set the audio pattern playback pitch"),
+];
+
+// Errors that happen while running carry the pc and opcode of the
+// instruction at fault, Display gives a one line description
+#[derive (Debug, thiserror::Error)]
 pub enum Error {
-    InvalidOperation(u8, u8),
-    RomTooLarge(usize),
-    PcOutOfBounds(u16),
+    #[error("unknown opcode {opcode:#06X} at {pc:#05X}")]
+    InvalidOperation { pc: u16, opcode: u16 },
+    #[error("rom is {size} bytes but only {max} fit in memory")]
+    RomTooLarge { size: usize, max: usize },
+    #[error("program counter {pc:#05X} ran off the end of memory")]
+    PcOutOfBounds { pc: u16 },
+    #[error("program counter {pc:#05X} is outside the {rom_len} byte rom")]
+    PcNotInRom { pc: u16, rom_len: usize },
+    #[error("program counter {pc:#05X} is not on an instruction boundary")]
+    PcMisaligned { pc: u16 },
+    #[error("{opcode:#06X} at {pc:#05X} accessed {addr:#X}, past the end of memory")]
+    MemoryOutOfBounds { pc: u16, opcode: u16, addr: usize },
+    #[error("{opcode:#06X} at {pc:#05X} overflowed the {depth} entry stack")]
+    StackOverflow { pc: u16, opcode: u16, depth: usize },
+    #[error("{opcode:#06X} at {pc:#05X} returned with an empty stack")]
+    StackUnderflow { pc: u16, opcode: u16 },
+    #[error("gave up at {pc:#05X} after {cycles} instructions")]
+    StepLimit { pc: u16, cycles: usize },
+    #[error("there is no register V{register:X}")]
+    InvalidRegister { register: u8 },
+    #[error("stopped for the debugger")]
     Debug,
 }
 
+#[derive (Debug, Clone, Copy, PartialEq)]
+pub enum Warning {
+    FrameCycleCap(usize),
+    QuirkConflict(&'static str),
+    SkippedOpcode(u16, u16),
+}
+
+#[derive (Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Variant {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Variant {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Variant::Chip8 => "CHIP-8",
+            Variant::SuperChip => "SUPER-CHIP",
+            Variant::XoChip => "XO-CHIP",
+        }
+    }
+
+    pub fn supports(&self, instruction: &Instruction) -> bool {
+        // Whether the instruction is in this variant's instruction set. Each
+        // variant has everything the one before it had
+        match instruction {
+            Instruction::ScrollDown { .. } | Instruction::ScrollRight | Instruction::ScrollLeft
+            | Instruction::Exit | Instruction::Low | Instruction::High
+            | Instruction::LdHf { .. } | Instruction::LdRVx { .. } | Instruction::LdVxR { .. } => *self != Variant::Chip8,
+            Instruction::ScrollUp { .. } | Instruction::Save { .. } | Instruction::Load { .. }
+            | Instruction::LdILong { .. } | Instruction::Plane { .. } | Instruction::Audio
+            | Instruction::Pitch { .. } => *self == Variant::XoChip,
+            Instruction::Unknown(_) => false,
+            _ => true,
+        }
+    }
+}
+
+#[derive (Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum IllegalPolicy {
+    Error,          // Stop with Error::InvalidOperation
+    Halt,           // Halt the chip as if the program had ended
+    Skip,           // Step over it and record a warning
+}
+
+// Makes run_for stop after a DXYN, for catching graphics glitches in the act
+#[derive (Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DrawBreak {
+    #[default]
+    Off,
+    Draw,           // After every sprite drawn
+    Collision,      // Only after a sprite that set VF
+}
+
+#[derive (Debug, Clone, Copy, PartialEq)]
+pub struct TraceEntry {
+    pub pc: u16,
+    pub opcode: u16,
+    pub instruction: Instruction,
+    pub changed_v: u16,             // Bit n is set if the instruction changed Vn
+    pub changed_i: bool,
+    pub v: [u8; 16],                // Registers after the instruction ran
+    pub i: u16,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // One line per instruction with the registers it changed, e.g.
+        // "0x204  6A02  LD VA, 0x02  VA=02"
+        write!(f, "{:#05X}  {:04X}  {}", self.pc, self.opcode, self.instruction)?;
+        let mut separator = "  ";
+        for n in 0..16 {
+            if self.changed_v & (1 << n) != 0 {
+                write!(f, "{}V{:X}={:02X}", separator, n, self.v[n])?;
+                separator = " ";
+            }
+        }
+        if self.changed_i {
+            write!(f, "{}I={:03X}", separator, self.i)?;
+        }
+        Ok(())
+    }
+}
+
+// What a frame did, so frontends can skip redraws when nothing changed
+#[derive (Debug, Clone, Copy, PartialEq)]
+pub struct FrameOutput {
+    pub display_changed: bool,      // Pixels or the resolution changed
+    pub beep: bool,                 // A tone should be playing
+    pub halted: bool,               // The rom has stopped
+    pub instructions_executed: usize,
+    pub breakpoint: Option<u16>,    // Stopped before the breakpoint at this address
+    pub watchpoint: Option<WatchHit>,   // Stopped after an access to a watched address
+    pub draw_break: Option<u16>,    // Stopped after the DXYN at this address, see DrawBreak
+}
+
+// What a single step did, for debuggers and test harnesses
+#[derive (Debug, Clone, Copy, PartialEq)]
+pub struct StepInfo {
+    pub opcode: u16,
+    pub instruction: Instruction,
+    pub pc_before: u16,
+    pub pc_after: u16,
+    pub drew: bool,                 // The display changed
+    pub waiting_for_key: bool,      // FX0A is now waiting for a key
+}
+
+// The programmer visible registers, for debuggers and save states
+#[derive (Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Registers {
+    pub v: [u8; 16],                // V0 to VF
+    pub i: u16,
+    pub pc: u16,
+    pub sp: u8,                     // Number of return addresses on the stack
+    pub dt: u8,                     // Delay timer
+    pub st: u8,                     // Sound timer
+}
+
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+// Pauses run_for before the instruction at addr, if the condition holds
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub addr: u16,
+    pub condition: Option<Condition>,
+}
+
+// Pauses run_for after an instruction reads or writes memory in start..end
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: usize,
+    pub end: usize,
+    pub access: Access,
+}
+
+// The access that tripped a watchpoint
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    pub addr: usize,
+    pub pc: u16,                    // The instruction that made the access
+    pub write: bool,
+}
+
+// A subroutine call still waiting to return
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CallFrame {
+    pub call_site: u16,             // Address of the 2NNN
+    pub target: u16,                // Subroutine it called
+}
+
+// The state an instruction can change, kept so step_back can put it back.
+// Memory and the display are only copied when an instruction touches them
+#[derive (Clone)]
+struct Undo {
+    v : [u8; 16],
+    i : u16,
+    pc : u16,
+    sp : u8,
+    call : Option<CallFrame>,                       // Innermost call, which a return drops
+    dt : u8,
+    st : u8,
+    input_wait : bool,
+    input_register : u16,
+    wait_key : Option<usize>,
+    halted : bool,
+    hires : bool,
+    rpl : [u8; 16],
+    planes : u8,
+    audio_pattern : [u8; 16],
+    pitch : u8,
+    rng : Option<Box<StdRng>>,                      // Only saved for CXKK
+    rng_replay_pos : usize,
+    rng_log_len : usize,
+    memory : Vec<(usize, u8)>,                      // Address and old value of each byte written
+    disp : Option<Box<[[u8; DISP_SIZE]; 2]>>,       // Only saved for instructions that draw
+}
+
+// Called with the chip and the address of a 0NNN machine code call
+pub type TrapHook = Arc<dyn Fn(&mut Chip, u16) + Send + Sync>;
+
 pub enum ProgramCounter {
     Next,
     Skip,
@@ -27,135 +349,1458 @@ impl ProgramCounter {
 
 }
 
-pub struct Chip {
-    memory : [u8; CHIP8_MEM],                       // Memory
+// With the serde feature the whole machine state can be saved and restored.
+// Debugging aids, the trap hook and a borrowed rom are not saved.
+// The rng comes back freshly seeded, reset_rng restarts it from the saved seed
+#[derive (Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Chip<'rom> {
+    memory : Vec<u8>,                               // Memory, 4K or 64K for XO-CHIP
     v : [u8; 16],                                   // 16 8-bit registers
     i : u16,                                        // 16-bit index
     pc : u16,                                       // 16-bit program counter
-    stack : [u16; 0x10],                            // 16 level 16-bit stack
+    stack : Vec<u16>,                               // 16-bit stack, 16 levels unless configured
     sp : u8,                                        // 8-bit stack pointer
+    calls : Vec<CallFrame>,                         // Where each stack entry was pushed from
     dt : u8,                                        // 8-bit delay timer
     st : u8,                                        // 8-bit sound timer
     input_wait : bool,                              // Waits for a keypad input 
     input_keys : [bool; 16],                        // 16 input keys
     input_register : u16,                           // Registers keypad inputs
-    disp : [u8; CHIP8_WIDTH * CHIP8_HEIGHT / 8],    // Display
-    tone: bool,                                     // toggle beep
-    time : isize,                                   // keypad register time
+    wait_key : Option<usize>,                       // Key pressed during FX0A, waiting for its release
+    input_buffering : bool,                         // Hold key presses over until the next frame
+    buffered_keys : [bool; 16],                     // Keys pressed since the last frame
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_planes"))]
+    disp : [[u8; DISP_SIZE]; 2],                    // Display planes, rows are width / 8 bytes apart
+    time : isize,                                   // Frame budget left, in nanoseconds
+    rom_len : usize,                                // Size of the loaded rom
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_planes"))]
+    delta_disp : [[u8; DISP_SIZE]; 2],              // Display as of the last delta
+    on_illegal : IllegalPolicy,                     // What to do on an unknown opcode
+    opcode : u16,                                   // Opcode being executed, for error reports
+    halted : bool,                                  // Execution has stopped
+    #[cfg_attr(feature = "serde", serde(skip, default = "Chip::fresh_rng"))]
+    rng : StdRng,                                   // Random source for CXKK
+    seed : Option<u64>,                             // Seed the rng started from
+    rng_logging : bool,                             // Record every random byte CXKK draws
+    rng_log : Vec<u8>,                              // Random bytes drawn while logging
+    rng_replay : Vec<u8>,                           // Logged bytes to hand out instead of the rng
+    rng_replay_pos : usize,                         // Next replayed byte
+    #[cfg_attr(feature = "serde", serde(skip))]
+    rom_ref : Option<&'rom [u8]>,                   // Borrowed rom run in place until written to
+    #[cfg_attr(feature = "serde", serde(skip))]
+    timings : Vec<Duration>,                        // Ring buffer of frame execution times
+    timings_cap : usize,                            // Timing ring size, 0 when disabled
+    timings_next : usize,                           // Next ring slot to overwrite
+    max_cycles : usize,                             // Instructions allowed in one frame
+    #[cfg_attr(feature = "serde", serde(skip))]
+    warnings : Vec<Warning>,                        // Recoverable problems seen so far
+    vip_draw_timing : bool,                         // DXYN costs more for taller sprites
+    vip_cycle_timing : bool,                        // Every opcode costs what it took on the VIP
+    prev_v : [u8; 16],                              // Registers before the last step or frame
+    prev_i : u16,                                   // I before the last step or frame
+    prev_pc : u16,                                  // PC before the last step or frame
+    prev_dt : u8,                                   // Delay timer before the last step or frame
+    prev_st : u8,                                   // Sound timer before the last step or frame
+    fill : u8,                                      // Value uninitialised registers and memory start as
+    min_beep_ticks : u8,                            // Shortest sound timer that is audible
+    interleave_timers : bool,                       // Tick timers between instructions, not per frame
+    timer_time : isize,                             // Time elapsed since the last timer tick
+    #[cfg_attr(feature = "serde", serde(skip))]
+    trace : VecDeque<TraceEntry>,                   // Most recently executed instructions
+    trace_cap : usize,                              // Trace buffer size, 0 when disabled
+    trace_range : Option<Range<u16>>,               // Only instructions in here are traced
+    #[cfg_attr(feature = "serde", serde(skip))]
+    undo : VecDeque<Undo>,                          // Undo records of the last instructions, newest last
+    undo_cap : usize,                               // Instructions step_back can undo, 0 when disabled
+    coverage_enabled : bool,                        // Count what runs
+    #[cfg_attr(feature = "serde", serde(skip))]
+    coverage : Vec<u64>,                            // Times an instruction started at each address
+    #[cfg_attr(feature = "serde", serde(skip))]
+    opcode_counts : Vec<u64>,                       // Times each supported opcode ran, in table order
+    #[cfg_attr(feature = "serde", serde(skip))]
+    breakpoints : Vec<Breakpoint>,                  // Addresses run_for stops before, sorted
+    #[cfg_attr(feature = "serde", serde(skip))]
+    at_break : bool,                                // Stopped on a breakpoint, which shouldn't stop it again
+    #[cfg_attr(feature = "serde", serde(skip))]
+    watchpoints : Vec<Watchpoint>,                  // Memory ranges run_for stops after accesses to
+    #[cfg_attr(feature = "serde", serde(skip))]
+    watch_hit : Cell<Option<WatchHit>>,             // Watched access made by the current instruction
+    #[cfg_attr(feature = "serde", serde(skip))]
+    draw_break : DrawBreak,                         // Which sprites run_for stops after
+    variant : Variant,                              // Instruction set in use
+    platform : Option<Platform>,                    // Preset last applied, if any
+    hires : bool,                                   // SCHIP 128x64 mode
+    rpl : [u8; 16],                                 // SCHIP RPL user flags, 16 on XO-CHIP
+    planes : u8,                                    // XO-CHIP bitplanes selected for drawing
+    audio_pattern : [u8; 16],                       // XO-CHIP 1-bit audio sample buffer
+    pitch : u8,                                     // XO-CHIP audio playback pitch
+    quirks : Quirks,                                // Interpreter specific behaviours
+    cycle_time : usize,                             // Nanoseconds each instruction takes
+    two_page : bool,                                // CHIP-8 HiRes 64x64 mode
+    big_font : bool,                                // The SCHIP 8x10 font is in memory
+    #[cfg_attr(feature = "serde", serde(skip))]
+    trap_hook : Option<TrapHook>,                   // Handles 0NNN machine code calls
+    load_addr : usize,                              // Where the rom is loaded and execution starts
+    #[cfg_attr(feature = "serde", serde(skip))]
+    observer : Option<Arc<dyn ChipObserver>>,       // Told about instructions, writes and the like
 }
 
-impl  Chip {
-    pub fn new() -> Self {
-        // Load the fonts into memory
-        let mut mem = [0, CHIP8_MEM];
-        for i in 0..FONT_SET.len() {
-            mem[i] = FONT_SET[i];
+pub fn supported_opcodes() -> &'static [(u16, &'static str, &'static str)] {
+    // Lists each opcode pattern the decoder handles with its mnemonic and a description
+    &OPCODES
+}
+
+fn opcode_index(instruction: &Instruction) -> Option<usize> {
+    // Where an instruction's entry is in the opcode table
+    let kind = core::mem::discriminant(instruction);
+    OPCODES.iter().position(|&(pattern, _, _)| core::mem::discriminant(&decode(pattern)) == kind)
+}
+
+pub fn chip_state_diff(a: &Chip, b: &Chip) -> Vec<String> {
+    // Lists the differences between two chips in a readable form
+    let mut diffs = Vec::new();
+    for n in 0..16 {
+        if a.v[n] != b.v[n] {
+            diffs.push(format!("V{:X}: {:#04x} != {:#04x}", n, a.v[n], b.v[n]));
         }
+    }
+    if a.i != b.i {
+        diffs.push(format!("I: {:#06x} != {:#06x}", a.i, b.i));
+    }
+    if a.pc != b.pc {
+        diffs.push(format!("PC: {:#06x} != {:#06x}", a.pc, b.pc));
+    }
+    if a.sp != b.sp {
+        diffs.push(format!("SP: {} != {}", a.sp, b.sp));
+    }
+    if a.dt != b.dt {
+        diffs.push(format!("DT: {} != {}", a.dt, b.dt));
+    }
+    if a.st != b.st {
+        diffs.push(format!("ST: {} != {}", a.st, b.st));
+    }
+    let lit = |chip: &Chip| chip.disp.iter().flatten().map(|byte| byte.count_ones()).sum::<u32>();
+    if a.disp != b.disp {
+        diffs.push(format!("display: {} pixels lit != {} pixels lit", lit(a), lit(b)));
+    }
+    diffs
+}
+
+impl Default for Chip<'_> {
+    fn default() -> Self {
+        Chip::new()
+    }
+}
 
-        Self {
-            memory: mem,
+impl<'rom> Chip<'rom> {
+    pub fn new() -> Self {
+        let mut chip = Self {
+            memory: vec![0; CHIP8_MEM],
             v: [0; 16],
             i: 0,
             pc : ROM_SIZE as u16,
-            stack : [0; 0x10],
+            stack : vec![0; DEFAULT_STACK_DEPTH],
             sp : 0,
+            calls : Vec::new(),
             dt : 0,
             st : 0,
             input_wait : false,
-            input_keys : 0,
+            input_keys : [false; 16],
             input_register : 0,
-            disp : [0; CHIP8_WIDTH * CHIP8_HEIGHT / 8],
-            tone: false,
+            wait_key : None,
+            input_buffering : false,
+            buffered_keys : [false; 16],
+            disp : [[0; DISP_SIZE]; 2],
             time : 0,
+            rom_len : 0,
+            delta_disp : [[0; DISP_SIZE]; 2],
+            on_illegal : IllegalPolicy::Skip,
+            opcode : 0,
+            halted : false,
+            rng : Self::fresh_rng(),
+            seed : None,
+            rng_logging : false,
+            rng_log : Vec::new(),
+            rng_replay : Vec::new(),
+            rng_replay_pos : 0,
+            rom_ref : None,
+            timings : Vec::new(),
+            timings_cap : 0,
+            timings_next : 0,
+            max_cycles : MAX_CYCLES_PER_FRAME,
+            warnings : Vec::new(),
+            vip_draw_timing : false,
+            vip_cycle_timing : false,
+            prev_v : [0; 16],
+            prev_i : 0,
+            prev_pc : ROM_SIZE as u16,
+            prev_dt : 0,
+            prev_st : 0,
+            fill : 0,
+            min_beep_ticks : 1,
+            interleave_timers : false,
+            timer_time : 0,
+            trace : VecDeque::new(),
+            trace_cap : 0,
+            trace_range : None,
+            undo : VecDeque::new(),
+            undo_cap : 0,
+            coverage_enabled : false,
+            coverage : Vec::new(),
+            opcode_counts : Vec::new(),
+            breakpoints : Vec::new(),
+            at_break : false,
+            watchpoints : Vec::new(),
+            watch_hit : Cell::new(None),
+            draw_break : DrawBreak::Off,
+            variant : Variant::Chip8,
+            platform : None,
+            hires : false,
+            rpl : [0; 16],
+            planes : 1,
+            audio_pattern : [0; 16],
+            pitch : 64,
+            quirks : Quirks::default(),
+            cycle_time : 1_000_000_000 / DEFAULT_CLOCK_HZ,
+            two_page : false,
+            big_font : true,
+            trap_hook : None,
+            load_addr : ROM_SIZE,
+            observer : None,
+        };
+        chip.load_fonts();
+        chip
+    }
+
+    pub fn builder() -> ChipBuilder {
+        // Configures a chip option by option, see ChipBuilder
+        ChipBuilder::new()
+    }
+
+    #[cfg(feature = "async")]
+    pub fn frames(&mut self) -> FrameStream<'_, 'rom> {
+        // Runs the chip in real time as an async stream of frame outputs
+        FrameStream::new(self)
+    }
+
+    pub fn set_two_page(&mut self, enabled: bool) {
+        // Switches to the 64x64 two page display of CHIP-8 HiRes roms
+        self.two_page = enabled;
+        self.check_quirks();
+    }
+
+    pub fn two_page(&self) -> bool {
+        self.two_page
+    }
+
+    pub fn set_clock_speed(&mut self, hz: usize) {
+        // Sets how many instructions run per second
+        self.cycle_time = (1_000_000_000 / hz.max(1)).max(1);
+    }
+
+    pub fn set_instructions_per_frame(&mut self, ipf: usize) {
+        self.cycle_time = (FRAME_NANOS as usize / ipf.max(1)).max(1);
+    }
+
+    pub fn instructions_per_frame(&self) -> usize {
+        FRAME_NANOS as usize / self.cycle_time
+    }
+
+    pub fn with_quirks(quirks: Quirks) -> Self {
+        let mut chip = Self::new();
+        chip.set_quirks(quirks);
+        chip
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        // Quirks can be changed at any point, taking effect from the next instruction
+        self.quirks = quirks;
+        self.check_quirks();
+    }
+
+    pub fn with_variant(variant: Variant) -> Self {
+        let mut chip = Self::new();
+        chip.set_variant(variant);
+        chip
+    }
+
+    pub fn with_platform(platform: Platform) -> Self {
+        let mut chip = Self::new();
+        chip.set_platform(platform);
+        chip
+    }
+
+    pub fn set_platform(&mut self, platform: Platform) {
+        // Applies the variant, quirks, clock speed and memory size of a preset
+        // The quirks are checked once both they and the variant are in place
+        self.platform = Some(platform);
+        self.variant = platform.variant();
+        self.set_memory_size(platform.memory_size());
+        self.quirks = platform.quirks();
+        self.check_quirks();
+        self.set_clock_speed(platform.clock_hz());
+        self.big_font = platform.big_font();
+        self.load_fonts();
+    }
+
+    fn load_fonts(&mut self) {
+        // Writes the fonts into the bottom of memory, leaving the big font
+        // area as free memory on platforms that never had one
+        self.memory[..FONT_SET.len()].copy_from_slice(&FONT_SET);
+        let big_font = &mut self.memory[BIG_FONT_ADDR..BIG_FONT_ADDR + BIG_FONT_SET.len()];
+        if self.big_font {
+            big_font.copy_from_slice(&BIG_FONT_SET);
+        } else {
+            big_font.fill(self.fill);
+        }
+    }
+
+    pub fn with_fill(byte: u8) -> Self {
+        // Creates a chip whose registers and free memory start as byte rather than zero
+        // Useful for catching roms that read state they never initialised
+        let mut chip = Self::new();
+        chip.fill = byte;
+        chip.v = [byte; 16];
+        for b in chip.memory[BIG_FONT_ADDR + BIG_FONT_SET.len()..].iter_mut() {
+            *b = byte;
+        }
+        chip
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        // Creates a chip whose random numbers are reproducible
+        let mut chip = Self::new();
+        chip.set_seed(seed);
+        chip
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        // Restarts the rng from seed so the random numbers are reproducible
+        self.seed = Some(seed);
+        self.reset_rng();
+    }
+
+    pub fn reset_rng(&mut self) {
+        // Re-seeds the rng with the original seed, or fresh entropy without one
+        self.rng = match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => Self::fresh_rng(),
+        };
+    }
+
+    #[cfg(feature = "std")]
+    fn fresh_rng() -> StdRng {
+        StdRng::from_entropy()
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn fresh_rng() -> StdRng {
+        // There's no entropy source without std, use with_seed for varied runs
+        StdRng::seed_from_u64(0)
+    }
+
+    pub fn set_rng_logging(&mut self, enabled: bool) {
+        // Starts or stops recording the random bytes CXKK draws
+        self.rng_logging = enabled;
+    }
+
+    pub fn rng_log(&self) -> &[u8] {
+        &self.rng_log
+    }
+
+    pub fn replay_rng(&mut self, log: Vec<u8>) {
+        // Makes CXKK draw from a recorded log, then the rng once it runs out
+        self.rng_replay = log;
+        self.rng_replay_pos = 0;
+    }
+
+    fn random_byte(&mut self) -> u8 {
+        let value = if self.rng_replay_pos < self.rng_replay.len() {
+            self.rng_replay_pos += 1;
+            self.rng_replay[self.rng_replay_pos - 1]
+        } else {
+            self.rng.gen::<u8>()
+        };
+        if self.rng_logging {
+            self.rng_log.push(value);
+        }
+        value
+    }
+
+    pub fn reset(&mut self) {
+        // Restarts the loaded rom from the beginning, as if it had just been loaded
+        // The rom itself is kept, but fonts a program wrote over are put back
+        self.load_fonts();
+        self.v = [self.fill; 16];
+        self.i = 0;
+        self.pc = self.load_addr as u16;
+        self.stack.iter_mut().for_each(|addr| *addr = 0);
+        self.sp = 0;
+        self.calls.clear();
+        self.undo.clear();
+        self.dt = 0;
+        self.st = 0;
+        self.input_wait = false;
+        self.input_register = 0;
+        self.wait_key = None;
+        self.buffered_keys = [false; 16];
+        self.disp = [[0; DISP_SIZE]; 2];
+        self.hires = false;
+        self.planes = 1;
+        self.audio_pattern = [0; 16];
+        self.pitch = 64;
+        self.time = 0;
+        self.timer_time = 0;
+        self.halted = false;
+        self.reset_rng();
+    }
+
+    pub fn set_load_address(&mut self, addr: usize) {
+        // Where roms are loaded and started, 0x600 for ETI-660 programs
+        self.load_addr = addr;
+        self.pc = addr as u16;
+    }
+
+    pub fn load_at(&mut self, addr: usize, bytes: &[u8]) -> Result<(), Error> {
+        // Copies bytes into memory at addr without touching the PC, for
+        // programs in several parts, font patches and boot stubs
+        let max = self.memory.len().saturating_sub(addr);
+        if bytes.len() > max {
+            return Err(Error::RomTooLarge { size: bytes.len(), max })
+        }
+        self.unmap_rom(addr, addr + bytes.len());
+        self.memory[addr..addr + bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    pub fn load_rom(&mut self, rom : &[u8]) -> Result<(), Error> {
+        // Load a rom into memory at the load address and start running it from there
+        let addr = self.load_addr;
+        self.rom_ref = None;
+        self.load_at(addr, rom)?;
+        self.start_rom(rom)
+    }
+
+    pub fn load_rom_borrowed(&mut self, rom: &'rom [u8]) -> Result<(), Error> {
+        // Like load_rom, but runs the rom where it is rather than copying it
+        // into memory, for roms in flash on small devices. The first write
+        // into the rom's range copies it over and carries on from the copy
+        let max = self.memory.len().saturating_sub(self.load_addr);
+        if rom.len() > max {
+            return Err(Error::RomTooLarge { size: rom.len(), max })
+        }
+        self.rom_ref = Some(rom);
+        self.start_rom(rom)
+    }
+
+    fn start_rom(&mut self, rom: &[u8]) -> Result<(), Error> {
+        // Points the PC at a rom just loaded at the load address
+        let addr = self.load_addr;
+        self.rom_len = rom.len();
+        self.pc = addr as u16;
+
+        // HiRes roms start by jumping to 0x260, where the two page interpreter
+        // used to sit. Enter 64x64 mode and jump straight to the program at 0x2C0
+        if self.variant == Variant::Chip8 && addr == ROM_SIZE && rom.starts_with(&[0x12, 0x60]) {
+            self.two_page = true;
+            self.poke(addr + 1, 0xC0)?;
+        }
+        Ok(())
+    }
+
+    pub fn rom_borrowed(&self) -> bool {
+        // Whether the rom is still being run in place
+        self.rom_ref.is_some()
+    }
+
+    fn read_mem(&self, addr: usize) -> Result<u8, Error> {
+        // Reads a byte for the program, checking the watchpoints
+        let value = self.peek(addr)?;
+        self.watch(addr, false);
+        Ok(value)
+    }
+
+    fn write_mem(&mut self, addr: usize, value: u8) -> Result<(), Error> {
+        // Writes a byte for the program, checking the watchpoints and
+        // keeping the old value when undo is enabled
+        if self.undo_cap > 0 {
+            let old = self.peek(addr)?;
+            if let Some(undo) = self.undo.back_mut() {
+                undo.memory.push((addr, old));
+            }
+        }
+        self.poke(addr, value)?;
+        self.watch(addr, true);
+        Ok(())
+    }
+
+    fn watch(&self, addr: usize, write: bool) {
+        // Notes the first watched access an instruction makes
+        if self.watch_hit.get().is_some() {
+            return;
+        }
+        let hit = self.watchpoints.iter().any(|watch| {
+            addr >= watch.start && addr < watch.end && match watch.access {
+                Access::Read => !write,
+                Access::Write => write,
+                Access::ReadWrite => true,
+            }
+        });
+        if hit {
+            self.watch_hit.set(Some(WatchHit { addr, pc: self.pc, write }));
+        }
+    }
+
+    fn peek(&self, addr: usize) -> Result<u8, Error> {
+        // Reads a byte, going to a borrowed rom for its range
+        if addr >= self.memory.len() {
+            return Err(self.out_of_bounds(addr));
+        }
+        if let Some(rom) = self.rom_ref {
+            if let Some(&byte) = addr.checked_sub(self.load_addr).and_then(|offset| rom.get(offset)) {
+                return Ok(byte);
+            }
+        }
+        Ok(self.memory[addr])
+    }
+
+    fn poke(&mut self, addr: usize, value: u8) -> Result<(), Error> {
+        // Writes a byte, copying a borrowed rom over first if it lands inside it
+        if addr >= self.memory.len() {
+            return Err(self.out_of_bounds(addr));
+        }
+        self.unmap_rom(addr, addr + 1);
+        self.memory[addr] = value;
+        if let Some(observer) = &self.observer {
+            observer.memory_written(addr as u16, value);
+        }
+        Ok(())
+    }
+
+    fn unmap_rom(&mut self, start: usize, end: usize) {
+        // Copies a borrowed rom into memory if start..end overlaps it
+        if let Some(rom) = self.rom_ref {
+            let base = self.load_addr;
+            if start < base + rom.len() && end > base {
+                self.memory[base..base + rom.len()].copy_from_slice(rom);
+                self.rom_ref = None;
+            }
+        }
+    }
+
+    fn out_of_bounds(&self, addr: usize) -> Error {
+        // Blames the instruction running, or the last one run when called from outside
+        Error::MemoryOutOfBounds { pc: self.pc, opcode: self.opcode, addr }
+    }
+
+    pub fn read_byte(&self, addr: u16) -> Result<u8, Error> {
+        // Reads a byte of memory, for debuggers and cheats
+        self.peek(addr as usize)
+    }
+
+    pub fn write_byte(&mut self, addr: u16, value: u8) -> Result<(), Error> {
+        // Writes a byte of memory, for debuggers and cheats
+        self.poke(addr as usize, value)
+    }
+
+    pub fn read_range(&self, start: u16, len: usize) -> Result<Vec<u8>, Error> {
+        // Copies len bytes out of memory from start, or nothing if they don't all fit
+        let begin = start as usize;
+        match begin.checked_add(len) {
+            Some(end) if end <= self.memory.len() => (begin..end).map(|addr| self.peek(addr)).collect(),
+            _ => Err(self.out_of_bounds(begin)),
+        }
+    }
+
+    pub fn write_range(&mut self, start: u16, data: &[u8]) -> Result<(), Error> {
+        // Copies data into memory at start, writing nothing if it doesn't fit
+        let begin = start as usize;
+        let end = begin + data.len();
+        if end > self.memory.len() {
+            return Err(self.out_of_bounds(start as usize));
+        }
+        self.unmap_rom(begin, end);
+        self.memory[begin..end].copy_from_slice(data);
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        // Checks the PC points at a whole instruction inside the loaded rom
+        let pc = self.pc as usize;
+        let base = self.load_addr;
+        if pc < base || pc + OPCODE_SIZE > base + self.rom_len {
+            return Err(Error::PcNotInRom { pc: self.pc, rom_len: self.rom_len });
+        }
+        if !(pc - base).is_multiple_of(OPCODE_SIZE) {
+            return Err(Error::PcMisaligned { pc: self.pc });
+        }
+        Ok(())
+    }
+
+    pub fn tone(&self) -> bool {
+        // Whether a tone should be played or not
+        self.st > 0 && self.st >= self.min_beep_ticks
+    }
+
+    pub fn set_input_buffering(&mut self, enabled: bool) {
+        // Makes a key pressed and released between frames still show up in the next one
+        self.input_buffering = enabled;
+        self.buffered_keys = [false; 16];
+    }
+
+    pub fn buffer_key_press(&mut self, key: usize) {
+        // Records a key down event to be held for the next frame
+        if self.input_buffering && key < 16 {
+            self.buffered_keys[key] = true;
+        }
+    }
+
+    pub fn set_interleaved_timers(&mut self, enabled: bool) {
+        // Ticks the timers part way through a frame, once per frame's worth of
+        // executed instructions, so FX07 can see them change mid-frame
+        self.interleave_timers = enabled;
+        self.timer_time = 0;
+    }
+
+    fn catch_up_timers(&mut self) {
+        // Ticks once for every 60th of a second accumulated
+        while self.timer_time >= FRAME_NANOS {
+            self.tick_timers();
+            self.timer_time -= FRAME_NANOS;
+        }
+    }
+
+    fn tick_timers(&mut self) {
+        if self.dt > 0 {
+            self.dt -= 1
+        }
+        if self.st > 0 {
+            self.st -= 1
+        }
+    }
+
+    pub fn set_min_beep_ticks(&mut self, ticks: u8) {
+        // Some interpreters needed a sound timer of at least 2 to beep
+        self.min_beep_ticks = ticks;
+    }
+
+    pub fn halted(&self) -> bool {
+        // True once the rom has exited or is stuck jumping to itself
+        self.halted
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        // Moves execution to pc, for code that calls the op_ handlers
+        // directly like a transpiled rom
+        self.pc = pc;
+    }
+
+    pub fn call_stack(&self) -> &[CallFrame] {
+        // Calls that haven't returned yet, outermost first
+        &self.calls[..self.calls.len().min(self.sp as usize)]
+    }
+
+    pub fn registers(&self) -> Registers {
+        // Copies out V0-VF, I, PC, SP and the timers
+        Registers {
+            v: self.v,
+            i: self.i,
+            pc: self.pc,
+            sp: self.sp,
+            dt: self.dt,
+            st: self.st,
+        }
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        // Copies the registers, memory and display for comparing with snapshot::diff
+        let mut memory = self.memory.clone();
+        if let Some(rom) = self.rom_ref {
+            memory[self.load_addr..self.load_addr + rom.len()].copy_from_slice(rom);
+        }
+        Snapshot {
+            registers: self.registers(),
+            memory,
+            planes: [self.plane(0).to_vec(), self.plane(1).to_vec()],
+            width: self.width(),
+            height: self.height(),
+        }
+    }
+
+    pub fn set_registers(&mut self, regs: Registers) {
+        // Overwrites the registers from a debugger, SP is kept within the stack
+        self.v = regs.v;
+        self.i = regs.i;
+        self.pc = regs.pc;
+        self.sp = regs.sp.min(self.stack.len().min(u8::MAX as usize) as u8);
+        self.dt = regs.dt;
+        self.st = regs.st;
+    }
+
+    pub fn set_trap_hook(&mut self, hook: Option<TrapHook>) {
+        // Routes 0NNN calls to hook rather than treating them as unknown opcodes
+        self.trap_hook = hook;
+    }
+
+    pub fn set_observer(&mut self, observer: Option<Arc<dyn ChipObserver>>) {
+        // Reports events to observer as they happen, None stops reporting
+        self.observer = observer;
+    }
+
+    pub fn set_illegal_policy(&mut self, policy: IllegalPolicy) {
+        // Chooses between erroring, halting or skipping on unknown opcodes
+        self.on_illegal = policy;
+    }
+
+    pub fn disp(&self) -> &[u8] {
+        // The packed pixels of the current resolution, eight to a byte
+        self.plane(0)
+    }
+
+    pub fn plane(&self, plane: usize) -> &[u8] {
+        // One XO-CHIP bitplane, plane 0 being the only one plain CHIP-8 draws to
+        &self.disp[plane][..self.width() * self.height() / 8]
+    }
+
+    pub fn audio_pattern(&self) -> &[u8; 16] {
+        &self.audio_pattern
+    }
+
+    pub fn pitch_hz(&self) -> f32 {
+        // Playback rate of the audio pattern in bits per second
+        // 4000 * 2^((pitch - 64) / 48), a whole octave every 48 steps
+        let steps = self.pitch as i32 - 64;
+        let step = steps.rem_euclid(48) as usize;
+        let hz = 4000.0 * PITCH_SIXTEENTHS[step / 3] * PITCH_THIRDS[step % 3];
+        let octaves = steps.div_euclid(48);
+        if octaves < 0 { hz / (1 << -octaves) as f32 } else { hz * (1 << octaves) as f32 }
+    }
+
+    pub fn width(&self) -> usize {
+        if self.hires { SCHIP_WIDTH } else { CHIP8_WIDTH }
+    }
+
+    pub fn height(&self) -> usize {
+        if self.hires || self.two_page { SCHIP_HEIGHT } else { CHIP8_HEIGHT }
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    pub fn set_variant(&mut self, variant: Variant) {
+        // XO-CHIP gets a full 64K address space
+        self.variant = variant;
+        let size = if variant == Variant::XoChip { XO_CHIP_MEM } else { CHIP8_MEM };
+        self.set_memory_size(size);
+        self.check_quirks();
+    }
+
+    pub fn set_stack_depth(&mut self, depth: usize) {
+        // How many nested calls are allowed before CALL fails with StackOverflow
+        // The VIP had 12 levels, SCHIP 16, and the stack pointer caps it at 255
+        let depth = depth.max(1).min(u8::MAX as usize);
+        self.stack.resize(depth, 0);
+        self.sp = self.sp.min(depth as u8);
+    }
+
+    pub fn stack_depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    pub fn set_memory_size(&mut self, size: usize) {
+        // Grows or shrinks memory, up to the 64K that I can address
+        // The fonts and the area below the load address always fit
+        let size = size.min(XO_CHIP_MEM).max(self.load_addr);
+        if size < self.memory.len() {
+            self.unmap_rom(size, usize::MAX);
+        }
+        self.memory.resize(size, self.fill);
+    }
+
+    pub fn memory_size(&self) -> usize {
+        self.memory.len()
+    }
+
+    pub fn display_delta(&mut self) -> Vec<u8> {
+        // Encodes the display bytes changed since the last call
+        // Each change is a big endian u16 index followed by the new value
+        // Indices past the first plane belong to the second
+        let mut delta = Vec::new();
+        for plane in 0..2 {
+            for (index, byte) in self.disp[plane].iter().enumerate() {
+                if *byte != self.delta_disp[plane][index] {
+                    delta.extend_from_slice(&((plane * DISP_SIZE + index) as u16).to_be_bytes());
+                    delta.push(*byte);
+                }
+            }
+        }
+        self.delta_disp = self.disp;
+        delta
+    }
+
+    pub fn apply_display_delta(&mut self, delta: &[u8]) {
+        // Applies a delta produced by display_delta to this display
+        for change in delta.chunks_exact(3) {
+            let index = u16::from_be_bytes([change[0], change[1]]) as usize;
+            let (plane, index) = (index / DISP_SIZE, index % DISP_SIZE);
+            if plane < 2 {
+                self.disp[plane][index] = change[2];
+                self.delta_disp[plane][index] = change[2];
+            }
+        }
+    }
+
+    pub fn render_sprite(&self, addr: u16, rows: u8) -> Result<Vec<bool>, Error> {
+        // Decodes rows bytes at addr into an 8 pixel wide grid, row by row
+        let start = addr as usize;
+        let end = start + rows as usize;
+        if end > self.memory.len() {
+            return Err(self.out_of_bounds(addr as usize));
+        }
+        let mut pixels = Vec::with_capacity(8 * rows as usize);
+        for addr in start..end {
+            let byte = self.peek(addr)?;
+            for bit in 0..8 {
+                pixels.push((byte >> (7 - bit)) & 1 == 1);
+            }
+        }
+        Ok(pixels)
+    }
+
+    pub fn set_max_cycles_per_frame(&mut self, cycles: usize) {
+        // Caps how many instructions a single frame may run
+        self.max_cycles = cycles;
+    }
+
+    pub fn set_sprite_clipping(&mut self, enabled: bool) {
+        // Clips sprites at the screen edge rather than wrapping them around
+        self.quirks.clipping = enabled;
+        self.check_quirks();
+    }
+
+    pub fn set_vip_draw_timing(&mut self, enabled: bool) {
+        // Models the VIP blanking the display while DXYN draws each row
+        self.vip_draw_timing = enabled;
+    }
+
+    pub fn set_vip_cycle_timing(&mut self, enabled: bool) {
+        // Charges each opcode its COSMAC VIP machine cycles instead of the clock speed
+        // Overrides vip draw timing, which it already covers
+        self.vip_cycle_timing = enabled;
+    }
+
+    fn check_quirks(&mut self) {
+        // Warns about settings that contradict the variant being emulated
+        if self.variant == Variant::XoChip && self.quirks.clipping {
+            self.warnings.push(Warning::QuirkConflict("XO-CHIP roms expect sprites to wrap, not clip"));
+        }
+        if self.variant != Variant::Chip8 && self.two_page {
+            self.warnings.push(Warning::QuirkConflict("two page hi-res only exists on plain CHIP-8"));
+        } else if self.two_page && self.platform.is_some_and(|platform| platform != Platform::Vip) {
+            self.warnings.push(Warning::QuirkConflict("two page hi-res only ran on the COSMAC VIP"));
+        }
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        // Drains the warnings recorded since the last call
+        core::mem::take(&mut self.warnings)
+    }
+
+    pub fn enable_frame_timing(&mut self, frames: usize) {
+        // Records how long the last frames spent executing opcodes, 0 disables it
+        // Without std there is no clock, so nothing is recorded
+        self.timings = Vec::with_capacity(frames);
+        self.timings_cap = frames;
+        self.timings_next = 0;
+    }
+
+    pub fn frame_timings(&self) -> &[Duration] {
+        // Recorded frame times, in ring buffer order once the buffer has wrapped
+        &self.timings
+    }
+
+    pub fn average_frame_time(&self) -> Duration {
+        if self.timings.is_empty() {
+            return Duration::ZERO;
+        }
+        self.timings.iter().sum::<Duration>() / self.timings.len() as u32
+    }
+
+    #[cfg(feature = "std")]
+    fn record_timing(&mut self, elapsed: Duration) {
+        if self.timings.len() < self.timings_cap {
+            self.timings.push(elapsed);
+        } else {
+            self.timings[self.timings_next] = elapsed;
+        }
+        self.timings_next = (self.timings_next + 1) % self.timings_cap;
+    }
+
+    pub fn draw_key_overlay(&mut self) {
+        // Marks each held key in a 4x4 grid of 8x8 cells in the top left corner
+        // Cells follow the keypad layout, so key 1 is top left and F bottom right
+        for (row, keys) in KEYPAD_LAYOUT.iter().enumerate() {
+            for (col, &key) in keys.iter().enumerate() {
+                if !self.input_keys[key] {
+                    continue;
+                }
+                for line in 1..7 {
+                    let y = row * 8 + line;
+                    self.disp[0][y * self.width() / 8 + col] |= 0x7e;
+                }
+            }
+        }
+    }
+
+    fn snapshot_registers(&mut self) {
+        // Remembers the registers so the next changes can be reported
+        self.prev_v = self.v;
+        self.prev_i = self.i;
+        self.prev_pc = self.pc;
+        self.prev_dt = self.dt;
+        self.prev_st = self.st;
+    }
+
+    pub fn changed_registers(&self) -> u16 {
+        // Bit n is set when Vn changed during the last step or frame
+        let mut mask = 0;
+        for n in 0..16 {
+            if self.v[n] != self.prev_v[n] {
+                mask |= 1 << n;
+            }
+        }
+        mask
+    }
+
+    pub fn changed_i(&self) -> bool {
+        self.i != self.prev_i
+    }
+
+    pub fn changed_pc(&self) -> bool {
+        self.pc != self.prev_pc
+    }
+
+    pub fn changed_dt(&self) -> bool {
+        self.dt != self.prev_dt
+    }
+
+    pub fn changed_st(&self) -> bool {
+        self.st != self.prev_st
+    }
+
+    pub fn frame(&mut self, keypad: &mut Keypad) -> Result<FrameOutput, Error> {
+        // Executes instructions and simulates hardware for the duration of a frame
+        self.run_for(keypad, Duration::from_nanos(FRAME_NANOS as u64))
+    }
+
+    pub fn run_for(&mut self, keypad: &mut Keypad, elapsed: Duration) -> Result<FrameOutput, Error> {
+        // Executes instructions and simulates hardware for elapsed host time
+        // The timers tick at 60Hz however often and irregularly this is called
+        let nanos = elapsed.as_nanos().min(isize::MAX as u128) as isize;
+        self.snapshot_registers();
+        let disp = self.disp;
+        let size = (self.width(), self.height());
+
+        // Go through the key changes since the last frame in order, so a
+        // press and release in between still completes FX0A
+        let mut pressed = [false; 16];
+        while let Some(event) = keypad.pop_event() {
+            if let KeyEvent::Pressed(key) = event {
+                pressed[key as usize] = true;
+            }
+            if self.input_wait {
+                self.key_wait_event(event);
+            }
+        }
+        let mut input_keys = keypad.keys();
+        if self.input_buffering {
+            for i in 0..input_keys.len() {
+                input_keys[i] |= self.buffered_keys[i] || pressed[i];
+            }
+            self.buffered_keys = [false; 16];
+        }
+        self.input_keys = input_keys;
+        // The timers keep counting down while FX0A waits
+        if self.input_wait || !self.interleave_timers {
+            self.timer_time += nanos;
+            self.catch_up_timers();
+        }
+
+        self.time += nanos;
+
+        // Only touch the clock when timing is enabled
+        #[cfg(feature = "std")]
+        let start = if self.timings_cap > 0 { Some(Instant::now()) } else { None };
+
+        let mut cycles = 0;
+        let mut breakpoint = None;
+        let mut watchpoint = None;
+        let mut draw_break = None;
+        while self.time > 0 {
+            if self.halted || self.input_wait {
+                // Time spent halted or waiting for a key isn't owed to the
+                // program, or it would all run at once when the key came
+                self.time = 0;
+                break;
+            }
+            if !self.at_break && self.breaks_here() {
+                // Leave the rest of the frame for when the debugger carries on
+                self.at_break = true;
+                breakpoint = Some(self.pc);
+                break;
+            }
+            if cycles == self.max_cycles {
+                // Drop the rest of the budget rather than hang the host
+                self.warnings.push(Warning::FrameCycleCap(cycles));
+                self.time = 0;
+                break;
+            }
+            cycles += 1;
+            let pc = self.pc;
+            let opcode = self.get_opcode()?;
+            let adv = self.exec(opcode)?;
+            self.time -= adv as isize;
+            if self.quirks.display_wait && opcode & 0xf000 == 0xd000 {
+                // The VIP only draws once the next vblank comes around, so
+                // nothing else runs this frame
+                self.time = 0;
+            }
+
+            if self.interleave_timers {
+                self.timer_time += adv as isize;
+                self.catch_up_timers();
+            }
+            if let Some(hit) = self.watch_hit.take() {
+                watchpoint = Some(hit);
+                break;
+            }
+            let catch = match self.draw_break {
+                DrawBreak::Off => false,
+                DrawBreak::Draw => true,
+                DrawBreak::Collision => self.v[0xf] != 0,
+            };
+            if catch && opcode & 0xf000 == 0xd000 {
+                draw_break = Some(pc);
+                break;
+            }
+        }
+
+        #[cfg(feature = "std")]
+        if let Some(start) = start {
+            self.record_timing(start.elapsed());
+        }
+        Ok(FrameOutput {
+            display_changed: self.disp != disp || (self.width(), self.height()) != size,
+            beep: self.tone(),
+            halted: self.halted,
+            instructions_executed: cycles,
+            breakpoint,
+            watchpoint,
+            draw_break,
+        })
+    }
+
+    fn key_wait_event(&mut self, event: KeyEvent) {
+        // Only a key going from up to down counts, so a key that was already
+        // held when FX0A ran doesn't satisfy it. Like the VIP, the wait then
+        // lasts until that key is released unless the press-only quirk is set
+        match event {
+            KeyEvent::Pressed(key) if self.wait_key.is_none() => {
+                if self.quirks.key_press_only {
+                    self.finish_key_wait(key as usize);
+                } else {
+                    self.wait_key = Some(key as usize);
+                }
+            }
+            KeyEvent::Released(key) if self.wait_key == Some(key as usize) => {
+                self.finish_key_wait(key as usize);
+            }
+            _ => {}
+        }
+    }
+
+    fn finish_key_wait(&mut self, key: usize) {
+        self.input_wait = false;
+        self.wait_key = None;
+        self.v[self.input_register as usize] = key as u8;
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        // Makes run_for stop before the instruction at addr
+        self.set_breakpoint(Breakpoint { addr, condition: None });
+    }
+
+    pub fn add_conditional_breakpoint(&mut self, addr: u16, condition: Condition) {
+        // Only stops when the condition holds just before the instruction runs
+        self.set_breakpoint(Breakpoint { addr, condition: Some(condition) });
+    }
+
+    fn set_breakpoint(&mut self, breakpoint: Breakpoint) {
+        // A new breakpoint replaces any already at the same address
+        match self.breakpoints.binary_search_by_key(&breakpoint.addr, |b| b.addr) {
+            Ok(at) => self.breakpoints[at] = breakpoint,
+            Err(at) => self.breakpoints.insert(at, breakpoint),
+        }
+    }
+
+    fn breaks_here(&self) -> bool {
+        // Whether a breakpoint at the pc wants to stop the chip now
+        match self.breakpoints.binary_search_by_key(&self.pc, |b| b.addr) {
+            Ok(at) => match &self.breakpoints[at].condition {
+                Some(condition) => condition.holds(&self.registers()),
+                None => true,
+            },
+            Err(_) => false,
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        // Returns whether there was a breakpoint at addr
+        match self.breakpoints.binary_search_by_key(&addr, |b| b.addr) {
+            Ok(at) => {
+                self.breakpoints.remove(at);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        // In address order
+        &self.breakpoints
+    }
+
+    pub fn add_watchpoint(&mut self, start: usize, end: usize, access: Access) {
+        // Makes run_for stop after an instruction accesses memory in start..end
+        self.watchpoints.push(Watchpoint { start, end, access });
+    }
+
+    pub fn remove_watchpoint(&mut self, start: usize, end: usize) -> bool {
+        // Returns whether there were watchpoints on exactly start..end
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|watch| (watch.start, watch.end) != (start, end));
+        self.watchpoints.len() != len
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn set_draw_break(&mut self, draw_break: DrawBreak) {
+        self.draw_break = draw_break;
+    }
+
+    pub fn draw_break(&self) -> DrawBreak {
+        self.draw_break
+    }
+
+    pub fn enable_trace_buffer(&mut self, n: usize) {
+        // Keeps the last n executed instructions, 0 disables the trace
+        self.trace = VecDeque::with_capacity(n);
+        self.trace_cap = n;
+    }
+
+    pub fn set_trace_range(&mut self, range: Option<Range<u16>>) {
+        // Limits the trace to instructions at addresses in range, None traces everything
+        self.trace_range = range;
+    }
+
+    pub fn history(&self) -> impl Iterator<Item=&TraceEntry> {
+        // The last instructions run, oldest first, while enable_trace_buffer
+        // keeps them
+        self.trace.iter()
+    }
+
+    #[deprecated(note = "use history")]
+    pub fn recent_trace(&self) -> impl Iterator<Item=&TraceEntry> {
+        self.history()
+    }
+
+    pub fn enable_undo(&mut self, n: usize) {
+        // Remembers enough to undo the last n instructions, 0 disables it
+        self.undo = VecDeque::with_capacity(n);
+        self.undo_cap = n;
+    }
+
+    pub fn undo_len(&self) -> usize {
+        // Instructions step_back can currently undo
+        self.undo.len()
+    }
+
+    pub fn step_back(&mut self) -> bool {
+        // Undoes the last instruction run, returns false when there is no
+        // undo record left. Timers ticking between frames aren't undone
+        let undo = match self.undo.pop_back() {
+            Some(undo) => undo,
+            None => return false,
+        };
+        for &(addr, value) in undo.memory.iter().rev() {
+            let _ = self.poke(addr, value);
+        }
+        if let Some(disp) = undo.disp {
+            self.disp = *disp;
+        }
+        if let Some(rng) = undo.rng {
+            self.rng = *rng;
+        }
+        self.calls.truncate(undo.sp as usize);
+        if let Some(call) = undo.call.filter(|_| self.calls.len() < undo.sp as usize) {
+            self.calls.push(call);
+        }
+        self.v = undo.v;
+        self.i = undo.i;
+        self.pc = undo.pc;
+        self.sp = undo.sp;
+        self.dt = undo.dt;
+        self.st = undo.st;
+        self.input_wait = undo.input_wait;
+        self.input_register = undo.input_register;
+        self.wait_key = undo.wait_key;
+        self.halted = undo.halted;
+        self.hires = undo.hires;
+        self.rpl = undo.rpl;
+        self.planes = undo.planes;
+        self.audio_pattern = undo.audio_pattern;
+        self.pitch = undo.pitch;
+        self.rng_replay_pos = undo.rng_replay_pos;
+        self.rng_log.truncate(undo.rng_log_len);
+        // Continuing shouldn't stop straight away on a breakpoint stepped back onto
+        self.at_break = true;
+        true
+    }
+
+    pub fn enable_coverage(&mut self, enabled: bool) {
+        // Starts or stops counting executions per address and per opcode, from zero
+        self.coverage_enabled = enabled;
+        self.clear_coverage();
+    }
+
+    pub fn clear_coverage(&mut self) {
+        self.coverage = if self.coverage_enabled { vec![0; self.memory.len()] } else { Vec::new() };
+        self.opcode_counts = if self.coverage_enabled { vec![0; OPCODES.len()] } else { Vec::new() };
+    }
+
+    pub fn execution_counts(&self) -> &[u64] {
+        // Indexed by address, empty unless coverage is enabled
+        &self.coverage
+    }
+
+    pub fn opcode_counts(&self) -> Vec<(u16, &'static str, u64)> {
+        // Pattern, mnemonic and count of each opcode that has run, as in supported_opcodes
+        OPCODES.iter().zip(self.opcode_counts.iter())
+            .filter(|(_, &count)| count > 0)
+            .map(|(&(pattern, mnemonic, _), &count)| (pattern, mnemonic, count))
+            .collect()
+    }
+
+    fn count_execution(&mut self, instruction: &Instruction) {
+        // Memory can be resized after coverage was enabled
+        if self.coverage.len() != self.memory.len() {
+            self.coverage.resize(self.memory.len(), 0);
+        }
+        if let Some(count) = self.coverage.get_mut(self.pc as usize) {
+            *count += 1;
+        }
+        if let Some(index) = opcode_index(instruction) {
+            self.opcode_counts[index] += 1;
         }
     }
 
-    pub fn load_rom(&mut self, rom : &[u8]) -> Result<(), Error> {
-        // Load a rom into memory
-        if rom.len() > CHIP8_MEM - RAM_SIZE {
-            return Err(Error::RomTooLarge(rom.len()))
+    fn save_undo(&mut self, instruction: &Instruction) {
+        // Records the state before an instruction, write_mem adds the bytes it overwrites
+        if self.undo.len() == self.undo_cap {
+            self.undo.pop_front();
         }
-        self.memory[ROM_SIZE..ROM_SIZE + rom.len()].copy_from_slice(rom);
-        Ok(())
+        let draws = matches!(instruction, Instruction::Cls | Instruction::Drw { .. } | Instruction::ScrollDown { .. }
+            | Instruction::ScrollUp { .. } | Instruction::ScrollRight | Instruction::ScrollLeft | Instruction::Low
+            | Instruction::High | Instruction::Unknown(0x0230));
+        self.undo.push_back(Undo {
+            v: self.v,
+            i: self.i,
+            pc: self.pc,
+            sp: self.sp,
+            call: self.calls.last().copied(),
+            dt: self.dt,
+            st: self.st,
+            input_wait: self.input_wait,
+            input_register: self.input_register,
+            wait_key: self.wait_key,
+            halted: self.halted,
+            hires: self.hires,
+            rpl: self.rpl,
+            planes: self.planes,
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch,
+            rng: if matches!(instruction, Instruction::Rnd { .. }) { Some(Box::new(self.rng.clone())) } else { None },
+            rng_replay_pos: self.rng_replay_pos,
+            rng_log_len: self.rng_log.len(),
+            memory: Vec::new(),
+            disp: if draws { Some(Box::new(self.disp)) } else { None },
+        });
     }
 
-    pub fn tone(&self) -> bool {
-        // Whether a tone should be played or not
-        self.tone
+    pub fn step(&mut self) -> Result<StepInfo, Error> {
+        // Executes a single instruction and reports what it did
+        self.snapshot_registers();
+        let opcode = self.get_opcode()?;
+        let instruction = self.decode_current(opcode);
+        let pc_before = self.pc;
+        let disp = self.disp;
+        self.exec(opcode)?;
+        Ok(StepInfo {
+            opcode,
+            instruction,
+            pc_before,
+            pc_after: self.pc,
+            drew: self.disp != disp,
+            waiting_for_key: self.input_wait,
+        })
     }
 
-    pub fn disp(&self) -> [u8; CHIP8_WIDTH * CHIP8_HEIGHT / 8] {
-        self.disp
+    fn run_one(&mut self) -> Result<(), Error> {
+        let opcode = self.get_opcode()?;
+        self.exec(opcode)?;
+        Ok(())
     }
 
-    pub fn frame(&mut self, input_keys : [bool; 16]) -> Result<(), Error> {
-        // Executes instructions and simulates hardware for the duration of a frame
-        self.input_keys = input_keys;
-        if self.input_wait {
-            for i in 0..input_keys.len() {
-                if input_keys[i] {
-                    self.input_wait = false;
-                    self.v[self.input_register] = i as u8;
-                    break;
-                }
-            }
+    pub fn step_over(&mut self) -> Result<(), Error> {
+        // Steps, running a CALL through to its return before stopping
+        if self.get_opcode()? & 0xF000 != 0x2000 {
+            return self.step().map(|_| ());
         }
-        else {
-            if self.delay_timer > 0 {
-                self.delay_timer -= 1
+
+        self.snapshot_registers();
+        let depth = self.sp;
+        self.run_one()?;
+        self.run_while_deeper(depth)
+    }
+
+    pub fn step_out(&mut self) -> Result<(), Error> {
+        // Runs until the current subroutine returns, or steps once outside of one
+        if self.sp == 0 {
+            return self.step().map(|_| ());
+        }
+
+        self.snapshot_registers();
+        let depth = self.sp - 1;
+        self.run_one()?;
+        self.run_while_deeper(depth)
+    }
+
+    fn run_while_deeper(&mut self, depth: u8) -> Result<(), Error> {
+        // Runs until the call stack is back down to depth, stopping early on a breakpoint
+        let mut cycles = 0;
+        while self.sp > depth && !self.halted {
+            if self.breaks_here() {
+                self.at_break = true;
+                break;
             }
-            if self.sound_timer > 0 {
-                self.sound_timer -= 1
+            if cycles == STEP_OVER_LIMIT {
+                return Err(Error::StepLimit { pc: self.pc, cycles: STEP_OVER_LIMIT });
             }
-            let opcode = self.get_opcode();
-            self.exec(opcode);
+            self.run_one()?;
+            cycles += 1;
         }
-        
-        self.time += FRAME_TIME;
+        Ok(())
+    }
 
-        while self.time > 0 {
-            if self.pc as usize > CHIP8_MEM -1 {
-                return Err(Error::PcOutOfBounds(self.pc));
+    pub fn run_until_register(&mut self, reg: u8, value: u8, max_cycles: usize) -> Result<bool, Error> {
+        // Steps until V[reg] == value, giving up after max_cycles instructions
+        if reg as usize >= self.v.len() {
+            return Err(Error::InvalidRegister { register: reg });
+        }
+        self.snapshot_registers();
+        for _ in 0..max_cycles {
+            if self.v[reg as usize] == value {
+                return Ok(true);
             }
-            let w0 = self.memory[self.pc as usize];
-            let w1 = self.memory[self.pc + 1 as usize];
-            let adv = self.exec(w0, w1)?;
-            self.time -= adv as isize;
+            if self.halted {
+                return Ok(false);
+            }
+            self.run_one()?;
         }
-        Ok(())
+        Ok(self.v[reg as usize] == value)
+    }
+
+    fn selected_planes(&self) -> Vec<usize> {
+        // The bitplanes drawing and scrolling currently affect
+        (0..2).filter(|plane| self.planes & (1 << plane) != 0).collect()
     }
 
     pub fn op_00e0(&mut self) -> ProgramCounter {
         // Clears the display (CLS)
-        for byte in self.disp.iter_mut() {
-            *byte = 0;
+        for plane in self.selected_planes() {
+            self.disp[plane] = [0; DISP_SIZE];
+        }
+        if let Some(observer) = &self.observer {
+            observer.display_cleared();
         }
         ProgramCounter::Next
     }
 
-    pub fn op_00ee(&mut self) -> ProgramCounter {
+    pub fn op_00ee(&mut self) -> Result<ProgramCounter, Error> {
         // Return from a subroutine
+        if self.sp == 0 {
+            return Err(Error::StackUnderflow { pc: self.pc, opcode: self.opcode });
+        }
         self.sp -= 1;
-        ProgramCounter::Jump(self.stack[self.sp])
+        self.calls.truncate(self.sp as usize);
+        Ok(ProgramCounter::Jump(self.stack[self.sp as usize] as usize))
+    }
+
+    pub fn op_0nnn(&mut self, nnn: u16) -> ProgramCounter {
+        // Hands a machine code call to the trap hook, then carries on
+        // with the next instruction
+        if let Some(hook) = self.trap_hook.clone() {
+            hook(self, nnn);
+        }
+        ProgramCounter::Next
     }
 
     pub fn op_1nnn(&mut self, nnn: u16) -> ProgramCounter {
         // Jump to location address nnn
-        ProgramCounter::Jump(nnn)
+        // A jump to itself can never be left, so it is how most roms end
+        if nnn == self.pc {
+            self.halted = true;
+        }
+        ProgramCounter::Jump(nnn as usize)
     }
 
-    pub fn op_2nnn(&mut self, nnn: u16) -> ProgramCounter {
+    pub fn op_2nnn(&mut self, nnn: u16) -> Result<ProgramCounter, Error> {
         // Call operation, increments stack pointer
         // Places current PC to stop of stack
         // PC is then set to nnn
-        self.stack[self.sp] = self.pc + OPCODE_SIZE;
+        if self.sp as usize >= self.stack.len() {
+            return Err(Error::StackOverflow { pc: self.pc, opcode: self.opcode, depth: self.stack.len() });
+        }
+        self.stack[self.sp as usize] = self.pc.wrapping_add(OPCODE_SIZE as u16);
+        self.calls.truncate(self.sp as usize);
+        self.calls.push(CallFrame { call_site: self.pc, target: nnn });
         self.sp += 1;
-        ProgramCounter::Jump(nnn)
+        Ok(ProgramCounter::Jump(nnn as usize))
     }
 
     pub fn op_3xkk(&mut self, x: u8, kk: u8) -> ProgramCounter {
         // Skips next instruction is Vx == kk
-        if self.v[x] == kk {
+        if self.v[x as usize] == kk {
             ProgramCounter::Skip
         }
         else{
@@ -165,7 +1810,7 @@ impl  Chip {
 
     pub fn op_4xkk(&mut self, x: u8, kk: u8) -> ProgramCounter {
         // Skips next instruction if Vx != kk
-        if self.v[x] != kk {
+        if self.v[x as usize] != kk {
             ProgramCounter::Skip
         }
         else{
@@ -175,91 +1820,110 @@ impl  Chip {
 
     pub fn op_5xy0(&mut self, x: u8, y :u8) -> ProgramCounter {
         // Skips next instruction if Vx == Vy
-        if self.v[x] == self.v[y] {
+        if self.v[x as usize] == self.v[y as usize] {
             ProgramCounter::Skip
-        }    
+        }
+        else{
+            ProgramCounter::Next
+        }
     }
 
     pub fn op_6xkk(&mut self, x: u8, kk: u8) -> ProgramCounter {
         // Sets Vx = kk
-        self.v[x] = kk;
+        self.v[x as usize] = kk;
         ProgramCounter::Next
     }
 
     pub fn op_7xkk(&mut self, x: u8, kk: u8) -> ProgramCounter {
         // Sets Vx = Vx + kk
-        let vx = V[x] as u16;
+        let vx = self.v[x as usize] as u16;
         let val = kk as u16;
         let result = vx + val;
-        self.v[x] = result as u8;
+        self.v[x as usize] = result as u8;
         ProgramCounter::Next    
     }
 
     pub fn op_8xy0(&mut self, x: u8, y :u8) -> ProgramCounter {
         // Sets Vx = Vy
-        self.v[x] = self.v[y];
+        self.v[x as usize] = self.v[y as usize];
         ProgramCounter::Next
     }
 
     pub fn op_8xy1(&mut self, x: u8, y :u8) -> ProgramCounter {
         // Sets Vx = Vx OR Vy
-        self.v[x] |=  self.v[y];
+        self.v[x as usize] |=  self.v[y as usize];
+        self.logic_vf_reset();
         ProgramCounter::Next
     }
 
     pub fn op_8xy2(&mut self, x: u8, y :u8) -> ProgramCounter {
         // Sets Vx = Vx AND Vy
-        self.v[x] &= self.v[y];
+        self.v[x as usize] &= self.v[y as usize];
+        self.logic_vf_reset();
         ProgramCounter::Next
     }
 
     pub fn op_8xy3(&mut self, x: u8, y :u8) -> ProgramCounter {
         // Sets Vx = Vx XOR Vy
-        self.v[x] ^= self.v[y];
+        self.v[x as usize] ^= self.v[y as usize];
+        self.logic_vf_reset();
         ProgramCounter::Next
     }
 
+    fn logic_vf_reset(&mut self) {
+        // The VIP interpreter left VF cleared after the logic operations
+        if self.quirks.vf_reset {
+            self.v[0x0f] = 0;
+        }
+    }
+
     pub fn op_8xy4(&mut self, x: u8, y :u8) -> ProgramCounter {
         // Set Vx = Vx + Vy, Set VF = carry
-        let vx = self.v[x] as u16;
-        let vy = self.v[y] as u16;
-        let result = vx - vy;
-        self.v[x] = result as u8;
+        let vx = self.v[x as usize] as u16;
+        let vy = self.v[y as usize] as u16;
+        let result = vx + vy;
+        self.v[x as usize] = result as u8;
         self.v[0x0f] = if result > 0xFF {1} else {0};
-        ProgramCounter::Next 
+        ProgramCounter::Next
     }
 
     pub fn op_8xy5(&mut self, x: u8, y :u8) -> ProgramCounter {
         // Set Vx = Vx - Vy, set VF = NOT BORROW
-        self.v[0x0f] = if self.v[x] > self.v[y] {1} else {0};
-        self.v[x] = self.v[x].wrapping_sub(v[y]);
+        self.v[0x0f] = if self.v[x as usize] > self.v[y as usize] {1} else {0};
+        self.v[x as usize] = self.v[x as usize].wrapping_sub(self.v[y as usize]);
         ProgramCounter::Next
     }
 
-    pub fn op_8xy6(&mut self, x: u8) -> ProgramCounter {
-        // Set Vx = Vx SHR 1
-        self.v[0x0f] = self.v[x] & 1;
-        self.v[x] >>= 1;
+    pub fn op_8xy6(&mut self, x: u8, y: u8) -> ProgramCounter {
+        // Set Vx = Vx SHR 1, or Vx = Vy SHR 1 with the shift quirk
+        if self.quirks.shift_uses_vy {
+            self.v[x as usize] = self.v[y as usize];
+        }
+        self.v[0x0f] = self.v[x as usize] & 1;
+        self.v[x as usize] >>= 1;
         ProgramCounter::Next
     }
 
     pub fn op_8xy7(&mut self, x: u8, y :u8) -> ProgramCounter {
         //
-        self.v[0x0f] = if self.v[y] > self.v[x] {1} else {0};
-        self.v[x] = self.v[y].wrapping_sub(v[x]);
+        self.v[0x0f] = if self.v[y as usize] > self.v[x as usize] {1} else {0};
+        self.v[x as usize] = self.v[y as usize].wrapping_sub(self.v[x as usize]);
         ProgramCounter::Next
     }
 
-    pub fn op_8xye(&mut self, x: u8) -> ProgramCounter {
-        //
-        self.v[0x0f] = (self.v[x] & 0b10000000) >> 7;
-        self.v[x] <<= 1;
+    pub fn op_8xye(&mut self, x: u8, y: u8) -> ProgramCounter {
+        // Set Vx = Vx SHL 1, or Vx = Vy SHL 1 with the shift quirk
+        if self.quirks.shift_uses_vy {
+            self.v[x as usize] = self.v[y as usize];
+        }
+        self.v[0x0f] = (self.v[x as usize] & 0b10000000) >> 7;
+        self.v[x as usize] <<= 1;
         ProgramCounter::Next
     }
 
     pub fn op_9xy0(&mut self, x: u8, y: u8) -> ProgramCounter {
         //
-        if self.v[x] != self.v[y] {
+        if self.v[x as usize] != self.v[y as usize] {
             ProgramCounter::Skip
         }
         else{
@@ -274,153 +1938,979 @@ impl  Chip {
     }
 
     pub fn op_bnnn(&mut self, nnn: u16) -> ProgramCounter {
-        //
-        ProgramCounter::Jump((self.v[0] as u16) + nnn)
+        // Jump to nnn + V0, or to xnn + Vx on SCHIP style interpreters
+        let reg = if self.quirks.jump_uses_vx { (nnn >> 8) as usize } else { 0 };
+        ProgramCounter::Jump((self.v[reg] as u16 + nnn) as usize)
     }
 
-    pub fn op_cxkk(&mut self, x: u8, kk: u16) -> ProgramCounter {
+    pub fn op_cxkk(&mut self, x: u8, kk: u8) -> ProgramCounter {
         //
-        let mut rng = rand::thread_rng();
-        self.v[x] = rng.gen::<u8>() & kk;
+        self.v[x as usize] = self.random_byte() & kk;
         ProgramCounter::Next
     }
 
-    pub fn op_dxyn(&mut self, x: u8, y :u8, n: u8) -> ProgramCounter {
+    pub fn op_dxyn(&mut self, x: u8, y :u8, n: u8) -> Result<ProgramCounter, Error> {
+        // Draws an n byte sprite from I at (Vx, Vy), VF = collision
+        // On SCHIP, DXY0 draws a 16x16 sprite of 32 bytes instead.
+        // On XO-CHIP with both planes selected, the second plane's sprite
+        // data follows straight on from the first's.
+        // The origin always wraps onto the screen first, so (64, 32) draws at (0, 0).
+        // Pixels then running off the right or bottom edge wrap around to the
+        // opposite side, or are dropped when sprite clipping is enabled.
+        let width = self.width();
+        let height = self.height();
+        let big = n == 0 && self.variant != Variant::Chip8;
+        let (cols, rows) = if big { (16, 16) } else { (8, n as usize) };
+        let sprite_size = if big { 32 } else { n as usize };
+
+        let origin_x = self.v[x as usize] as usize % width;
+        let origin_y = self.v[y as usize] as usize % height;
         self.v[0x0f] = 0;
-        for byte in 0..(n as usize) {
-            let y = (self.v[y] as usize + byte) % CHIP8_HEIGHT;
-            for bit in 0..8 {
-                let x = (self.v[x] as usize + bit) % CHIP8_WIDTH;
-                let colour = (self.memory[self.i + byte] >> (7 - bit)) & 1;
-                self.v[0x0f] |= colour & self.mem[y][x];
-                self.mem[y][x] ^= colour;
+        for (count, plane) in self.selected_planes().into_iter().enumerate() {
+            let base = self.i as usize + count * sprite_size;
+            for row in 0..rows {
+                let mut y = origin_y + row;
+                if y >= height {
+                    if self.quirks.clipping {
+                        break;
+                    }
+                    y %= height;
+                }
+                let bits = if big {
+                    let addr = base + row * 2;
+                    (self.read_mem(addr)? as u16) << 8 | self.read_mem(addr + 1)? as u16
+                } else {
+                    (self.read_mem(base + row)? as u16) << 8
+                };
+                for col in 0..cols {
+                    let mut x = origin_x + col;
+                    if x >= width {
+                        if self.quirks.clipping {
+                            break;
+                        }
+                        x %= width;
+                    }
+                    if bits & (0x8000 >> col) != 0 && self.xor_pixel(plane, x, y) {
+                        self.v[0x0f] = 1;
+                    }
+                }
+            }
+        }
+
+        Ok(ProgramCounter::Next)
+    }
+
+    fn xor_pixel(&mut self, plane: usize, x: usize, y: usize) -> bool {
+        // Flips a pixel, returning whether it was set beforehand
+        let index = y * self.width() / 8 + x / 8;
+        let mask = 0x80 >> (x % 8);
+        let collided = self.disp[plane][index] & mask != 0;
+        self.disp[plane][index] ^= mask;
+        collided
+    }
+
+    pub fn op_00cn(&mut self, n: u8) -> ProgramCounter {
+        // SCHIP: scrolls the display down n pixels
+        let stride = self.width() / 8;
+        let shift = n as usize * stride;
+        let size = stride * self.height();
+        for plane in self.selected_planes() {
+            let disp = &mut self.disp[plane];
+            for index in (0..size).rev() {
+                disp[index] = if index >= shift { disp[index - shift] } else { 0 };
+            }
+        }
+        ProgramCounter::Next
+    }
+
+    pub fn op_00dn(&mut self, n: u8) -> ProgramCounter {
+        // XO-CHIP: scrolls the display up n pixels
+        let stride = self.width() / 8;
+        let shift = n as usize * stride;
+        let size = stride * self.height();
+        for plane in self.selected_planes() {
+            let disp = &mut self.disp[plane];
+            for index in 0..size {
+                disp[index] = if index + shift < size { disp[index + shift] } else { 0 };
+            }
+        }
+        ProgramCounter::Next
+    }
+
+    pub fn op_00fb(&mut self) -> ProgramCounter {
+        // SCHIP: scrolls the display right 4 pixels
+        let stride = self.width() / 8;
+        let size = stride * self.height();
+        for plane in self.selected_planes() {
+            for row in self.disp[plane][..size].chunks_mut(stride) {
+                for b in (0..stride).rev() {
+                    let carry = if b > 0 { row[b - 1] << 4 } else { 0 };
+                    row[b] = (row[b] >> 4) | carry;
+                }
             }
         }
+        ProgramCounter::Next
+    }
 
+    pub fn op_00fc(&mut self) -> ProgramCounter {
+        // SCHIP: scrolls the display left 4 pixels
+        let stride = self.width() / 8;
+        let size = stride * self.height();
+        for plane in self.selected_planes() {
+            for row in self.disp[plane][..size].chunks_mut(stride) {
+                for b in 0..stride {
+                    let carry = if b + 1 < stride { row[b + 1] >> 4 } else { 0 };
+                    row[b] = (row[b] << 4) | carry;
+                }
+            }
+        }
         ProgramCounter::Next
     }
 
+    pub fn op_00fd(&mut self) -> ProgramCounter {
+        // SCHIP: exits the interpreter
+        self.halted = true;
+        ProgramCounter::Jump(self.pc as usize)
+    }
+
+    pub fn op_00fe(&mut self) -> ProgramCounter {
+        // SCHIP: switches to low resolution
+        self.hires = false;
+        self.op_00e0()
+    }
+
+    pub fn op_00ff(&mut self) -> ProgramCounter {
+        // SCHIP: switches to high resolution
+        self.hires = true;
+        self.op_00e0()
+    }
+
     pub fn op_ex9e(&mut self, x: u8) -> ProgramCounter {
-        if self.input_keys[self.v[x]] as usize {
+        // Skips the next instruction if the key in Vx is down
+        // Only the low nibble of Vx selects a key
+        if self.input_keys[(self.v[x as usize] & 0x0f) as usize] {
             ProgramCounter::Skip
         }
         else {
-            ProgramCounter::Skip
+            ProgramCounter::Next
         }
     }
 
-    pub fn op_exal(&mut self, x: u8) -> ProgramCounter {
-        if !self.input_keys[self.v[x]] as usize {
+    pub fn op_exa1(&mut self, x: u8) -> ProgramCounter {
+        // Skips the next instruction if the key in Vx is up
+        if !self.input_keys[(self.v[x as usize] & 0x0f) as usize] {
             ProgramCounter::Skip
         }
         else {
-            ProgramCounter::Skip
+            ProgramCounter::Next
         }
     }
 
     pub fn op_fx07(&mut self, x: u8) -> ProgramCounter {
-        self.v[x] = self.dt;
-        ProgramCounter::Nexto
+        self.v[x as usize] = self.dt;
+        ProgramCounter::Next
     }
 
     pub fn op_fx0a(&mut self, x: u8) -> ProgramCounter {
         self.input_wait = true;
-        self.input_keys = x;
+        self.input_register = x as u16;
+        self.wait_key = None;
+        if let Some(observer) = &self.observer {
+            observer.key_waited(x);
+        }
+        ProgramCounter::Next
+    }
+
+    pub fn op_fx15(&mut self, x: u8) -> ProgramCounter {
+        self.dt = self.v[x as usize];
         ProgramCounter::Next
     }
 
     pub fn op_fx18(&mut self, x: u8) -> ProgramCounter {
-        self.st = self.v[x];
+        let was_silent = !self.tone();
+        self.st = self.v[x as usize];
+        if let Some(observer) = &self.observer {
+            if was_silent && self.tone() {
+                observer.sound_started();
+            }
+        }
         ProgramCounter::Next
     }
 
     pub fn op_fx1e(&mut self, x: u8) -> ProgramCounter {
-        self.i = self.v[x] as u8;
+        self.i = self.i.wrapping_add(self.v[x as usize] as u16);
         self.v[0x0f] = if self.i > 0x0F00 {1} else {0};
         ProgramCounter::Next
     }
 
     pub fn op_fx29(&mut self, x: u8) -> ProgramCounter {
-        self.i = (self.v[x] as u8) * 5;
+        self.i = self.v[x as usize] as u16 * 5;
         ProgramCounter::Next
     }
 
-    pub fn op_fx33(&mut self, x: u8) -> ProgramCounter {
-        self.mem[self.i] = self.v[x] / 100;
-        self.mem[self.i + 1] = (self.v[x] % 100) / 10;
-        self.mem[self.i + 2] = self.v[x] % 10;
+    pub fn op_fx30(&mut self, x: u8) -> ProgramCounter {
+        // SCHIP: points I at the 10 byte big font sprite for digit Vx
+        self.i = (BIG_FONT_ADDR + (self.v[x as usize] & 0x0f) as usize * 10) as u16;
         ProgramCounter::Next
     }
 
-    pub fn op_fx55(&mut self, x: u8) -> ProgramCounter {
-        for i in 0..x + 1 {
-            self.ram[self.i + 1] = self.v[i];
+    fn rpl_count(&self, x: u8) -> usize {
+        // SCHIP has 8 RPL flags, XO-CHIP has 16
+        let flags = if self.variant == Variant::XoChip { 16 } else { 8 };
+        (x as usize + 1).min(flags)
+    }
+
+    pub fn op_fx75(&mut self, x: u8) -> ProgramCounter {
+        // SCHIP: saves V0 to Vx in the RPL flags
+        let count = self.rpl_count(x);
+        self.rpl[..count].copy_from_slice(&self.v[..count]);
+        ProgramCounter::Next
+    }
+
+    pub fn op_fx85(&mut self, x: u8) -> ProgramCounter {
+        // SCHIP: restores V0 to Vx from the RPL flags
+        let count = self.rpl_count(x);
+        self.v[..count].copy_from_slice(&self.rpl[..count]);
+        ProgramCounter::Next
+    }
+
+    pub fn op_5xy2(&mut self, x: u8, y: u8) -> Result<ProgramCounter, Error> {
+        // XO-CHIP: stores Vx to Vy, in either order, at I without changing I
+        let (x, y) = (x as usize, y as usize);
+        let regs: Vec<usize> = if x <= y { (x..=y).collect() } else { (y..=x).rev().collect() };
+        for (offset, reg) in regs.into_iter().enumerate() {
+            self.write_mem(self.i as usize + offset, self.v[reg])?;
         }
+        Ok(ProgramCounter::Next)
+    }
+
+    pub fn op_5xy3(&mut self, x: u8, y: u8) -> Result<ProgramCounter, Error> {
+        // XO-CHIP: reads Vx to Vy, in either order, from I without changing I
+        let (x, y) = (x as usize, y as usize);
+        let regs: Vec<usize> = if x <= y { (x..=y).collect() } else { (y..=x).rev().collect() };
+        for (offset, reg) in regs.into_iter().enumerate() {
+            self.v[reg] = self.read_mem(self.i as usize + offset)?;
+        }
+        Ok(ProgramCounter::Next)
+    }
+
+    pub fn op_f000(&mut self) -> Result<ProgramCounter, Error> {
+        // XO-CHIP: loads I with the 16 bit word following the instruction
+        let pc = self.pc as usize;
+        self.i = (self.peek(pc + 2)? as u16) << 8 | self.peek(pc + 3)? as u16;
+        Ok(ProgramCounter::Next)
+    }
+
+    pub fn op_fn01(&mut self, n: u8) -> ProgramCounter {
+        // XO-CHIP: selects which bitplanes drawing affects
+        self.planes = n & 0x03;
         ProgramCounter::Next
     }
 
-    pub fn op_fx65(&mut self, x: u8) -> ProgramCounter {
-        for i in 0..x + 1 {
-            self.v[i] = self.mem[self.i + i];
+    pub fn op_f002(&mut self) -> Result<ProgramCounter, Error> {
+        // XO-CHIP: copies the 16 byte audio pattern from I
+        for offset in 0..16 {
+            self.audio_pattern[offset] = self.read_mem(self.i as usize + offset)?;
         }
+        Ok(ProgramCounter::Next)
+    }
+
+    pub fn op_fx3a(&mut self, x: u8) -> ProgramCounter {
+        // XO-CHIP: sets the audio pattern pitch
+        self.pitch = self.v[x as usize];
         ProgramCounter::Next
     }
 
-    pub fn get_opcode(&mut self) -> u16 {
-        (self.ram[self.pc] as u16) << 8 | (self.ram[self.pc + 1] as u16)
+    pub fn op_fx33(&mut self, x: u8) -> Result<ProgramCounter, Error> {
+        let vx = self.v[x as usize];
+        self.write_mem(self.i as usize, vx / 100)?;
+        self.write_mem(self.i as usize + 1, (vx % 100) / 10)?;
+        self.write_mem(self.i as usize + 2, vx % 10)?;
+        Ok(ProgramCounter::Next)
+    }
+
+    pub fn op_fx55(&mut self, x: u8) -> Result<ProgramCounter, Error> {
+        for i in 0..x + 1 {
+            self.write_mem(self.i as usize + i as usize, self.v[i as usize])?;
+        }
+        if self.quirks.load_store_inc_i {
+            self.i = self.i.wrapping_add(x as u16 + 1);
+        }
+        Ok(ProgramCounter::Next)
+    }
+
+    pub fn op_fx65(&mut self, x: u8) -> Result<ProgramCounter, Error> {
+        for i in 0..x + 1 {
+            self.v[i as usize] = self.read_mem(self.i as usize + i as usize)?;
+        }
+        if self.quirks.load_store_inc_i {
+            self.i = self.i.wrapping_add(x as u16 + 1);
+        }
+        Ok(ProgramCounter::Next)
+    }
+
+    pub fn get_opcode(&mut self) -> Result<u16, Error> {
+        // Fetches the instruction at the PC
+        let pc = self.pc as usize;
+        if pc + 1 >= self.memory.len() {
+            return Err(Error::PcOutOfBounds { pc: self.pc });
+        }
+        Ok((self.peek(pc)? as u16) << 8 | self.peek(pc + 1)? as u16)
     }
 
     pub fn exec(&mut self, opcode: u16) -> Result<usize, Error> {
-        // Execute steps given w0 and w1
-        let nibbles = (
-            (opcode & 0xF000) >> 12 as u8,
-            (opcode & 0x0F00) >> 8 as u8,
-            (opcode & 0x00F0) >> 4 as u8,
-            (opcode & 0x000F) as u8,
-        );
-
-        let nnn = (opcode & 0x0FFF) as u16;
-        let kk = (opcode & 0x00FF) as u8;
-        let x = nibbles.1 as u8;
-        let y = nibbles.2 as u8;
-        let n = nibbles.3 as u8;
-
-        let pc_change = match nibbles {
-            (0x00, 0x00, 0x0e, 0x00) => self.op_00e0(),
-            (0x00, 0x00, 0x0e, 0x0e) => self.op_00ee(),
-            (0x01, _, _, _) => self.op_1nnn(nnn),
-            (0x02, _, _, _) => self.op_2nnn(nnn),
-            (0x03, _, _, _) => self.op_3xkk(x, kk),
-            (0x04, _, _, _) => self.op_4xkk(x, kk),
-            (0x05, _, _, 0x00) => self.op_5xy0(x, y),
-            (0x06, _, _, _) => self.op_6xkk(x, kk),
-            (0x07, _, _, _) => self.op_7xkk(x, kk),
-            (0x08, _, _, 0x00) => self.op_8xy0(x, y),
-            (0x08, _, _, 0x01) => self.op_8xy1(x, y),
-            (0x08, _, _, 0x02) => self.op_8xy2(x, y),
-            (0x08, _, _, 0x03) => self.op_8xy3(x, y),
-            (0x08, _, _, 0x04) => self.op_8xy4(x, y),
-            (0x08, _, _, 0x05) => self.op_8xy5(x, y),
-            (0x08, _, _, 0x06) => self.op_8xy6(x),
-            (0x08, _, _, 0x07) => self.op_8xy7(x, y),
-            (0x08, _, _, 0x0e) => self.op_8xye(x),
-            (0x09, _, _, 0x00) => self.op_9xy0(x, y),
-            (0x0a, _, _, _) => self.op_annn(nnn),
-            (0x0b, _, _, _) => self.op_bnnn(nnn),
-            (0x0c, _, _, _) => self.op_cxkk(x, kk),
-            (0x0d, _, _, _) => self.op_dxyn(x, y, n),
-            (0x0e, _, 0x09, 0x0e) => self.op_ex9e(x),
-            (0x0e, _, 0x0a, 0x01) => self.op_exa1(x),
-            (0x0f, _, 0x00, 0x07) => self.op_fx07(x),
-            (0x0f, _, 0x00, 0x0a) => self.op_fx0a(x),
-            (0x0f, _, 0x01, 0x05) => self.op_fx15(x),
-            (0x0f, _, 0x01, 0x08) => self.op_fx18(x),
-            (0x0f, _, 0x01, 0x0e) => self.op_fx1e(x),
-            (0x0f, _, 0x02, 0x09) => self.op_fx29(x),
-            (0x0f, _, 0x03, 0x03) => self.op_fx33(x),
-            (0x0f, _, 0x05, 0x05) => self.op_fx55(x),
-            (0x0f, _, 0x06, 0x05) => self.op_fx65(x),
-        }
-    } 
+        // Execute steps given w0 and w1. An instruction that fails leaves no
+        // undo record, so step_back goes to the last one that ran
+        let result = self.run_opcode(opcode);
+        if result.is_err() && self.undo_cap > 0 {
+            self.undo.pop_back();
+        }
+        result
+    }
+
+    fn run_opcode(&mut self, opcode: u16) -> Result<usize, Error> {
+        // The undo record goes in first, write_mem adds to it as memory changes
+        self.opcode = opcode;
+        self.at_break = false;
+        self.watch_hit.set(None);
+        // The entry goes in before executing so a crashing instruction is
+        // still the last one in the buffer, it is finished off afterwards
+        let traced = (self.trace_cap > 0 || self.observer.is_some())
+            && self.trace_range.as_ref().is_none_or(|range| range.contains(&self.pc));
+        let mut entry = TraceEntry {
+            pc: self.pc,
+            opcode,
+            instruction: Instruction::Unknown(opcode),
+            changed_v: 0,
+            changed_i: false,
+            v: self.v,
+            i: self.i,
+        };
+        if traced {
+            entry.instruction = self.decode_current(opcode);
+            if self.trace_cap > 0 {
+                if self.trace.len() == self.trace_cap {
+                    self.trace.pop_front();
+                }
+                self.trace.push_back(entry);
+            }
+        }
+
+        let instruction = decode(opcode);
+        if self.undo_cap > 0 {
+            self.save_undo(&instruction);
+        }
+        if self.coverage_enabled {
+            self.count_execution(&instruction);
+        }
+
+        let supported = self.variant.supports(&instruction);
+
+        let pc_change = match instruction {
+            Instruction::Cls => self.op_00e0(),
+            Instruction::Unknown(0x0230) if self.two_page => self.op_00e0(),
+            Instruction::Ret => self.op_00ee()?,
+            Instruction::ScrollDown { n } if supported => self.op_00cn(n),
+            Instruction::ScrollUp { n } if supported => self.op_00dn(n),
+            Instruction::ScrollRight if supported => self.op_00fb(),
+            Instruction::ScrollLeft if supported => self.op_00fc(),
+            Instruction::Exit if supported => self.op_00fd(),
+            Instruction::Low if supported => self.op_00fe(),
+            Instruction::High if supported => self.op_00ff(),
+            _ if opcode & 0xF000 == 0 && self.trap_hook.is_some() => self.op_0nnn(opcode & 0x0FFF),
+            Instruction::Jp { nnn } => self.op_1nnn(nnn),
+            Instruction::Call { nnn } => self.op_2nnn(nnn)?,
+            Instruction::SeByte { x, kk } => self.op_3xkk(x, kk),
+            Instruction::SneByte { x, kk } => self.op_4xkk(x, kk),
+            Instruction::SeReg { x, y } => self.op_5xy0(x, y),
+            Instruction::Save { x, y } if supported => self.op_5xy2(x, y)?,
+            Instruction::Load { x, y } if supported => self.op_5xy3(x, y)?,
+            Instruction::LdByte { x, kk } => self.op_6xkk(x, kk),
+            Instruction::AddByte { x, kk } => self.op_7xkk(x, kk),
+            Instruction::LdReg { x, y } => self.op_8xy0(x, y),
+            Instruction::Or { x, y } => self.op_8xy1(x, y),
+            Instruction::And { x, y } => self.op_8xy2(x, y),
+            Instruction::Xor { x, y } => self.op_8xy3(x, y),
+            Instruction::AddReg { x, y } => self.op_8xy4(x, y),
+            Instruction::Sub { x, y } => self.op_8xy5(x, y),
+            Instruction::Shr { x, y } => self.op_8xy6(x, y),
+            Instruction::Subn { x, y } => self.op_8xy7(x, y),
+            Instruction::Shl { x, y } => self.op_8xye(x, y),
+            Instruction::SneReg { x, y } => self.op_9xy0(x, y),
+            Instruction::LdI { nnn } => self.op_annn(nnn),
+            Instruction::JpV0 { nnn } => self.op_bnnn(nnn),
+            Instruction::Rnd { x, kk } => self.op_cxkk(x, kk),
+            Instruction::Drw { x, y, n } => self.op_dxyn(x, y, n)?,
+            Instruction::Skp { x } => self.op_ex9e(x),
+            Instruction::Sknp { x } => self.op_exa1(x),
+            Instruction::LdILong { .. } if supported => self.op_f000()?,
+            Instruction::Plane { n } if supported => self.op_fn01(n),
+            Instruction::Audio if supported => self.op_f002()?,
+            Instruction::LdVxDt { x } => self.op_fx07(x),
+            Instruction::LdVxK { x } => self.op_fx0a(x),
+            Instruction::LdDtVx { x } => self.op_fx15(x),
+            Instruction::LdStVx { x } => self.op_fx18(x),
+            Instruction::AddI { x } => self.op_fx1e(x),
+            Instruction::LdF { x } => self.op_fx29(x),
+            Instruction::LdHf { x } if supported => self.op_fx30(x),
+            Instruction::LdB { x } => self.op_fx33(x)?,
+            Instruction::Pitch { x } if supported => self.op_fx3a(x),
+            Instruction::LdMemVx { x } => self.op_fx55(x)?,
+            Instruction::LdVxMem { x } => self.op_fx65(x)?,
+            Instruction::LdRVx { x } if supported => self.op_fx75(x),
+            Instruction::LdVxR { x } if supported => self.op_fx85(x),
+            _ => return self.illegal(opcode),
+        };
+
+        if traced {
+            entry.changed_v = (0..16).filter(|&n| self.v[n] != entry.v[n]).fold(0, |mask, n| mask | 1 << n);
+            entry.changed_i = self.i != entry.i;
+            entry.v = self.v;
+            entry.i = self.i;
+            if self.trace_cap > 0 {
+                if let Some(last) = self.trace.back_mut() {
+                    *last = entry;
+                }
+            }
+        }
+        if let Some(observer) = &self.observer {
+            observer.instruction_executed(self.pc, opcode);
+            if traced {
+                observer.traced(&entry);
+            }
+        }
+
+        let size = self.opcode_size(opcode);
+        match pc_change {
+            ProgramCounter::Next => self.pc = self.pc.wrapping_add(size as u16),
+            ProgramCounter::Skip => {
+                let next = self.pc as usize + size;
+                self.pc = (next + self.opcode_size_at(next)) as u16;
+            }
+            ProgramCounter::Jump(addr) => self.pc = addr as u16,
+        }
+        Ok(self.opcode_cost(opcode))
+    }
+
+    fn opcode_size(&self, opcode: u16) -> usize {
+        // Bytes an instruction takes up, XO-CHIP F000 NNNN is the only long one
+        if self.variant == Variant::XoChip && opcode == 0xF000 { 2 * OPCODE_SIZE } else { OPCODE_SIZE }
+    }
+
+    fn opcode_size_at(&self, addr: usize) -> usize {
+        // Size of the instruction stored at addr
+        match (self.peek(addr), self.peek(addr + 1)) {
+            (Ok(hi), Ok(lo)) => self.opcode_size((hi as u16) << 8 | lo as u16),
+            _ => OPCODE_SIZE,
+        }
+    }
+
+    fn decode_current(&self, opcode: u16) -> Instruction {
+        // Decodes the opcode at the PC, reading the address word of F000 NNNN
+        match decode(opcode) {
+            Instruction::LdILong { .. } => {
+                let pc = self.pc as usize;
+                let word = |at| self.peek(at).unwrap_or(0) as u16;
+                Instruction::LdILong { nnnn: word(pc + 2) << 8 | word(pc + 3) }
+            }
+            instruction => instruction,
+        }
+    }
+
+    fn opcode_cost(&self, opcode: u16) -> usize {
+        // How much of the frame budget an instruction consumes
+        if self.vip_cycle_timing {
+            return (VIP_FETCH_CYCLES + self.vip_cycles(opcode)) * VIP_CYCLE_NANOS;
+        }
+        match opcode & 0xF000 {
+            0xD000 if self.vip_draw_timing => self.cycle_time * (1 + (opcode & 0x000F) as usize),
+            _ => self.cycle_time,
+        }
+    }
+
+    fn vip_cycles(&self, opcode: u16) -> usize {
+        // Approximate machine cycles the VIP interpreter took to execute an opcode
+        let x = ((opcode & 0x0F00) >> 8) as usize;
+        let n = (opcode & 0x000F) as usize;
+        match (opcode & 0xF000, opcode & 0x00FF) {
+            (0x0000, 0xE0) => 3078,
+            (0x0000, 0xEE) => 10,
+            (0x1000, _) => 12,
+            (0x2000, _) => 26,
+            (0x3000, _) | (0x4000, _) => 10,
+            (0x5000, _) | (0x9000, _) => 14,
+            (0x6000, _) => 6,
+            (0x7000, _) => 10,
+            (0x8000, _) => 44,
+            (0xA000, _) => 12,
+            (0xB000, _) => 22,
+            (0xC000, _) => 36,
+            // Sprites not aligned to a byte need shifting across two bytes per row
+            (0xD000, _) => 26 + n * if self.v[x].is_multiple_of(8) { 38 } else { 58 },
+            (0xE000, _) => 14,
+            (0xF000, 0x0A) => 19,
+            (0xF000, 0x1E) | (0xF000, 0x29) => 16,
+            (0xF000, 0x33) => 84,
+            (0xF000, 0x55) | (0xF000, 0x65) => 14 + 14 * (x + 1),
+            (0xF000, _) => 10,
+            _ => 0,
+        }
+    }
+
+    fn illegal(&mut self, opcode: u16) -> Result<usize, Error> {
+        // Handles an unknown opcode according to the illegal policy
+        match self.on_illegal {
+            IllegalPolicy::Error => Err(Error::InvalidOperation { pc: self.pc, opcode }),
+            IllegalPolicy::Halt => {
+                self.halted = true;
+                Ok(0)
+            }
+            IllegalPolicy::Skip => {
+                // Roms often keep data between their code, so carry on past it
+                self.warnings.push(Warning::SkippedOpcode(self.pc, opcode));
+                self.pc = self.pc.wrapping_add(OPCODE_SIZE as u16);
+                Ok(self.cycle_time)
+            }
+        }
+    }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chip_with(rom: &[u8]) -> Chip<'static> {
+        let mut chip = Chip::new();
+        chip.load_rom(rom).unwrap();
+        chip
+    }
+
+    #[test]
+    fn validate_rejects_pc_past_the_rom() {
+        let mut chip = chip_with(&[0x60, 0x01, 0x12, 0x02]);
+        assert!(chip.validate().is_ok());
+        chip.set_pc(0x204);
+        assert!(matches!(chip.validate(), Err(Error::PcNotInRom { pc: 0x204, rom_len: 4 })));
+        chip.set_pc(0x201);
+        assert!(matches!(chip.validate(), Err(Error::PcMisaligned { pc: 0x201 })));
+    }
+
+    #[test]
+    fn display_delta_round_trips() {
+        // V0 = 0, I = font 0, draw it at 0,0
+        let mut chip = chip_with(&[0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05]);
+        for _ in 0..3 {
+            chip.step().unwrap();
+        }
+        let delta = chip.display_delta();
+        assert!(!delta.is_empty());
+        let mut remote = Chip::new();
+        remote.apply_display_delta(&delta);
+        assert_eq!(remote.disp(), chip.disp());
+        assert!(chip.display_delta().is_empty());
+    }
+
+    #[test]
+    fn illegal_opcodes_follow_the_policy() {
+        let rom = [0x50, 0x01];
+        let mut chip = chip_with(&rom);
+        chip.set_illegal_policy(IllegalPolicy::Error);
+        assert!(matches!(chip.step(), Err(Error::InvalidOperation { pc: 0x200, opcode: 0x5001 })));
+
+        let mut chip = chip_with(&rom);
+        chip.set_illegal_policy(IllegalPolicy::Halt);
+        chip.step().unwrap();
+        assert!(chip.halted());
+        assert_eq!(chip.pc(), 0x200);
+
+        let mut chip = chip_with(&rom);
+        chip.set_illegal_policy(IllegalPolicy::Skip);
+        chip.step().unwrap();
+        assert!(!chip.halted());
+        assert_eq!(chip.pc(), 0x202);
+        assert_eq!(chip.take_warnings(), vec![Warning::SkippedOpcode(0x200, 0x5001)]);
+    }
+
+    #[test]
+    fn render_sprite_decodes_the_zero_glyph() {
+        let chip = Chip::new();
+        let rows: Vec<String> = chip.render_sprite(0, 5).unwrap().chunks(8)
+            .map(|row| row.iter().map(|&lit| if lit { '#' } else { '.' }).collect())
+            .collect();
+        assert_eq!(rows, ["####....", "#..#....", "#..#....", "#..#....", "####...."]);
+        assert!(chip.render_sprite(0xFFE, 5).is_err());
+    }
+
+    #[test]
+    fn reset_replays_the_seeded_random_numbers() {
+        let mut chip = Chip::with_seed(42);
+        chip.load_rom(&[0xC0, 0xFF, 0xC1, 0xFF, 0xC2, 0xFF, 0xC3, 0xFF]).unwrap();
+        let draw = |chip: &mut Chip| {
+            for _ in 0..4 {
+                chip.step().unwrap();
+            }
+            chip.registers().v
+        };
+        let first = draw(&mut chip);
+        chip.reset();
+        assert_eq!(draw(&mut chip), first);
+        chip.reset();
+        chip.reset_rng();
+        assert_eq!(draw(&mut chip), first);
+    }
+
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn frame_timing_fills_its_ring_buffer() {
+        // A tight loop of additions, so each frame does some work
+        let mut chip = chip_with(&[0x70, 0x01, 0x12, 0x00]);
+        chip.enable_frame_timing(4);
+        let mut keypad = Keypad::new();
+        for _ in 0..6 {
+            chip.frame(&mut keypad).unwrap();
+        }
+        assert_eq!(chip.frame_timings().len(), 4);
+        assert!(chip.average_frame_time() > Duration::ZERO);
+    }
+
+    #[test]
+    fn step_over_runs_a_call_through() {
+        // call 0x206, then v1 := 1, with the subroutine setting v0 to 6
+        let mut chip = chip_with(&[0x22, 0x06, 0x61, 0x01, 0x12, 0x04, 0x60, 0x05, 0x70, 0x01, 0x00, 0xEE]);
+        chip.step_over().unwrap();
+        assert_eq!(chip.pc(), 0x202);
+        assert_eq!(chip.registers().v[0], 6);
+        assert_eq!(chip.registers().sp, 0);
+        chip.step_over().unwrap();
+        assert_eq!(chip.pc(), 0x204);
+        assert_eq!(chip.registers().v[1], 1);
+    }
+
+    #[test]
+    fn frame_stops_at_the_cycle_cap() {
+        // An endless loop given far more time than the cap allows
+        let mut chip = chip_with(&[0x70, 0x01, 0x12, 0x00]);
+        chip.set_max_cycles_per_frame(100);
+        let output = chip.run_for(&mut Keypad::new(), Duration::from_secs(60)).unwrap();
+        assert_eq!(output.instructions_executed, 100);
+        assert_eq!(chip.take_warnings(), vec![Warning::FrameCycleCap(100)]);
+    }
+
+    #[test]
+    fn key_overlay_marks_held_keys() {
+        let mut chip = chip_with(&[0x70, 0x01, 0x12, 0x00]);
+        let mut keypad = Keypad::new();
+        for key in [0x0, 0x5, 0xF] {
+            keypad.press(key);
+        }
+        chip.frame(&mut keypad).unwrap();
+        let before = chip.registers();
+        chip.draw_key_overlay();
+        assert_eq!(chip.registers(), before);
+
+        // Cells are 8x8 with a one pixel border, in keypad order
+        let cell = |chip: &Chip, row: usize, col: usize| chip.disp()[(row * 8 + 3) * 8 + col] != 0;
+        let lit: Vec<(usize, usize)> = (0..4).flat_map(|row| (0..4).map(move |col| (row, col)))
+            .filter(|&(row, col)| cell(&chip, row, col))
+            .collect();
+        assert_eq!(lit, [(1, 1), (3, 1), (3, 3)]);
+    }
+
+    #[test]
+    fn write_range_writes_all_or_nothing() {
+        let mut chip = Chip::new();
+        let patch: Vec<u8> = (1..=16).collect();
+        chip.write_range(0x800, &patch).unwrap();
+        assert_eq!(chip.read_range(0x800, 16).unwrap(), patch);
+
+        assert!(chip.write_range(0xFF8, &patch).is_err());
+        assert_eq!(chip.read_range(0xFF8, 8).unwrap(), [0; 8]);
+    }
+
+    #[test]
+    fn vip_draw_timing_charges_for_sprite_height() {
+        // Loops drawing a 1 row or a 15 row sprite, counting what fits in a frame
+        let loops = |n: u8, vip: bool| {
+            let mut chip = chip_with(&[0xD0, 0x10 | n, 0x12, 0x00]);
+            chip.set_vip_draw_timing(vip);
+            chip.frame(&mut Keypad::new()).unwrap().instructions_executed
+        };
+        assert_eq!(loops(1, false), loops(15, false));
+        // A pass costs 3 instructions' time for the short sprite, 17 for the tall one
+        let (short, tall) = (loops(1, true), loops(15, true));
+        assert!(short > tall * 5, "{} short draws vs {} tall", short, tall);
+    }
+
+    #[test]
+    fn opcode_table_covers_the_decoder() {
+        // Every instruction exec can be handed has exactly one table entry
+        let mut kinds = Vec::new();
+        for opcode in 0..=0xFFFF {
+            let instruction = decode(opcode);
+            if instruction == Instruction::Unknown(opcode) {
+                continue;
+            }
+            assert!(opcode_index(&instruction).is_some(), "{:04X} isn't in the table", opcode);
+            let kind = core::mem::discriminant(&instruction);
+            if !kinds.contains(&kind) {
+                kinds.push(kind);
+            }
+        }
+        assert_eq!(kinds.len(), supported_opcodes().len());
+        for (n, &(pattern, _, _)) in supported_opcodes().iter().enumerate() {
+            assert_eq!(opcode_index(&decode(pattern)), Some(n), "{:04X}", pattern);
+        }
+    }
+
+    #[test]
+    fn changed_registers_marks_the_written_register() {
+        let mut chip = chip_with(&[0x63, 0x2A]);
+        chip.step().unwrap();
+        assert_eq!(chip.changed_registers(), 1 << 3);
+        assert!(chip.changed_pc());
+        assert!(!chip.changed_i() && !chip.changed_dt() && !chip.changed_st());
+    }
+
+    #[test]
+    fn key_wait_needs_a_fresh_press() {
+        // Wait for a key into V0, then count in V1
+        let mut chip = chip_with(&[0xF0, 0x0A, 0x71, 0x01, 0x12, 0x02]);
+        let mut keypad = Keypad::new();
+        keypad.press(0x3);
+        assert_eq!(chip.frame(&mut keypad).unwrap().instructions_executed, 1);
+        for _ in 0..30 {
+            assert_eq!(chip.frame(&mut keypad).unwrap().instructions_executed, 0);
+        }
+        assert_eq!(chip.registers().v[1], 0);
+
+        keypad.press(0x7);
+        chip.frame(&mut keypad).unwrap();
+        keypad.release(0x7);
+        let resumed = chip.frame(&mut keypad).unwrap();
+        assert_eq!(chip.registers().v[0], 0x7);
+
+        // The frames spent waiting aren't made up all at once
+        let normal = chip.frame(&mut keypad).unwrap();
+        assert!(resumed.instructions_executed <= normal.instructions_executed);
+    }
+
+    #[test]
+    fn sprites_at_the_corner_wrap_or_clip() {
+        // A 2x2 block drawn from (63, 31)
+        let draw = |clipping: bool| {
+            let mut chip = chip_with(&[0x60, 63, 0x61, 31, 0xA3, 0x00, 0xD0, 0x12]);
+            chip.set_quirks(Quirks { clipping, ..Quirks::default() });
+            chip.write_range(0x300, &[0xC0, 0xC0]).unwrap();
+            for _ in 0..4 {
+                chip.step().unwrap();
+            }
+            let mut lit = Vec::new();
+            for y in 0..chip.height() {
+                for x in 0..chip.width() {
+                    if chip.disp()[y * chip.width() / 8 + x / 8] & (0x80 >> (x % 8)) != 0 {
+                        lit.push((x, y));
+                    }
+                }
+            }
+            lit
+        };
+        assert_eq!(draw(false), [(0, 0), (63, 0), (0, 31), (63, 31)]);
+        assert_eq!(draw(true), [(63, 31)]);
+    }
+
+    #[test]
+    fn fill_leaves_fonts_and_rom_alone() {
+        let mut chip = Chip::with_fill(0xFF);
+        chip.load_rom(&[0x60, 0x01]).unwrap();
+        assert_eq!(chip.registers().v[3], 0xFF);
+        assert_eq!(chip.read_range(0, FONT_SET.len()).unwrap(), FONT_SET);
+        assert_eq!(chip.read_range(0x200, 3).unwrap(), [0x60, 0x01, 0xFF]);
+    }
+
+    #[test]
+    fn replayed_rng_log_draws_the_same_bytes() {
+        // Four CXKK draws into V0..V3
+        let rom = [0xC0, 0xFF, 0xC1, 0xFF, 0xC2, 0xFF, 0xC3, 0xFF];
+        let mut chip = chip_with(&rom);
+        chip.set_rng_logging(true);
+        for _ in 0..4 {
+            chip.step().unwrap();
+        }
+        let log = chip.rng_log().to_vec();
+        assert_eq!(log, chip.registers().v[..4]);
+
+        let mut replay = Chip::with_seed(7);
+        replay.load_rom(&rom).unwrap();
+        replay.replay_rng(log);
+        for _ in 0..4 {
+            replay.step().unwrap();
+        }
+        assert_eq!(replay.registers().v[..4], chip.registers().v[..4]);
+    }
+
+    #[test]
+    fn short_sound_timers_stay_silent_under_the_threshold() {
+        // ST = 1
+        let mut chip = chip_with(&[0x60, 0x01, 0xF0, 0x18]);
+        chip.step().unwrap();
+        chip.step().unwrap();
+        assert!(chip.tone());
+        chip.set_min_beep_ticks(2);
+        assert!(!chip.tone());
+    }
+
+    #[test]
+    fn state_diff_names_the_changed_register() {
+        let mut chip = chip_with(&[0x65, 0x2A]);
+        let before = chip.clone();
+        chip.step().unwrap();
+        chip.set_pc(before.pc());
+        assert_eq!(chip_state_diff(&before, &chip), ["V5: 0x00 != 0x2a"]);
+    }
+
+    #[test]
+    fn buffered_taps_are_seen_by_ex9e() {
+        // Skips setting V1 if key 5 is down
+        let rom = [0x60, 0x05, 0xE0, 0x9E, 0x61, 0x01, 0x12, 0x06];
+        let tap = |buffering: bool| {
+            let mut chip = chip_with(&rom);
+            chip.set_input_buffering(buffering);
+            let mut keypad = Keypad::new();
+            keypad.press(5);
+            keypad.release(5);
+            chip.frame(&mut keypad).unwrap();
+            chip.registers().v[1]
+        };
+        assert_eq!(tap(false), 1);
+        assert_eq!(tap(true), 0);
+    }
+
+    #[test]
+    fn run_until_register_stops_at_the_value() {
+        // Counts V0 up forever
+        let mut chip = chip_with(&[0x70, 0x01, 0x12, 0x00]);
+        assert!(chip.run_until_register(0, 10, 1000).unwrap());
+        assert_eq!(chip.registers().v[0], 10);
+        assert!(!chip.run_until_register(0, 5, 100).unwrap());
+    }
+
+    #[test]
+    fn interleaved_timers_count_down_mid_frame() {
+        // DT = 2, poll it until it reaches zero, then set VA
+        let rom = [0x60, 0x02, 0xF0, 0x15, 0xF1, 0x07, 0x31, 0x00, 0x12, 0x04, 0x6A, 0x01, 0x12, 0x0C];
+        let poll = |interleaved: bool| {
+            let mut chip = chip_with(&rom);
+            chip.set_interleaved_timers(interleaved);
+            chip.run_for(&mut Keypad::new(), Duration::from_millis(100)).unwrap();
+            chip.registers().v[0xA]
+        };
+        assert_eq!(poll(false), 0);
+        assert_eq!(poll(true), 1);
+    }
+
+    #[test]
+    fn trace_buffer_keeps_the_last_instructions() {
+        let mut chip = chip_with(&[0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0x63, 0x04, 0x64, 0x05]);
+        chip.enable_trace_buffer(3);
+        for _ in 0..5 {
+            chip.step().unwrap();
+        }
+        let pcs: Vec<u16> = chip.history().map(|entry| entry.pc).collect();
+        assert_eq!(pcs, [0x204, 0x206, 0x208]);
+    }
+
+    #[test]
+    fn inconsistent_quirks_warn_without_failing() {
+        let mut chip = Chip::with_variant(Variant::XoChip);
+        chip.set_sprite_clipping(false);
+        assert!(chip.take_warnings().is_empty());
+        chip.set_sprite_clipping(true);
+        assert_eq!(chip.take_warnings(), vec![Warning::QuirkConflict("XO-CHIP roms expect sprites to wrap, not clip")]);
+
+        let mut chip = Chip::with_variant(Variant::SuperChip);
+        chip.load_rom(&[0x60, 0x01]).unwrap();
+        chip.set_two_page(true);
+        assert_eq!(chip.take_warnings(), vec![Warning::QuirkConflict("two page hi-res only exists on plain CHIP-8")]);
+        chip.step().unwrap();
+        assert_eq!(chip.registers().v[0], 1);
+
+        // Hi-res on a CHIP-8 preset that never had it
+        let mut chip = ChipBuilder::new().platform(Platform::Chip48).two_page(true).build();
+        assert_eq!(chip.take_warnings(), vec![Warning::QuirkConflict("two page hi-res only ran on the COSMAC VIP")]);
+        let mut chip = ChipBuilder::new().platform(Platform::Vip).two_page(true).build();
+        assert!(chip.take_warnings().is_empty());
+
+        // Switching variant after setup is checked too, switching preset isn't a conflict
+        let mut chip = Chip::with_platform(Platform::Vip);
+        chip.set_variant(Variant::XoChip);
+        assert_eq!(chip.take_warnings(), vec![Warning::QuirkConflict("XO-CHIP roms expect sprites to wrap, not clip")]);
+        chip.set_platform(Platform::XoChip);
+        chip.set_platform(Platform::Vip);
+        assert!(chip.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn pitch_table_matches_the_formula() {
+        let mut chip = Chip::new();
+        for pitch in 0..=255 {
+            chip.pitch = pitch;
+            let expected = 4000.0 * 2f32.powf((pitch as f32 - 64.0) / 48.0);
+            assert!((chip.pitch_hz() - expected).abs() < expected * 1e-5, "pitch {}", pitch);
+        }
+    }
+
+    #[test]
+    fn borrowed_roms_run_like_copied_ones() {
+        // Draws a sprite from the rom across the top of the screen
+        let rom = [0xA2, 0x0C, 0xD0, 0x15, 0x70, 0x08, 0x30, 0x40, 0x12, 0x02, 0x12, 0x0A, 0xF0, 0x90, 0xF0, 0x90, 0xF0];
+        let mut copied = chip_with(&rom);
+        let mut borrowed = Chip::new();
+        borrowed.load_rom_borrowed(&rom).unwrap();
+        let (mut keys_a, mut keys_b) = (Keypad::new(), Keypad::new());
+        for _ in 0..3 {
+            assert_eq!(copied.frame(&mut keys_a).unwrap(), borrowed.frame(&mut keys_b).unwrap());
+        }
+        assert!(borrowed.halted());
+        assert!(borrowed.rom_borrowed());
+        assert!(copied.snapshot() == borrowed.snapshot());
+    }
+
+    #[test]
+    fn writing_into_a_borrowed_rom_copies_it() {
+        // Stores V0 over the byte after the halt loop
+        let rom = [0xA2, 0x08, 0x60, 0x12, 0xF0, 0x55, 0x12, 0x06, 0x00];
+        let mut chip = Chip::new();
+        chip.load_rom_borrowed(&rom).unwrap();
+        for _ in 0..2 {
+            chip.step().unwrap();
+        }
+        assert!(chip.rom_borrowed());
+        chip.step().unwrap();
+        assert!(!chip.rom_borrowed());
+        assert_eq!(chip.read_range(0x200, rom.len()).unwrap(), [0xA2, 0x08, 0x60, 0x12, 0xF0, 0x55, 0x12, 0x06, 0x12]);
+        assert_eq!(rom[8], 0x00);
+    }
+
+    #[test]
+    fn huge_read_lengths_are_out_of_range() {
+        let chip = chip_with(&[0x00, 0xE0]);
+        assert!(chip.read_range(0x200, usize::MAX).is_err());
+        assert!(chip.read_range(0xFFF, 2).is_err());
+        assert_eq!(chip.read_range(0xFFF, 1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn failing_instructions_leave_no_undo_record() {
+        let mut chip = chip_with(&[0x60, 0x05, 0x50, 0x01]);
+        chip.set_illegal_policy(IllegalPolicy::Error);
+        chip.enable_undo(8);
+        chip.step().unwrap();
+        assert!(chip.step().is_err());
+        assert_eq!(chip.undo_len(), 1);
+        assert!(chip.step_back());
+        assert_eq!(chip.pc(), 0x200);
+        assert_eq!(chip.registers().v[0], 0);
+    }
+}
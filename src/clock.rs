@@ -0,0 +1,96 @@
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+use crate::FRAME_TIME;
+
+// Where a run loop gets its time from. Swapping the real clock for a fixed
+// step or manual one lets headless runs advance virtual time deterministically
+pub trait Clock {
+    // Time that has passed since the last call
+    fn elapsed(&mut self) -> Duration;
+
+    // Waits out the rest of a frame, only a real clock needs to
+    fn wait(&mut self, _frame: Duration) {}
+}
+
+// Follows the host's wall clock, sleeping between frames
+#[cfg(feature = "std")]
+pub struct RealTimeClock {
+    last : Instant,                 // When elapsed was last called
+}
+
+#[cfg(feature = "std")]
+impl RealTimeClock {
+    pub fn new() -> Self {
+        RealTimeClock { last: Instant::now() }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for RealTimeClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for RealTimeClock {
+    fn elapsed(&mut self) -> Duration {
+        let now = Instant::now();
+        let elapsed = now - self.last;
+        self.last = now;
+        elapsed
+    }
+
+    fn wait(&mut self, frame: Duration) {
+        std::thread::sleep(frame);
+    }
+}
+
+// Claims the same step has passed every time it is asked, a frame by default
+#[derive (Debug, Clone, Copy)]
+pub struct FixedStepClock {
+    step : Duration,
+}
+
+impl FixedStepClock {
+    pub fn new(step: Duration) -> Self {
+        FixedStepClock { step }
+    }
+}
+
+impl Default for FixedStepClock {
+    fn default() -> Self {
+        Self::new(Duration::from_micros(FRAME_TIME as u64))
+    }
+}
+
+impl Clock for FixedStepClock {
+    fn elapsed(&mut self) -> Duration {
+        self.step
+    }
+}
+
+// Only moves when told to, for tests that step time by hand
+#[derive (Debug, Clone, Copy, Default)]
+pub struct ManualClock {
+    pending : Duration,             // Time advanced since elapsed was last called
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advance(&mut self, by: Duration) {
+        // Moves time forward, the next elapsed call reports it
+        self.pending += by;
+    }
+}
+
+impl Clock for ManualClock {
+    fn elapsed(&mut self) -> Duration {
+        core::mem::take(&mut self.pending)
+    }
+}
@@ -0,0 +1,149 @@
+use core::fmt;
+use core::str::FromStr;
+
+use crate::chip::Registers;
+use crate::snapshot::Register;
+
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Register(Register),
+    Value(u16),
+}
+
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+// A comparison between registers and numbers, like "V3 == 0x1F" or
+// "I >= 0x300", that a breakpoint can wait for
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub left: Operand,
+    pub comparison: Comparison,
+    pub right: Operand,
+}
+
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub struct ParseConditionError;
+
+impl fmt::Display for ParseConditionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "expected a condition like V3 == 0x1F")
+    }
+}
+
+impl Operand {
+    fn value(&self, regs: &Registers) -> u16 {
+        match *self {
+            Operand::Register(Register::V(n)) => regs.v[n as usize & 0x0f] as u16,
+            Operand::Register(Register::I) => regs.i,
+            Operand::Register(Register::Pc) => regs.pc,
+            Operand::Register(Register::Sp) => regs.sp as u16,
+            Operand::Register(Register::Dt) => regs.dt as u16,
+            Operand::Register(Register::St) => regs.st as u16,
+            Operand::Value(value) => value,
+        }
+    }
+}
+
+impl FromStr for Operand {
+    type Err = ParseConditionError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        // Register names ignore case, numbers are decimal or 0x prefixed hex
+        let upper = text.to_ascii_uppercase();
+        let register = match upper.as_str() {
+            "I" => Register::I,
+            "PC" => Register::Pc,
+            "SP" => Register::Sp,
+            "DT" => Register::Dt,
+            "ST" => Register::St,
+            _ => {
+                if let Some(n) = upper.strip_prefix('V').filter(|n| n.len() == 1) {
+                    let n = u8::from_str_radix(n, 16).map_err(|_| ParseConditionError)?;
+                    return Ok(Operand::Register(Register::V(n)));
+                }
+                let value = match upper.strip_prefix("0X") {
+                    Some(hex) => u16::from_str_radix(hex, 16),
+                    None => upper.parse(),
+                };
+                return value.map(Operand::Value).map_err(|_| ParseConditionError);
+            }
+        };
+        Ok(Operand::Register(register))
+    }
+}
+
+impl Condition {
+    pub fn holds(&self, regs: &Registers) -> bool {
+        let (left, right) = (self.left.value(regs), self.right.value(regs));
+        match self.comparison {
+            Comparison::Eq => left == right,
+            Comparison::Ne => left != right,
+            Comparison::Lt => left < right,
+            Comparison::Le => left <= right,
+            Comparison::Gt => left > right,
+            Comparison::Ge => left >= right,
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = ParseConditionError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        // Two character operators are tried first so "<=" isn't read as "<"
+        const OPERATORS: [(&str, Comparison); 6] = [
+            ("==", Comparison::Eq),
+            ("!=", Comparison::Ne),
+            ("<=", Comparison::Le),
+            (">=", Comparison::Ge),
+            ("<", Comparison::Lt),
+            (">", Comparison::Gt),
+        ];
+        for (symbol, comparison) in OPERATORS {
+            if let Some((left, right)) = text.split_once(symbol) {
+                return Ok(Condition {
+                    left: left.trim().parse()?,
+                    comparison,
+                    right: right.trim().parse()?,
+                });
+            }
+        }
+        Err(ParseConditionError)
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operand::Register(Register::V(n)) => write!(f, "V{:X}", n),
+            Operand::Register(Register::I) => write!(f, "I"),
+            Operand::Register(Register::Pc) => write!(f, "PC"),
+            Operand::Register(Register::Sp) => write!(f, "SP"),
+            Operand::Register(Register::Dt) => write!(f, "DT"),
+            Operand::Register(Register::St) => write!(f, "ST"),
+            Operand::Value(value) => write!(f, "{:#X}", value),
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self.comparison {
+            Comparison::Eq => "==",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        };
+        write!(f, "{} {} {}", self.left, symbol, self.right)
+    }
+}
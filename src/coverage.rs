@@ -0,0 +1,142 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::analysis::analyze;
+use crate::disasm::labels;
+use crate::instruction::decode_at;
+use crate::symbols::Symbols;
+
+// Rom bytes to a row of the HTML heat view
+const HEAT_ROW: usize = 16;
+
+// What ran of a rom, from a chip's execution counts after a session
+#[derive (Debug, Clone, PartialEq)]
+pub struct Coverage {
+    pub base: usize,
+    pub runs: Vec<u64>,             // Each rom byte, times an instruction covering it ran
+    pub code: Vec<bool>,            // Each rom byte, true if the analyzer found it as code
+}
+
+impl Coverage {
+    pub fn new(rom: &[u8], base: usize, counts: &[u64]) -> Self {
+        // Spreads the count for each instruction over all of its bytes
+        let mut runs = vec![0; rom.len()];
+        for offset in 0..rom.len() {
+            let count = counts.get(base + offset).copied().unwrap_or(0);
+            if count == 0 {
+                continue;
+            }
+            let size = decode_at(rom, offset).1;
+            for run in runs.iter_mut().skip(offset).take(size) {
+                *run = count.max(*run);
+            }
+        }
+        Coverage { base, runs, code: analyze(rom, base).code }
+    }
+
+    pub fn executed(&self) -> usize {
+        self.runs.iter().filter(|&&count| count > 0).count()
+    }
+
+    pub fn unreached(&self) -> Vec<(Range<usize>, bool)> {
+        // Runs of bytes that never ran, as addresses, each either all code
+        // the analyzer found or all not
+        let mut ranges: Vec<(Range<usize>, bool)> = Vec::new();
+        for (offset, &count) in self.runs.iter().enumerate() {
+            if count > 0 {
+                continue;
+            }
+            let addr = self.base + offset;
+            let code = self.code[offset];
+            match ranges.last_mut() {
+                Some((range, was_code)) if range.end == addr && *was_code == code => range.end = addr + 1,
+                _ => ranges.push((addr..addr + 1, code)),
+            }
+        }
+        ranges
+    }
+}
+
+pub fn report(rom: &[u8], coverage: &Coverage, symbols: &Symbols) -> String {
+    // How much of the rom ran, then what didn't: code that was never reached
+    // is dead or an untested branch, anything else is probably data
+    let names = labels(rom, &coverage.code, symbols);
+    let percent = if rom.is_empty() { 0 } else { coverage.executed() * 100 / rom.len() };
+    let mut text = format!("{} of {} rom bytes ran ({}%)\n", coverage.executed(), rom.len(), percent);
+    let unreached = coverage.unreached();
+    if !unreached.is_empty() {
+        text.push_str("never reached:\n");
+    }
+    for (range, code) in unreached {
+        let name = names.name(range.start as u16).unwrap_or("");
+        let kind = if code { "code" } else { "data" };
+        text.push_str(&format!("  0x{:03X}..0x{:03X}  {:>5} bytes  {}  {}\n", range.start, range.end, range.len(), kind, name));
+    }
+    text
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+pub fn html(rom: &[u8], coverage: &Coverage, symbols: &Symbols, title: &str) -> String {
+    // A page showing every rom byte as a cell, redder the more often it ran,
+    // blue where code never ran and grey for other bytes that never ran.
+    // Hovering a cell gives its address, label and count
+    let names = labels(rom, &coverage.code, symbols);
+    let most = coverage.runs.iter().copied().max().unwrap_or(0).max(1);
+    let mut rows = String::new();
+    for (row, bytes) in rom.chunks(HEAT_ROW).enumerate() {
+        let start = row * HEAT_ROW;
+        rows.push_str(&format!("<tr><th>{:03X}</th>", coverage.base + start));
+        for (column, byte) in bytes.iter().enumerate() {
+            let offset = start + column;
+            let addr = coverage.base + offset;
+            let count = coverage.runs[offset];
+            // Counts go from pale to full red on a log scale, so inner loops
+            // don't wash out everything that ran a few times
+            let colour = match count {
+                0 if coverage.code[offset] => String::from("#9cc3e6"),
+                0 => String::from("#ddd"),
+                count => {
+                    let heat = (64 - count.leading_zeros()) * 100 / (64 - most.leading_zeros());
+                    format!("hsl(0, 90%, {}%)", 90 - heat * 45 / 100)
+                }
+            };
+            let name = names.name(addr as u16).map(|name| format!(" {}", escape(name))).unwrap_or_default();
+            rows.push_str(&format!(
+                "<td style=\"background:{}\" title=\"0x{:03X}{} ran {} times\">{:02X}</td>",
+                colour, addr, name, count, byte,
+            ));
+        }
+        rows.push_str("</tr>\n");
+    }
+    format!(
+"<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>Coverage of {title}</title>
+<style>
+body {{ font-family: monospace; }}
+td, th {{ padding: 2px 4px; }}
+th {{ color: #888; font-weight: normal; }}
+</style>
+</head>
+<body>
+<h1>Coverage of {title}</h1>
+<p>{executed} of {len} rom bytes ran. Red ran, darker more often. Blue is code that never ran, grey is anything else that didn't.</p>
+<table>
+{rows}</table>
+</body>
+</html>
+",
+        title = escape(title),
+        executed = coverage.executed(),
+        len = rom.len(),
+        rows = rows,
+    )
+}
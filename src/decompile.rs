@@ -0,0 +1,193 @@
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::analysis::{analyze, Analysis};
+use crate::disasm::labels;
+use crate::instruction::{decode_at, Instruction};
+use crate::symbols::Symbols;
+
+// Times a byte has to be loaded or compared against before it's offered as a :const
+const CONST_USES: usize = 3;
+// Bytes on a line of data that isn't a sprite
+const DATA_ROW: usize = 8;
+
+// Names for everything the source refers to, with the addresses a label can
+// be put on, those being where a statement or a data line starts
+struct Names {
+    labels: Symbols,
+    consts: BTreeMap<u8, String>,
+    placeable: BTreeSet<usize>,
+}
+
+impl Names {
+    fn addr(&self, addr: u16) -> String {
+        match self.labels.name(addr) {
+            Some(name) if self.placeable.contains(&(addr as usize)) => name.to_string(),
+            _ => format!("0x{:03X}", addr),
+        }
+    }
+
+    fn byte(&self, value: u8) -> String {
+        match self.consts.get(&value) {
+            Some(name) => name.clone(),
+            None => format!("0x{:02X}", value),
+        }
+    }
+}
+
+fn statement(instruction: Instruction, names: &Names) -> Option<String> {
+    // The Octo for an instruction. Skips become if/then with the opposite
+    // test, since then skips the next statement when the test fails
+    let text = match instruction {
+        Instruction::Cls => String::from("clear"),
+        Instruction::Ret => String::from("return"),
+        Instruction::Jp { nnn } => format!("jump {}", names.addr(nnn)),
+        Instruction::Call { nnn } => format!(":call {}", names.addr(nnn)),
+        Instruction::SeByte { x, kk } => format!("if v{:x} != {} then", x, names.byte(kk)),
+        Instruction::SneByte { x, kk } => format!("if v{:x} == {} then", x, names.byte(kk)),
+        Instruction::SeReg { x, y } => format!("if v{:x} != v{:x} then", x, y),
+        Instruction::SneReg { x, y } => format!("if v{:x} == v{:x} then", x, y),
+        Instruction::Skp { x } => format!("if v{:x} -key then", x),
+        Instruction::Sknp { x } => format!("if v{:x} key then", x),
+        Instruction::LdByte { x, kk } => format!("v{:x} := {}", x, names.byte(kk)),
+        Instruction::AddByte { x, kk } => format!("v{:x} += {}", x, names.byte(kk)),
+        Instruction::LdReg { x, y } => format!("v{:x} := v{:x}", x, y),
+        Instruction::Or { x, y } => format!("v{:x} |= v{:x}", x, y),
+        Instruction::And { x, y } => format!("v{:x} &= v{:x}", x, y),
+        Instruction::Xor { x, y } => format!("v{:x} ^= v{:x}", x, y),
+        Instruction::AddReg { x, y } => format!("v{:x} += v{:x}", x, y),
+        Instruction::Sub { x, y } => format!("v{:x} -= v{:x}", x, y),
+        Instruction::Shr { x, y } => format!("v{:x} >>= v{:x}", x, y),
+        Instruction::Subn { x, y } => format!("v{:x} =- v{:x}", x, y),
+        Instruction::Shl { x, y } => format!("v{:x} <<= v{:x}", x, y),
+        Instruction::LdI { nnn } => format!("i := {}", names.addr(nnn)),
+        Instruction::JpV0 { nnn } => format!("jump0 {}", names.addr(nnn)),
+        Instruction::Rnd { x, kk } => format!("v{:x} := random 0x{:02X}", x, kk),
+        Instruction::Drw { x, y, n } => format!("sprite v{:x} v{:x} {}", x, y, n),
+        Instruction::LdVxDt { x } => format!("v{:x} := delay", x),
+        Instruction::LdVxK { x } => format!("v{:x} := key", x),
+        Instruction::LdDtVx { x } => format!("delay := v{:x}", x),
+        Instruction::LdStVx { x } => format!("buzzer := v{:x}", x),
+        Instruction::AddI { x } => format!("i += v{:x}", x),
+        Instruction::LdF { x } => format!("i := hex v{:x}", x),
+        Instruction::LdB { x } => format!("bcd v{:x}", x),
+        Instruction::LdMemVx { x } => format!("save v{:x}", x),
+        Instruction::LdVxMem { x } => format!("load v{:x}", x),
+        Instruction::ScrollDown { n } => format!("scroll-down {}", n),
+        Instruction::ScrollRight => String::from("scroll-right"),
+        Instruction::ScrollLeft => String::from("scroll-left"),
+        Instruction::Exit => String::from("exit"),
+        Instruction::Low => String::from("lores"),
+        Instruction::High => String::from("hires"),
+        Instruction::LdHf { x } => format!("i := bighex v{:x}", x),
+        Instruction::LdRVx { x } => format!("saveflags v{:x}", x),
+        Instruction::LdVxR { x } => format!("loadflags v{:x}", x),
+        Instruction::ScrollUp { n } => format!("scroll-up {}", n),
+        Instruction::Save { x, y } => format!("save v{:x} - v{:x}", x, y),
+        Instruction::Load { x, y } => format!("load v{:x} - v{:x}", x, y),
+        Instruction::LdILong { nnnn } => format!("i := long {}", names.addr(nnnn)),
+        Instruction::Plane { n } => format!("plane {}", n),
+        Instruction::Audio => String::from("audio"),
+        Instruction::Pitch { x } => format!("pitch := v{:x}", x),
+        Instruction::Unknown(_) => return None,
+    };
+    Some(text)
+}
+
+fn const_candidates(rom: &[u8], analysis: &Analysis) -> BTreeMap<u8, String> {
+    // Bytes the code loads or compares against often enough to be worth a
+    // name, leaving out 0 and 1 which are rarely anything but themselves
+    let mut uses: BTreeMap<u8, usize> = BTreeMap::new();
+    for &addr in &analysis.instructions {
+        match decode_at(rom, addr as usize - analysis.base).0 {
+            Instruction::SeByte { kk, .. } | Instruction::SneByte { kk, .. } | Instruction::LdByte { kk, .. } => {
+                *uses.entry(kk).or_insert(0) += 1;
+            }
+            _ => {}
+        }
+    }
+    uses.into_iter()
+        .filter(|&(value, count)| value > 1 && count >= CONST_USES)
+        .map(|(value, _)| (value, format!("K_{:02X}", value)))
+        .collect()
+}
+
+pub fn decompile(rom: &[u8], base: usize, symbols: &Symbols) -> String {
+    // Reconstructs Octo source that assembles back to the same rom, with
+    // labels for branch targets and sprites, :const candidates for busy
+    // values, and sprites written out in binary a row to a line. Bytes the
+    // analyzer didn't reach as code come out as data
+    let analysis = analyze(rom, base);
+    let mut names = Names {
+        labels: labels(rom, &analysis.code, symbols),
+        consts: const_candidates(rom, &analysis),
+        placeable: BTreeSet::new(),
+    };
+
+    // Work out where each statement and data line will start, so labels are
+    // only used where they can be defined
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    while offset < rom.len() {
+        let addr = base + offset;
+        if analysis.code[offset] && offset + 1 < rom.len() {
+            let (instruction, size) = decode_at(rom, offset);
+            lines.push((addr, size, Some(instruction)));
+            offset += size;
+            continue;
+        }
+        // Data runs to the next code or label, a sprite being taken whole
+        let limit = match analysis.sprites.get(&(addr as u16)) {
+            Some(&len) => len,
+            None => DATA_ROW,
+        };
+        let mut end = offset + 1;
+        while end < rom.len() && end - offset < limit && !analysis.code[end] && names.labels.name((base + end) as u16).is_none() {
+            end += 1;
+        }
+        lines.push((addr, end - offset, None));
+        offset = end;
+    }
+    names.placeable = lines.iter().map(|&(addr, _, _)| addr).collect();
+
+    let mut source = String::new();
+    source.push_str("# Decompiled by chip8 decompile\n\n");
+    for (value, name) in &names.consts {
+        source.push_str(&format!(":const {} 0x{:02X}\n", name, value));
+    }
+    if !names.consts.is_empty() {
+        source.push('\n');
+    }
+
+    for (addr, size, instruction) in lines {
+        // Octo starts at main, which assembles to nothing extra when it comes first
+        if addr == base {
+            source.push_str(": main\n");
+        }
+        if let Some(name) = names.labels.name(addr as u16).filter(|&name| name != "main") {
+            source.push_str(&format!(": {}\n", name));
+        }
+        let offset = addr - base;
+        let bytes = &rom[offset..offset + size];
+        let text = instruction.and_then(|instruction| statement(instruction, &names));
+        match text {
+            Some(text) => source.push_str(&format!("    {}\n", text)),
+            None if analysis.sprites.contains_key(&(addr as u16)) => {
+                // Sixteen pixel wide sprites take two bytes a row
+                let row = if bytes.len() == 32 { 2 } else { 1 };
+                source.push_str(&format!("    # sprite at 0x{:03X}\n", addr));
+                for pixels in bytes.chunks(row) {
+                    let pixels: Vec<String> = pixels.iter().map(|byte| format!("0b{:08b}", byte)).collect();
+                    source.push_str(&format!("    {}\n", pixels.join(" ")));
+                }
+            }
+            None => {
+                let bytes: Vec<String> = bytes.iter().map(|byte| format!("0x{:02X}", byte)).collect();
+                source.push_str(&format!("    {}\n", bytes.join(" ")));
+            }
+        }
+    }
+    source
+}
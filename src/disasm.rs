@@ -0,0 +1,337 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::analysis::analyze;
+use crate::chip::Variant;
+use crate::instruction::{decode_at, Instruction};
+use crate::symbols::Symbols;
+
+use crate::{OPCODE_SIZE, ROM_SIZE};
+
+// Unchanged instructions shown either side of a change in a diff
+const DIFF_CONTEXT: usize = 3;
+// Instructions either side of the first and last change past which a diff
+// stops lining them up and shows the whole stretch as changed
+const DIFF_LIMIT: usize = 4096;
+
+fn word_at(rom: &[u8], offset: usize) -> u16 {
+    // Reads a big endian word, padding past the end of the rom with zero
+    let hi = rom.get(offset).copied().unwrap_or(0) as u16;
+    let lo = rom.get(offset + 1).copied().unwrap_or(0) as u16;
+    hi << 8 | lo
+}
+
+pub fn disassemble(rom: &[u8]) -> String {
+    // Disassembles a rom into one line per instruction: address, opcode and assembly
+    let mut listing = String::new();
+    let mut offset = 0;
+    while offset < rom.len() {
+        let (instruction, size) = decode_at(rom, offset);
+        let words: Vec<String> = (offset..offset + size)
+            .step_by(2)
+            .map(|at| format!("{:04X}", word_at(rom, at)))
+            .collect();
+        listing.push_str(&format!("{:03X}: {}  {}\n", ROM_SIZE + offset, words.join(" "), instruction));
+        offset += size;
+    }
+    listing
+}
+
+pub fn disassemble_json(rom: &[u8]) -> String {
+    // Disassembles a rom into a JSON array with one object per instruction
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while offset < rom.len() {
+        let opcode = word_at(rom, offset);
+        let (instruction, size) = decode_at(rom, offset);
+        let operands: Vec<String> = instruction.operands()
+            .iter()
+            .map(|(name, value)| format!("\"{}\": {}", name, value))
+            .collect();
+
+        entries.push(format!(
+            "{{ \"addr\": {}, \"opcode\": \"0x{:04X}\", \"mnemonic\": \"{}\", \"operands\": {{ {} }} }}",
+            ROM_SIZE + offset,
+            opcode,
+            instruction.mnemonic(),
+            operands.join(", "),
+        ));
+        offset += size;
+    }
+    format!("[{}]", entries.join(", "))
+}
+
+pub fn labels(rom: &[u8], code: &[bool], symbols: &Symbols) -> Symbols {
+    // Names the places code branches to that symbols doesn't, sub_ for
+    // calls, loop_ for jumps and data_ for what I is pointed at
+    let mut symbols = symbols.clone();
+    let mut offset = 0;
+    while offset + 1 < rom.len() {
+        if !code[offset] {
+            offset += 1;
+            continue;
+        }
+        let (instruction, size) = decode_at(rom, offset);
+        match instruction {
+            Instruction::Call { nnn } => symbols.insert(&format!("sub_{:03X}", nnn), nnn),
+            Instruction::Jp { nnn } | Instruction::JpV0 { nnn } => symbols.insert(&format!("loop_{:03X}", nnn), nnn),
+            Instruction::LdI { nnn } => symbols.insert(&format!("data_{:03X}", nnn), nnn),
+            Instruction::LdILong { nnnn } => symbols.insert(&format!("data_{:04X}", nnnn), nnnn),
+            _ => {}
+        }
+        offset += size;
+    }
+    symbols
+}
+
+pub fn annotated(rom: &[u8], base: usize, symbols: &Symbols) -> String {
+    // A listing with labels on their own lines, branch targets by name and
+    // bytes that no path reaches shown as data, eight to a line, picking out
+    // those drawn as sprites
+    annotated_for(rom, base, symbols, Variant::XoChip)
+}
+
+pub fn annotated_for(rom: &[u8], base: usize, symbols: &Symbols, variant: Variant) -> String {
+    // Like annotated, but flagging instructions the variant doesn't have
+    let analysis = analyze(rom, base);
+    let code = &analysis.code;
+    let names = labels(rom, code, symbols);
+    let mut listing = String::new();
+    let mut offset = 0;
+    while offset < rom.len() {
+        let addr = base + offset;
+        if let Some(name) = names.name(addr as u16) {
+            listing.push_str(&format!("{}:\n", name));
+        }
+        if code[offset] {
+            let (instruction, size) = decode_at(rom, offset);
+            let supported = variant.supports(&instruction);
+            // Only XO-CHIP has the long F000 NNNN, everywhere else it's one word
+            let size = if supported { size } else { OPCODE_SIZE };
+            let words: Vec<String> = (offset..offset + size)
+                .step_by(2)
+                .map(|at| format!("{:04X}", word_at(rom, at)))
+                .collect();
+            let mut line = format!("{:03X}: {:<10} {}", addr, words.join(" "), names.annotate(&instruction));
+            if !supported && !matches!(instruction, Instruction::Unknown(_)) {
+                line.push_str(&format!("  ; not in {}", variant.name()));
+            }
+            listing.push_str(&line);
+            listing.push('\n');
+            offset += size;
+        } else {
+            // Data runs to the next code, label or eight bytes
+            let mut end = offset + 1;
+            while end < rom.len() && end - offset < 8 && !code[end] && names.name((base + end) as u16).is_none() {
+                end += 1;
+            }
+            let bytes: Vec<String> = rom[offset..end].iter().map(|byte| format!("{:02X}", byte)).collect();
+            let kind = if analysis.is_sprite(addr) { "sprite" } else { "data" };
+            listing.push_str(&format!("{:03X}: {:<10} ; {}\n", addr, bytes.join(" "), kind));
+            offset = end;
+        }
+    }
+    listing
+}
+
+// An instruction in a rom being diffed: its address, opcode words and assembly
+struct DiffLine {
+    addr: usize,
+    words: String,
+    text: String,
+}
+
+// How an instruction in one rom relates to the other
+enum Edit {
+    Same(usize),
+    Removed(usize),
+    Added(usize),
+}
+
+fn diff_lines(rom: &[u8], base: usize) -> Vec<DiffLine> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    while offset < rom.len() {
+        let (instruction, size) = decode_at(rom, offset);
+        let words: Vec<String> = (offset..offset + size)
+            .step_by(2)
+            .map(|at| format!("{:04X}", word_at(rom, at)))
+            .collect();
+        lines.push(DiffLine { addr: base + offset, words: words.join(" "), text: format!("{}", instruction) });
+        offset += size;
+    }
+    lines
+}
+
+fn edits(a: &[DiffLine], b: &[DiffLine]) -> Vec<Edit> {
+    // Matches the longest common run of instructions, trimming the common
+    // start and end first since patches rarely touch much of a rom
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x.text == y.text).count();
+    let suffix = a[prefix..].iter().rev().zip(b[prefix..].iter().rev()).take_while(|(x, y)| x.text == y.text).count();
+    let (a_mid, b_mid) = (prefix..a.len() - suffix, prefix..b.len() - suffix);
+
+    let mut edits: Vec<Edit> = (0..prefix).map(Edit::Same).collect();
+    if a_mid.len() > DIFF_LIMIT || b_mid.len() > DIFF_LIMIT {
+        edits.extend(a_mid.clone().map(Edit::Removed));
+        edits.extend(b_mid.clone().map(Edit::Added));
+    } else {
+        // lcs[i][j] is the longest common run of a_mid[i..] and b_mid[j..]
+        let (n, m) = (a_mid.len(), b_mid.len());
+        let mut lcs = vec![vec![0u16; m + 1]; n + 1];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i][j] = if a[a_mid.start + i].text == b[b_mid.start + j].text {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && a[a_mid.start + i].text == b[b_mid.start + j].text {
+                edits.push(Edit::Same(a_mid.start + i));
+                i += 1;
+                j += 1;
+            } else if j == m || (i < n && lcs[i + 1][j] >= lcs[i][j + 1]) {
+                edits.push(Edit::Removed(a_mid.start + i));
+                i += 1;
+            } else {
+                edits.push(Edit::Added(b_mid.start + j));
+                j += 1;
+            }
+        }
+    }
+    edits.extend((0..suffix).map(|n| Edit::Same(a_mid.end + n)));
+    edits
+}
+
+pub fn diff(a: &[u8], b: &[u8], base: usize) -> String {
+    // Compares two roms instruction by instruction, so code that moved still
+    // lines up. Instructions only in a start with -, only in b with +, and a
+    // few unchanged ones are kept around each change. Empty if they match
+    let (a, b) = (diff_lines(a, base), diff_lines(b, base));
+    let edits = edits(&a, &b);
+    let changed: Vec<usize> = edits.iter().enumerate()
+        .filter(|(_, edit)| !matches!(edit, Edit::Same(..)))
+        .map(|(n, _)| n)
+        .collect();
+
+    let mut listing = String::new();
+    let mut last_shown = None;
+    for (n, edit) in edits.iter().enumerate() {
+        let near = changed.iter().any(|&change| change.abs_diff(n) <= DIFF_CONTEXT);
+        if !near {
+            continue;
+        }
+        if last_shown.map_or(n > 0, |last| last + 1 < n) {
+            listing.push_str("...\n");
+        }
+        last_shown = Some(n);
+        let (sign, line) = match *edit {
+            Edit::Same(i) => (' ', &a[i]),
+            Edit::Removed(i) => ('-', &a[i]),
+            Edit::Added(j) => ('+', &b[j]),
+        };
+        listing.push_str(&format!("{} {:03X}: {:<10} {}\n", sign, line.addr, line.words, line.text));
+    }
+    if last_shown.is_some_and(|last| last + 1 < edits.len()) {
+        listing.push_str("...\n");
+    }
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::iter::Peekable;
+    use core::str::Chars;
+
+    // Just enough JSON to read the listing back
+    #[derive (Debug, PartialEq)]
+    enum Json {
+        Number(i64),
+        Text(String),
+        Array(Vec<Json>),
+        Object(Vec<(String, Json)>),
+    }
+
+    fn parse(chars: &mut Peekable<Chars>) -> Json {
+        let skip = |chars: &mut Peekable<Chars>| while chars.next_if(|c| c.is_whitespace()).is_some() {};
+        skip(chars);
+        let value = match chars.next() {
+            Some('"') => Json::Text(chars.by_ref().take_while(|&c| c != '"').collect()),
+            Some('[') => {
+                let mut items = Vec::new();
+                skip(chars);
+                while chars.next_if_eq(&']').is_none() {
+                    items.push(parse(chars));
+                    chars.next_if_eq(&',');
+                    skip(chars);
+                }
+                Json::Array(items)
+            }
+            Some('{') => {
+                let mut fields = Vec::new();
+                skip(chars);
+                while chars.next_if_eq(&'}').is_none() {
+                    let name = match parse(chars) {
+                        Json::Text(name) => name,
+                        other => panic!("field name {:?}", other),
+                    };
+                    skip(chars);
+                    assert_eq!(chars.next(), Some(':'));
+                    fields.push((name, parse(chars)));
+                    chars.next_if_eq(&',');
+                    skip(chars);
+                }
+                Json::Object(fields)
+            }
+            Some(c) if c.is_ascii_digit() => {
+                let mut digits = String::from(c);
+                while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+                    digits.push(c);
+                }
+                Json::Number(digits.parse().unwrap())
+            }
+            other => panic!("unexpected {:?}", other),
+        };
+        skip(chars);
+        value
+    }
+
+    #[test]
+    fn json_listing_parses_back() {
+        let json = disassemble_json(&[0x00, 0xE0, 0x6A, 0x2B, 0xF0, 0x00, 0x12, 0x34]);
+        let mut chars = json.chars().peekable();
+        let entries = match parse(&mut chars) {
+            Json::Array(entries) => entries,
+            other => panic!("not an array: {:?}", other),
+        };
+        assert_eq!(chars.next(), None);
+        assert_eq!(entries.len(), 3);
+        let entry = |addr: i64, opcode: &str, mnemonic: &str, operands: Vec<(&str, i64)>| Json::Object(vec![
+            (String::from("addr"), Json::Number(addr)),
+            (String::from("opcode"), Json::Text(String::from(opcode))),
+            (String::from("mnemonic"), Json::Text(String::from(mnemonic))),
+            (String::from("operands"), Json::Object(operands.into_iter().map(|(name, value)| (String::from(name), Json::Number(value))).collect())),
+        ]);
+        assert_eq!(entries[0], entry(512, "0x00E0", "CLS", vec![]));
+        assert_eq!(entries[1], entry(514, "0x6A2B", "LD", vec![("x", 10), ("kk", 43)]));
+        assert_eq!(entries[2], entry(516, "0xF000", "LD", vec![("nnnn", 0x1234)]));
+    }
+
+    #[test]
+    fn listing_flags_instructions_the_variant_lacks() {
+        // HIGH, CLS, then the XO-CHIP long load of I
+        let rom = [0x00, 0xFF, 0x00, 0xE0, 0xF0, 0x00, 0x12, 0x34];
+        let listing = annotated_for(&rom, ROM_SIZE, &Symbols::new(), Variant::Chip8);
+        let flagged: Vec<&str> = listing.lines().filter(|line| line.ends_with("; not in CHIP-8")).collect();
+        assert_eq!(flagged.len(), 2);
+        assert!(flagged[0].starts_with("200: 00FF"));
+        assert!(flagged[1].starts_with("204: F000 "));
+        assert!(!annotated_for(&rom, ROM_SIZE, &Symbols::new(), Variant::XoChip).contains("; not in"));
+    }
+}
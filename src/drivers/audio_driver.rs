@@ -1,13 +1,14 @@
-use sdl2;
 use sdl2::audio::{AudioDevice, AudioCallback, AudioSpecDesired};
 
+use crate::drivers::AudioBackend;
+
 pub struct AudioDriver {
     device: AudioDevice<SquareWave>,
 }
 
 impl AudioDriver {
-    pub fn new(sdl_context: &sdl2::Sdl) -> Self {
-        let audio_subsystem = sdl_context.audio().unwrap();
+    pub fn new(sdl_context: &sdl2::Sdl) -> Result<Self, String> {
+        let audio_subsystem = sdl_context.audio()?;
 
         let desired_spec = AudioSpecDesired {
             freq: Some(44100),
@@ -23,21 +24,68 @@ impl AudioDriver {
                 phase: 0.0,
                 volume: 0.25,
             }
-        })
-        .unwrap();
+        })?;
 
-        AudioDriver { device: device }
+        Ok(AudioDriver { device })
     }
+}
 
-    pub fn start_beep(&self) {
+impl AudioBackend for AudioDriver {
+    fn start_beep(&self) {
         self.device.resume();
     }
 
-    pub fn stop_beep(&self) {
+    fn stop_beep(&self) {
         self.device.pause();
     }
 }
 
+pub struct NullAudio;
+
+impl AudioBackend for NullAudio {
+    fn start_beep(&self) {}
+
+    fn stop_beep(&self) {}
+}
+
+pub enum Audio {
+    Sdl(AudioDriver),
+    Null(NullAudio),
+}
+
+impl Audio {
+    pub fn new(sdl_context: &sdl2::Sdl) -> Self {
+        // Opens the SDL audio device, falling back to silence if there isn't one
+        Self::from_result(AudioDriver::new(sdl_context))
+    }
+
+    pub fn from_result(driver: Result<AudioDriver, String>) -> Self {
+        match driver {
+            Ok(driver) => Audio::Sdl(driver),
+            Err(e) => {
+                eprintln!("warning: audio unavailable, running without sound: {}", e);
+                Audio::Null(NullAudio)
+            }
+        }
+    }
+}
+
+impl AudioBackend for Audio {
+    fn start_beep(&self) {
+        match self {
+            Audio::Sdl(driver) => driver.start_beep(),
+            Audio::Null(null) => null.start_beep(),
+        }
+    }
+
+    fn stop_beep(&self) {
+        match self {
+            Audio::Sdl(driver) => driver.stop_beep(),
+            Audio::Null(null) => null.stop_beep(),
+        }
+    }
+}
+
 struct SquareWave {
     phase_inc: f32,
     phase: f32,
@@ -55,3 +103,47 @@ impl AudioCallback for SquareWave {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::{Chip, FixedStepClock};
+    use crate::drivers::{DisplayBackend, GameDriver, InputBackend};
+
+    // Quits after a few polls, so a run ends by itself
+    struct Frames(usize);
+
+    impl InputBackend for Frames {
+        fn poll(&mut self) -> [bool; 16] {
+            self.0 = self.0.saturating_sub(1);
+            [false; 16]
+        }
+
+        fn quit(&self) -> bool {
+            self.0 == 0
+        }
+    }
+
+    struct CountingDisplay(usize);
+
+    impl DisplayBackend for CountingDisplay {
+        fn draw(&mut self, _pixels: &[u8], _width: usize, _height: usize) {
+            self.0 += 1;
+        }
+    }
+
+    #[test]
+    fn runs_silently_without_an_audio_device() {
+        let audio = Audio::from_result(Err(String::from("no audio device")));
+        assert!(matches!(audio, Audio::Null(_)));
+
+        // V0 = 0, I = font 0, draw it, then stop
+        let mut chip = Chip::new();
+        chip.load_rom(&[0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06]).unwrap();
+        let mut driver = GameDriver::new(chip, vec![Box::new(Frames(3))]);
+        let mut display = CountingDisplay(0);
+        driver.run(&mut display, &audio, &mut FixedStepClock::default()).unwrap();
+        assert!(display.0 > 0);
+        assert_eq!(driver.chip().pc(), 0x206);
+    }
+}
@@ -0,0 +1,516 @@
+use std::cmp::Reverse;
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use chip8::condition::Operand;
+use chip8::snapshot::Register;
+use chip8::{decode_at, Access, Chip, Condition, DrawBreak, Error, FrameOutput, Symbols};
+
+use crate::drivers::Debugger;
+use crate::parse_address;
+
+const DISASM_LINES: usize = 8;
+const MEM_BYTES: usize = 64;
+
+// Debugger commands read from stdin. Lines are read on their own thread so
+// the window keeps drawing and taking events while waiting for input
+pub struct DebugConsole {
+    commands: Receiver<String>,
+    paused: bool,
+    quit: bool,
+    until: Option<Until>,
+    symbols: Symbols,               // Labels accepted in place of addresses and shown with them
+}
+
+// Where frame and draw resume to, checked after each frame
+#[derive (Debug, Clone, Copy, PartialEq)]
+enum Until {
+    Frame,
+    Draw,
+}
+
+impl DebugConsole {
+    pub fn new(symbols: Symbols) -> Self {
+        // Starts paused before the first instruction
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                match line {
+                    Ok(line) => if sender.send(line).is_err() { break },
+                    Err(_) => break,
+                }
+            }
+        });
+        println!("paused, type help for commands");
+        prompt();
+        DebugConsole {
+            commands: receiver,
+            paused: true,
+            quit: false,
+            until: None,
+            symbols,
+        }
+    }
+
+    fn command(&mut self, chip: &mut Chip, line: &str) {
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => return,
+        };
+        if command == "b" || command == "break" {
+            return self.set_breakpoint(chip, line);
+        }
+        if command == "set" {
+            return self.set_value(chip, line);
+        }
+        if command == "catch" {
+            return catch_sprites(chip, words.next());
+        }
+        let args: Vec<usize> = match words.map(|word| self.address(word)).collect() {
+            Some(args) => args,
+            None => {
+                println!("expected labels or decimal or 0x prefixed numbers");
+                return;
+            }
+        };
+
+        match command {
+            "s" | "step" => {
+                for _ in 0..args.first().copied().unwrap_or(1) {
+                    match chip.step() {
+                        Ok(info) => println!("{:#05X}  {:04X}  {}", info.pc_before, info.opcode, self.symbols.annotate(&info.instruction)),
+                        Err(e) => {
+                            println!("error: {}", e);
+                            break;
+                        }
+                    }
+                }
+            }
+            "rs" | "back" => {
+                let mut undone = 0;
+                while undone < args.first().copied().unwrap_or(1) && chip.step_back() {
+                    undone += 1;
+                }
+                if undone == 0 {
+                    println!("no history to step back through");
+                } else {
+                    print_disassembly(chip, &self.symbols, chip.pc() as usize, 1);
+                }
+            }
+            "n" | "next" => run_and_show(chip, &self.symbols, Chip::step_over),
+            "f" | "finish" => run_and_show(chip, &self.symbols, Chip::step_out),
+            "c" | "continue" => self.paused = false,
+            "frame" | "draw" => {
+                self.until = Some(if command == "frame" { Until::Frame } else { Until::Draw });
+                self.paused = false;
+            }
+            "r" | "regs" => print_registers(chip),
+            "bt" | "backtrace" => print_call_stack(chip, &self.symbols),
+            "hist" | "history" => print_history(chip, &self.symbols),
+            "cov" | "coverage" => print_coverage(chip),
+            "m" | "mem" => {
+                let start = args.first().copied().unwrap_or(chip.registers().i as usize);
+                let len = args.get(1).copied().unwrap_or(MEM_BYTES);
+                print_memory(chip, start, len);
+            }
+            "d" | "disasm" => {
+                let start = args.first().copied().unwrap_or(chip.pc() as usize);
+                let lines = args.get(1).copied().unwrap_or(DISASM_LINES);
+                print_disassembly(chip, &self.symbols, start, lines);
+            }
+            "delete" => match args.first() {
+                Some(&addr) => {
+                    if let Some(addr) = address_u16(addr) {
+                        if !chip.remove_breakpoint(addr) {
+                            println!("no breakpoint at {:#05X}", addr);
+                        }
+                    }
+                }
+                None => chip.clear_breakpoints(),
+            },
+            "watch" | "rwatch" | "awatch" => match args.first() {
+                Some(&addr) => {
+                    let access = match command {
+                        "watch" => Access::Write,
+                        "rwatch" => Access::Read,
+                        _ => Access::ReadWrite,
+                    };
+                    chip.add_watchpoint(addr, addr + args.get(1).copied().unwrap_or(1), access);
+                }
+                None => print_watchpoints(chip),
+            },
+            "unwatch" => match args.first() {
+                Some(&addr) => {
+                    if !chip.remove_watchpoint(addr, addr + args.get(1).copied().unwrap_or(1)) {
+                        println!("no watchpoint on {:#05X}", addr);
+                    }
+                }
+                None => chip.clear_watchpoints(),
+            },
+            "q" | "quit" => self.quit = true,
+            "h" | "help" => {
+                println!("step [n]             run n instructions, 1 by default");
+                println!("back [n]             undo n instructions, 1 by default");
+                println!("next                 step, running a call through to its return");
+                println!("finish               run until the current subroutine returns");
+                println!("continue             run until a breakpoint");
+                println!("frame                run to the end of the next frame");
+                println!("draw                 run until a frame changes the display");
+                println!("regs                 show the registers");
+                println!("backtrace            show the calls that led here");
+                println!("history              show the last instructions run");
+                println!("coverage             show which code has run and how often each opcode has");
+                println!("mem [addr] [len]     dump memory, from I by default");
+                println!("disasm [addr] [n]    disassemble n instructions, from the PC by default");
+                println!("set reg value        change a register, like set V4 0x20");
+                println!("set mem addr bytes   write bytes to memory from addr");
+                println!("break [addr]         stop before the instruction at addr, or list breakpoints");
+                println!("break addr if cond   stop only when a condition like V3 == 0x1F holds");
+                println!("delete [addr]        remove the breakpoint at addr, or all of them");
+                println!("watch addr [len]     stop after a write to len bytes from addr");
+                println!("rwatch addr [len]    stop after a read");
+                println!("awatch addr [len]    stop after a read or a write");
+                println!("unwatch [addr] [len] remove a watchpoint, or all of them");
+                println!("catch draw|collision stop after every sprite drawn, or only ones that collide");
+                println!("catch off            stop catching sprites");
+                println!("quit                 exit the emulator");
+            }
+            _ => println!("unknown command {}, type help for a list", command),
+        }
+    }
+
+    fn address(&self, word: &str) -> Option<usize> {
+        // A number, or a label from the symbol file
+        parse_address(word).or_else(|| self.symbols.address(word).map(usize::from))
+    }
+
+    fn set_breakpoint(&mut self, chip: &mut Chip, line: &str) {
+        // break [addr [if condition]], the condition being everything after "if"
+        let mut words = line.split_whitespace().skip(1);
+        let addr = match words.next().map(|word| self.address(word)) {
+            Some(Some(addr)) => match address_u16(addr) {
+                Some(addr) => addr,
+                None => return,
+            },
+            Some(None) => {
+                println!("expected a label or decimal or 0x prefixed numbers");
+                return;
+            }
+            None => {
+                print_breakpoints(chip, &self.symbols);
+                return;
+            }
+        };
+        match words.next() {
+            Some("if") => {
+                let condition: Condition = match words.collect::<Vec<_>>().join(" ").parse() {
+                    Ok(condition) => condition,
+                    Err(e) => {
+                        println!("{}", e);
+                        return;
+                    }
+                };
+                chip.add_conditional_breakpoint(addr, condition);
+                println!("breakpoint at {:#05X} if {}", addr, condition);
+            }
+            Some(_) => println!("expected if after the address"),
+            None => {
+                chip.add_breakpoint(addr);
+                println!("breakpoint at {:#05X}", addr);
+            }
+        }
+    }
+
+    fn set_value(&mut self, chip: &mut Chip, line: &str) {
+        // set mem addr byte.. or set register value, only while paused so the
+        // program can't overwrite the change before it is looked at
+        if !self.paused {
+            println!("pause before changing anything");
+            return;
+        }
+        let words: Vec<&str> = line.split_whitespace().skip(1).collect();
+        let values: Option<Vec<usize>> = words.iter().skip(1).map(|word| self.address(word)).collect();
+        let values = match values {
+            Some(values) if !values.is_empty() => values,
+            _ => {
+                println!("expected labels or decimal or 0x prefixed numbers after {}", words.first().unwrap_or(&"set"));
+                return;
+            }
+        };
+        if words[0] == "mem" {
+            let bytes: Option<Vec<u8>> = values[1..].iter().map(|&value| u8::try_from(value).ok()).collect();
+            let addr = match address_u16(values[0]) {
+                Some(addr) => addr,
+                None => return,
+            };
+            match bytes {
+                Some(bytes) if !bytes.is_empty() => match chip.write_range(addr, &bytes) {
+                    Ok(()) => print_memory(chip, values[0], bytes.len()),
+                    Err(e) => println!("error: {}", e),
+                },
+                _ => println!("expected an address then byte values"),
+            }
+            return;
+        }
+        match words[0].parse::<Operand>() {
+            Ok(Operand::Register(register)) => set_register(chip, register, values[0]),
+            _ => println!("unknown register {}, try V0-VF, I, PC, SP, DT or ST", words[0]),
+        }
+    }
+}
+
+fn catch_sprites(chip: &mut Chip, what: Option<&str>) {
+    // catch draw, collision or off, or on its own shows the current setting
+    let draw_break = match what {
+        Some("draw") => DrawBreak::Draw,
+        Some("collision") => DrawBreak::Collision,
+        Some("off") => DrawBreak::Off,
+        None => chip.draw_break(),
+        Some(what) => {
+            println!("can't catch {}, try draw, collision or off", what);
+            return;
+        }
+    };
+    chip.set_draw_break(draw_break);
+    println!("catching sprites: {:?}", draw_break);
+}
+
+fn set_register(chip: &mut Chip, register: Register, value: usize) {
+    // Registers go through set_registers, so SP stays within the stack
+    let mut regs = chip.registers();
+    let wide = matches!(register, Register::I | Register::Pc);
+    if value > if wide { 0xFFFF } else { 0xFF } {
+        println!("{:#X} doesn't fit in the register", value);
+        return;
+    }
+    match register {
+        Register::V(n) => regs.v[n as usize] = value as u8,
+        Register::I => regs.i = value as u16,
+        Register::Pc => regs.pc = value as u16,
+        Register::Sp => regs.sp = value as u8,
+        Register::Dt => regs.dt = value as u8,
+        Register::St => regs.st = value as u8,
+    }
+    chip.set_registers(regs);
+    print_registers(chip);
+}
+
+fn address_u16(addr: usize) -> Option<u16> {
+    // Addresses typed at the console are checked rather than wrapped into range
+    let addr = u16::try_from(addr).ok();
+    if addr.is_none() {
+        println!("addresses only go up to 0xFFFF");
+    }
+    addr
+}
+
+impl Debugger for DebugConsole {
+    fn poll(&mut self, chip: &mut Chip) {
+        // Runs every command typed since the last poll
+        loop {
+            match self.commands.try_recv() {
+                Ok(line) => {
+                    self.command(chip, &line);
+                    if self.paused {
+                        prompt();
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    // Stdin closed, so nothing could ever resume a paused chip
+                    self.paused = false;
+                    break;
+                }
+            }
+        }
+    }
+
+    fn paused(&self) -> bool {
+        self.paused
+    }
+
+    fn quit(&self) -> bool {
+        self.quit
+    }
+
+    fn frame_done(&mut self, chip: &Chip, output: &FrameOutput) {
+        // Pauses and says why when the chip stopped on a breakpoint, watchpoint or sprite
+        if let Some(pc) = output.breakpoint {
+            println!("breakpoint at {}", describe(&self.symbols, pc));
+        } else if let Some(hit) = output.watchpoint {
+            let access = if hit.write { "wrote" } else { "read" };
+            println!("{:#05X} {} {:#05X}", hit.pc, access, hit.addr);
+        } else if let Some(pc) = output.draw_break {
+            let collided = if chip.registers().v[0xf] != 0 { " with a collision" } else { "" };
+            println!("{:#05X} drew{}", pc, collided);
+        } else if self.until == Some(Until::Frame) || (self.until == Some(Until::Draw) && output.display_changed) {
+            println!("frame done, {} instructions run", output.instructions_executed);
+        } else {
+            return;
+        }
+        self.until = None;
+        self.paused = true;
+        prompt();
+    }
+}
+
+fn run_and_show<'rom>(chip: &mut Chip<'rom>, symbols: &Symbols, run: fn(&mut Chip<'rom>) -> Result<(), Error>) {
+    // Runs a stepping command, then shows where it stopped
+    match run(chip) {
+        Ok(()) => print_disassembly(chip, symbols, chip.pc() as usize, 1),
+        Err(e) => println!("error: {}", e),
+    }
+}
+
+fn prompt() {
+    print!("(chip8) ");
+    let _ = io::stdout().flush();
+}
+
+fn print_registers(chip: &Chip) {
+    let regs = chip.registers();
+    for (n, v) in regs.v.iter().enumerate() {
+        print!("V{:X}={:02X}{}", n, v, if n % 8 == 7 { "\n" } else { " " });
+    }
+    println!("I={:03X} PC={:03X} SP={} DT={} ST={}", regs.i, regs.pc, regs.sp, regs.dt, regs.st);
+}
+
+fn describe(symbols: &Symbols, addr: u16) -> String {
+    // An address with the label it falls under, if there is one
+    match symbols.describe(addr) {
+        Some(label) => format!("{:#05X} ({})", addr, label),
+        None => format!("{:#05X}", addr),
+    }
+}
+
+pub fn print_call_stack(chip: &Chip, symbols: &Symbols) {
+    // Innermost call first, like a debugger backtrace
+    println!("#0  {}", describe(symbols, chip.pc()));
+    for (depth, frame) in chip.call_stack().iter().rev().enumerate() {
+        println!("#{}  {}  called {}", depth + 1, describe(symbols, frame.call_site), describe(symbols, frame.target));
+    }
+}
+
+fn print_coverage(chip: &Chip) {
+    // Runs of instructions executed one after another, so a skipped or
+    // never taken branch shows up as a gap between runs
+    let counts = chip.execution_counts();
+    let mut run: Option<(usize, usize, u64)> = None;     // First, last and fewest executions
+    for (addr, &count) in counts.iter().enumerate().filter(|(_, &count)| count > 0) {
+        run = match run {
+            Some((first, last, fewest)) if addr == last + instruction_size(chip, last) => Some((first, addr, fewest.min(count))),
+            Some((first, last, fewest)) => {
+                println!("{:#05X}..{:#05X}  ran at least {} times", first, last, fewest);
+                Some((addr, addr, count))
+            }
+            None => Some((addr, addr, count)),
+        };
+    }
+    match run {
+        Some((first, last, fewest)) => println!("{:#05X}..{:#05X}  ran at least {} times", first, last, fewest),
+        None => println!("nothing has run with coverage on"),
+    }
+
+    let mut opcodes = chip.opcode_counts();
+    opcodes.sort_by_key(|&(_, _, count)| Reverse(count));
+    for (pattern, mnemonic, count) in opcodes {
+        println!("{:04X}  {:<14}  {}", pattern, mnemonic, count);
+    }
+}
+
+fn instruction_size(chip: &Chip, addr: usize) -> usize {
+    // Only the XO-CHIP F000 long load is longer than two bytes
+    match u16::try_from(addr).map(|addr| chip.read_range(addr, 2)) {
+        Ok(Ok(bytes)) if bytes == [0xF0, 0x00] => 4,
+        _ => 2,
+    }
+}
+
+pub fn print_history(chip: &Chip, symbols: &Symbols) {
+    // Oldest first, so the last line is the instruction that just ran or failed
+    for entry in chip.history() {
+        if let Some(name) = symbols.name(entry.pc) {
+            println!("{}:", name);
+        }
+        println!("{}", entry);
+    }
+}
+
+fn print_breakpoints(chip: &Chip, symbols: &Symbols) {
+    if chip.breakpoints().is_empty() {
+        println!("no breakpoints");
+    }
+    for breakpoint in chip.breakpoints() {
+        match breakpoint.condition {
+            Some(condition) => println!("breakpoint at {} if {}", describe(symbols, breakpoint.addr), condition),
+            None => println!("breakpoint at {}", describe(symbols, breakpoint.addr)),
+        }
+    }
+}
+
+fn print_watchpoints(chip: &Chip) {
+    if chip.watchpoints().is_empty() {
+        println!("no watchpoints");
+    }
+    for watch in chip.watchpoints() {
+        println!("{:?} watchpoint on {:#05X}..{:#05X}", watch.access, watch.start, watch.end);
+    }
+}
+
+fn print_memory(chip: &Chip, start: usize, len: usize) {
+    // Sixteen bytes to a line, stopping at the end of memory
+    let addr = match address_u16(start) {
+        Some(addr) => addr,
+        None => return,
+    };
+    let len = len.min(chip.memory_size().saturating_sub(start));
+    let bytes = match chip.read_range(addr, len) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            println!("error: {}", e);
+            return;
+        }
+    };
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02X}", byte)).collect();
+        println!("{:#05X}  {}", start + line * 16, hex.join(" "));
+    }
+}
+
+fn print_disassembly(chip: &Chip, symbols: &Symbols, start: usize, lines: usize) {
+    for line in disassemble(chip, symbols, start, lines) {
+        println!("{}", line);
+    }
+}
+
+pub fn disassemble(chip: &Chip, symbols: &Symbols, start: usize, lines: usize) -> Vec<String> {
+    // One line per instruction with a > on the PC, and a line for each label.
+    // Long XO-CHIP instructions take four bytes, so read a little extra
+    let addr = match u16::try_from(start) {
+        Ok(addr) => addr,
+        Err(_) => return vec![String::from("addresses only go up to 0xFFFF")],
+    };
+    let len = (lines * 4).min(chip.memory_size().saturating_sub(start));
+    let bytes = match chip.read_range(addr, len) {
+        Ok(bytes) => bytes,
+        Err(e) => return vec![format!("error: {}", e)],
+    };
+    let mut text = Vec::new();
+    let mut offset = 0;
+    for _ in 0..lines {
+        if offset + 1 >= bytes.len() {
+            break;
+        }
+        let (instruction, size) = decode_at(&bytes, offset);
+        let marker = if start + offset == chip.pc() as usize { ">" } else { " " };
+        if let Some(name) = symbols.name((start + offset) as u16) {
+            text.push(format!("{}:", name));
+        }
+        text.push(format!("{} {:#05X}  {:02X}{:02X}  {}", marker, start + offset, bytes[offset], bytes[offset + 1],
+            symbols.annotate(&instruction)));
+        offset += size;
+    }
+    text
+}
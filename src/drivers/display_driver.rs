@@ -1,19 +1,23 @@
-use sdl2;
 use sdl2::pixels;
 use sdl2::rect::Rect;
-use sdl2::render::Canvas;
-use sdl2::sys::Display;
+use sdl2::render::{BlendMode, Canvas};
 use sdl2::video::Window;
 
-use CHIP8_WIDTH;
-use CHIP8_HEIGHT;
+use chip8::{CHIP8_WIDTH, CHIP8_HEIGHT};
+
+use crate::drivers::text::{glyph, GLYPH_HEIGHT, GLYPH_WIDTH};
+use crate::drivers::{DisplayBackend, OverlayLine};
 
 const SCALE_FACTOR: u32 = 20;
 const SCREEN_WIDTH: u32 = (CHIP8_WIDTH as u32) * SCALE_FACTOR;
 const SCREEN_HEIGHT: u32 = (CHIP8_HEIGHT as u32) * SCALE_FACTOR;
+const TEXT_SCALE: u32 = 3;
+const TEXT_MARGIN: u32 = 8;
 
 pub struct DisplayDriver {
     canvas: Canvas<Window>,
+    overlay: Vec<OverlayLine>,
+    hud: Vec<OverlayLine>,
 }
 
 impl DisplayDriver {
@@ -34,28 +38,98 @@ impl DisplayDriver {
         canvas.set_draw_color(pixels::Color::RGB(0, 0, 0));
         canvas.clear();
         canvas.present();
+        canvas.set_blend_mode(BlendMode::Blend);
+
+        DisplayDriver { canvas, overlay: Vec::new(), hud: Vec::new() }
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        let _ = self.canvas.window_mut().set_title(title);
+    }
 
-        DisplayDriver { canvas:canvas }
+    fn color(value: u8) -> pixels::Color {
+        // Bit 0 is the first plane, bit 1 the XO-CHIP second plane
+        match value {
+            0 => pixels::Color::RGB(0, 0, 0),
+            1 => pixels::Color::RGB(0, 250, 0),
+            2 => pixels::Color::RGB(0, 110, 0),
+            _ => pixels::Color::RGB(190, 255, 190),
+        }
     }
 
-    pub fn draw(&mut self, pixels: &[[u8; CHIP8_WIDTH]; CHIP8_HEIGHT]) {
-        for (y, row) in pixels.iter().enumerate() {
-            for (x, &col) in row.iter().enumerate() {
-                let x = (x as u32) * SCALE_FACTOR;
-                let y = (y as u32) * SCALE_FACTOR;
+    fn draw_text(canvas: &mut Canvas<Window>, lines: &[OverlayLine], right: bool) {
+        // White text on a dimmed box in a top corner, a glyph and a column
+        // of space per character, highlights in yellow
+        let advance = (GLYPH_WIDTH as u32 + 1) * TEXT_SCALE;
+        let line_height = (GLYPH_HEIGHT as u32 + 2) * TEXT_SCALE;
+        let columns = lines.iter().map(|line| line.text.chars().count()).max().unwrap_or(0) as u32;
+        if columns == 0 {
+            return;
+        }
+        let width = columns * advance + TEXT_MARGIN * 2;
+        let height = lines.len() as u32 * line_height + TEXT_MARGIN * 2;
+        let origin = if right { SCREEN_WIDTH.saturating_sub(width) } else { 0 };
+        canvas.set_draw_color(pixels::Color::RGBA(0, 0, 0, 200));
+        let _ = canvas.fill_rect(Rect::new(origin as i32, 0, width, height));
 
-                self.canvas.set_draw_color(color(col));
-                let _ = self.canvas.fill_rect(Rect::new(x as i32, y as i32, SCALE_FACTOR, SCALE_FACTOR));
+        for (row, line) in lines.iter().enumerate() {
+            let top = TEXT_MARGIN + row as u32 * line_height;
+            for (column, c) in line.text.chars().enumerate() {
+                let left = origin + TEXT_MARGIN + column as u32 * advance;
+                let color = if line.highlight.iter().any(|range| range.contains(&column)) {
+                    pixels::Color::RGB(255, 220, 0)
+                } else {
+                    pixels::Color::RGB(255, 255, 255)
+                };
+                canvas.set_draw_color(color);
+                for (y, bits) in glyph(c).iter().enumerate() {
+                    for x in 0..GLYPH_WIDTH {
+                        if (bits >> (GLYPH_WIDTH - 1 - x)) & 1 == 1 {
+                            let x = left + x as u32 * TEXT_SCALE;
+                            let y = top + y as u32 * TEXT_SCALE;
+                            let _ = canvas.fill_rect(Rect::new(x as i32, y as i32, TEXT_SCALE, TEXT_SCALE));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl DisplayBackend for DisplayDriver {
+    fn draw(&mut self, pixels: &[u8], width: usize, height: usize) {
+        let blank = vec![0; pixels.len()];
+        self.draw_planes([pixels, &blank], width, height);
+    }
+
+    fn draw_planes(&mut self, planes: [&[u8]; 2], width: usize, height: usize) {
+        // Other resolutions are drawn with smaller pixels in the same window
+        let scale = (SCREEN_WIDTH / width as u32).min(SCREEN_HEIGHT / height as u32);
+        let offset = (SCREEN_WIDTH - width as u32 * scale) / 2;
+        self.canvas.set_draw_color(pixels::Color::RGB(0, 0, 0));
+        self.canvas.clear();
+        for y in 0..height {
+            for x in 0..width {
+                let index = y * width / 8 + x / 8;
+                let shift = 7 - x % 8;
+                let col = (planes[0][index] >> shift) & 1 | ((planes[1][index] >> shift) & 1) << 1;
+                let x = (x as u32) * scale + offset;
+                let y = (y as u32) * scale;
+
+                self.canvas.set_draw_color(Self::color(col));
+                let _ = self.canvas.fill_rect(Rect::new(x as i32, y as i32, scale, scale));
             } 
         }
+        Self::draw_text(&mut self.canvas, &self.overlay, false);
+        Self::draw_text(&mut self.canvas, &self.hud, true);
         self.canvas.present();
     }
 
-    fn color(value: u8) -> pixels::Color {
-        if value == 0 {
-            pixels::Color::RGB(0, 0, 0)
-        } else {
-            pixels::Color::RGB(0, 250, 0)
-        }
+    fn set_overlay(&mut self, lines: Vec<OverlayLine>) {
+        self.overlay = lines;
+    }
+
+    fn set_hud(&mut self, lines: Vec<OverlayLine>) {
+        self.hud = lines;
     }
 }
\ No newline at end of file
@@ -1,26 +1,232 @@
 use std::fs::File;
 use std::io::prelude::*;
+use std::time::Duration;
+
+use chip8::{Chip, Clock, Error, FrameOutput, Keypad, Symbols, FRAME_TIME};
+
+use crate::drivers::overlay::{self, MEMORY_ROW, MEMORY_ROWS};
+use crate::drivers::{AudioBackend, Debugger, DisplayBackend, HostKey, InputBackend};
 
 pub struct CartridgeDriver {
-    pub rom: [u8; 3584],
+    pub rom: Vec<u8>,
     pub size: usize,
 }
 
 impl CartridgeDriver {
     pub fn new(filename: &str) -> Self {
-
+        // Reads the whole file, the chip decides whether it fits in memory
         let mut f = File::open(filename).expect("file not found");
-        let mut buffer = [0u8; 3584];
+        let mut buffer = Vec::new();
 
-        let bytes_read = if let Ok(bytes_read) = f.read(&mut buffer) {
-            bytes_read
-        } else {
-            0
-        };
+        let bytes_read = f.read_to_end(&mut buffer).unwrap_or_default();
 
         CartridgeDriver {
             rom: buffer,
             size: bytes_read,
         }
     }
-}
\ No newline at end of file
+}
+
+pub struct GameDriver {
+    chip: Chip<'static>,
+    inputs: Vec<Box<dyn InputBackend>>,
+    keypad: Keypad,
+    debugger: Option<Box<dyn Debugger>>,
+    memory_view: Option<usize>,     // First address of the memory pane while it is up
+    hud: bool,                      // Show the registers in a corner every frame
+    symbols: Symbols,               // Labels for the paused disassembly
+}
+
+impl GameDriver {
+    pub fn new(chip: Chip<'static>, inputs: Vec<Box<dyn InputBackend>>) -> Self {
+        GameDriver {
+            chip,
+            inputs,
+            keypad: Keypad::new(),
+            debugger: None,
+            memory_view: None,
+            hud: false,
+            symbols: Symbols::new(),
+        }
+    }
+
+    pub fn set_debugger(&mut self, debugger: Option<Box<dyn Debugger>>) {
+        // The debugger decides when the chip runs, between frames
+        self.debugger = debugger;
+    }
+
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    pub fn chip(&self) -> &Chip<'static> {
+        &self.chip
+    }
+
+    pub fn chip_mut(&mut self) -> &mut Chip<'static> {
+        &mut self.chip
+    }
+
+    pub fn poll_inputs(&mut self) -> [bool; 16] {
+        // A key is down if it is down on any of the input sources
+        let mut keys = [false; 16];
+        for input in self.inputs.iter_mut() {
+            for (key, down) in keys.iter_mut().zip(input.poll().iter()) {
+                *key |= *down;
+            }
+        }
+        keys
+    }
+
+    fn host_keys(&mut self) -> Vec<HostKey> {
+        self.inputs.iter_mut().flat_map(|input| input.host_keys()).collect()
+    }
+
+    fn host_key(&mut self, key: HostKey) {
+        // Toggles the views and pages and lines through memory, which opens at I
+        if key == HostKey::RegisterHud {
+            self.hud = !self.hud;
+            return;
+        }
+        let page = MEMORY_ROW * MEMORY_ROWS;
+        let last = self.chip.memory_size().saturating_sub(1) / MEMORY_ROW * MEMORY_ROW;
+        self.memory_view = match (self.memory_view, key) {
+            (None, HostKey::MemoryView) => Some(self.chip.registers().i as usize / MEMORY_ROW * MEMORY_ROW),
+            (Some(_), HostKey::MemoryView) => None,
+            (Some(start), HostKey::PageUp) => Some(start.saturating_sub(page)),
+            (Some(start), HostKey::PageDown) => Some((start + page).min(last)),
+            (Some(start), HostKey::Up) => Some(start.saturating_sub(MEMORY_ROW)),
+            (Some(start), HostKey::Down) => Some((start + MEMORY_ROW).min(last)),
+            (view, _) => view,
+        };
+    }
+
+    pub fn run_for(&mut self, elapsed: Duration) -> Result<Option<FrameOutput>, Error> {
+        // Runs the chip on the merged input for however much host time has
+        // passed, returns None once a source asks to quit
+        let keys = self.poll_inputs();
+        if self.inputs.iter().any(|input| input.quit()) {
+            return Ok(None);
+        }
+        self.keypad.set_keys(keys);
+        self.chip.run_for(&mut self.keypad, elapsed).map(Some)
+    }
+
+    pub fn run<D: DisplayBackend, A: AudioBackend, C: Clock>(&mut self, display: &mut D, audio: &A, clock: &mut C) -> Result<(), Error> {
+        // Runs the chip on the given backends until an input asks to quit,
+        // with time kept by clock
+        let mut halted = false;
+        let mut overlay_shown = false;
+
+        loop {
+            // Run for the time that passed so a slow host doesn't slow the timers
+            let elapsed = clock.elapsed();
+            let hud = self.hud;
+            for key in self.host_keys() {
+                self.host_key(key);
+            }
+            if hud && !self.hud {
+                let chip = &self.chip;
+                display.set_hud(Vec::new());
+                display.draw_planes([chip.plane(0), chip.plane(1)], chip.width(), chip.height());
+            }
+            if let Some(debugger) = &mut self.debugger {
+                debugger.poll(&mut self.chip);
+                if debugger.quit() {
+                    return Ok(());
+                }
+                if debugger.paused() {
+                    // Keep the window alive and showing what stepping draws
+                    self.poll_inputs();
+                    if self.inputs.iter().any(|input| input.quit()) {
+                        return Ok(());
+                    }
+                    let chip = &self.chip;
+                    display.set_overlay(match self.memory_view {
+                        Some(start) => overlay::memory(chip, start),
+                        None => overlay::disassembly(chip, &self.symbols),
+                    });
+                    overlay_shown = true;
+                    if self.hud {
+                        display.set_hud(overlay::registers(chip));
+                    }
+                    display.draw_planes([chip.plane(0), chip.plane(1)], chip.width(), chip.height());
+                    audio.stop_beep();
+                    clock.wait(Duration::from_micros(FRAME_TIME as u64));
+                    continue;
+                }
+            }
+            let output = match self.run_for(elapsed)? {
+                Some(output) => output,
+                None => return Ok(()),
+            };
+            if let Some(debugger) = &mut self.debugger {
+                debugger.frame_done(&self.chip, &output);
+            }
+
+            for warning in self.chip.take_warnings() {
+                eprintln!("warning: {:?}", warning);
+            }
+
+            let chip = &self.chip;
+            if overlay_shown {
+                // Running again, so the paused view has to go
+                display.set_overlay(Vec::new());
+                display.draw_planes([chip.plane(0), chip.plane(1)], chip.width(), chip.height());
+                overlay_shown = false;
+            }
+            if output.halted && !halted {
+                // Keep the display up so the final screen can still be seen
+                eprintln!("rom halted at {:03X}", chip.pc());
+                halted = true;
+            }
+            if self.hud {
+                display.set_hud(overlay::registers(chip));
+            }
+            if output.display_changed || self.hud {
+                display.draw_planes([chip.plane(0), chip.plane(1)], chip.width(), chip.height());
+            }
+            if output.beep {
+                audio.start_beep();
+            } else {
+                audio.stop_beep();
+            }
+
+            clock.wait(Duration::from_micros(FRAME_TIME as u64));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Holds down the same keys on every poll
+    struct Held(&'static [usize]);
+
+    impl InputBackend for Held {
+        fn poll(&mut self) -> [bool; 16] {
+            let mut keys = [false; 16];
+            for &key in self.0 {
+                keys[key] = true;
+            }
+            keys
+        }
+    }
+
+    #[test]
+    fn chip_sees_keys_from_every_input() {
+        // VA and VB stay zero when keys 1 and 2 are down
+        let mut chip = Chip::new();
+        chip.load_rom(&[0x60, 0x01, 0x61, 0x02, 0xE0, 0x9E, 0x6A, 0x01, 0xE1, 0x9E, 0x6B, 0x01, 0x12, 0x0C]).unwrap();
+        let mut driver = GameDriver::new(chip, vec![Box::new(Held(&[1])), Box::new(Held(&[2]))]);
+        let keys = driver.poll_inputs();
+        assert_eq!(keys.iter().filter(|&&down| down).count(), 2);
+        assert!(keys[1] && keys[2]);
+
+        driver.run_for(Duration::from_millis(100)).unwrap().unwrap();
+        let v = driver.chip().registers().v;
+        assert_eq!((v[0xA], v[0xB]), (0, 0));
+        assert!(driver.chip().halted());
+    }
+}
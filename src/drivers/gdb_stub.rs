@@ -0,0 +1,374 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use chip8::{Access, Chip, FrameOutput, Registers};
+
+use crate::drivers::Debugger;
+
+// Registers as gdb numbers them: V0-VF, I, PC, SP, DT, ST
+const REGISTER_COUNT: usize = 21;
+const REG_I: usize = 16;
+const REG_PC: usize = 17;
+const REG_SP: usize = 18;
+const REG_DT: usize = 19;
+const REG_ST: usize = 20;
+
+// Sent in answer to qXfer:features:read so gdb knows the register layout
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="dt" bitsize="8" type="uint8"/>
+    <reg name="st" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+// A minimal gdb remote serial protocol server. It waits for gdb to connect
+// with the chip paused, then reads registers and memory, sets breakpoints
+// and watchpoints, and steps or continues on request
+pub struct GdbStub {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    input: Vec<u8>,                 // Bytes received but not yet a whole packet
+    paused: bool,
+    quit: bool,
+}
+
+impl GdbStub {
+    pub fn listen(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+        println!("waiting for gdb on port {}", port);
+        Ok(GdbStub {
+            listener,
+            client: None,
+            input: Vec::new(),
+            paused: true,
+            quit: false,
+        })
+    }
+
+    fn receive(&mut self) {
+        // Pulls in whatever the client has sent, dropping it if it went away
+        let client = match &mut self.client {
+            Some(client) => client,
+            None => return,
+        };
+        let mut buffer = [0; 1024];
+        loop {
+            match client.read(&mut buffer) {
+                Ok(0) => {
+                    self.disconnect();
+                    return;
+                }
+                Ok(n) => self.input.extend_from_slice(&buffer[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(_) => {
+                    self.disconnect();
+                    return;
+                }
+            }
+        }
+    }
+
+    fn disconnect(&mut self) {
+        // Lets the rom carry on without a debugger
+        self.client = None;
+        self.input.clear();
+        self.paused = false;
+    }
+
+    fn next_packet(&mut self) -> Option<String> {
+        // Takes the next $data#cs packet off the input, acknowledging it.
+        // A lone 0x03 is gdb interrupting a running target
+        loop {
+            let start = self.input.iter().position(|&b| b == b'$' || b == 0x03)?;
+            if self.input[start] == 0x03 {
+                self.input.drain(..=start);
+                return Some("\x03".to_string());
+            }
+            let end = self.input[start..].iter().position(|&b| b == b'#')? + start;
+            if self.input.len() < end + 3 {
+                return None;
+            }
+            let data = String::from_utf8_lossy(&self.input[start + 1..end]).into_owned();
+            let sum = std::str::from_utf8(&self.input[end + 1..end + 3]).ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            self.input.drain(..end + 3);
+            if sum == Some(checksum(&data)) {
+                self.write(b"+");
+                return Some(data);
+            }
+            self.write(b"-");
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        if let Some(client) = &mut self.client {
+            if client.write_all(bytes).is_err() {
+                self.disconnect();
+            }
+        }
+    }
+
+    fn send(&mut self, data: &str) {
+        let packet = format!("${}#{:02x}", data, checksum(data));
+        self.write(packet.as_bytes());
+    }
+
+    fn stop(&mut self, reason: &str) {
+        // Tells gdb the chip has stopped, and why
+        self.paused = true;
+        self.send(reason);
+    }
+
+    fn handle(&mut self, chip: &mut Chip, packet: &str) {
+        if packet.is_empty() || !packet.is_ascii() {
+            self.send("");
+            return;
+        }
+        if packet == "\x03" {
+            if !self.paused {
+                self.stop("S02");
+            }
+            return;
+        }
+        let (command, args) = packet.split_at(1);
+        let reply = match command {
+            "?" => "S05".to_string(),
+            "g" => {
+                let regs = chip.registers();
+                (0..REGISTER_COUNT).map(|n| register_hex(&regs, n)).collect()
+            }
+            "G" => self.write_registers(chip, args),
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(n) if n < REGISTER_COUNT => register_hex(&chip.registers(), n),
+                _ => "E01".to_string(),
+            },
+            "P" => self.write_register(chip, args),
+            "m" => match parse_pair(args, ',') {
+                Some((addr, len)) => match chip.read_range(addr as u16, len.min(chip.memory_size().saturating_sub(addr))) {
+                    Ok(bytes) => bytes.iter().map(|byte| format!("{:02x}", byte)).collect(),
+                    Err(_) => "E01".to_string(),
+                },
+                None => "E01".to_string(),
+            },
+            "M" => {
+                let written = args.split_once(':').and_then(|(range, data)| {
+                    let (addr, _) = parse_pair(range, ',')?;
+                    chip.write_range(addr as u16, &decode_hex(data)?).ok()
+                });
+                if written.is_some() { "OK" } else { "E01" }.to_string()
+            }
+            "Z" | "z" => self.set_point(chip, command == "Z", args),
+            "s" => {
+                let _ = chip.step();
+                "S05".to_string()
+            }
+            "b" if args == "s" => {
+                // Reverse step, running out of history is a stop reason of its own
+                if chip.step_back() { "S05" } else { "T05replaylog:begin;" }.to_string()
+            }
+            "c" => {
+                // The reply waits until the chip stops
+                self.paused = false;
+                return;
+            }
+            "D" => {
+                self.send("OK");
+                self.disconnect();
+                return;
+            }
+            "k" => {
+                self.quit = true;
+                return;
+            }
+            "H" => "OK".to_string(),
+            "q" => self.query(args),
+            _ => String::new(),
+        };
+        self.send(&reply);
+    }
+
+    fn query(&self, args: &str) -> String {
+        if args.starts_with("Supported") {
+            return "PacketSize=1000;qXfer:features:read+;ReverseStep+".to_string();
+        }
+        if let Some(range) = args.strip_prefix("Xfer:features:read:target.xml:") {
+            return match parse_pair(range, ',') {
+                Some((offset, len)) => {
+                    let xml = TARGET_XML.as_bytes();
+                    let chunk = &xml[offset.min(xml.len())..(offset + len).min(xml.len())];
+                    let more = offset + len < xml.len();
+                    format!("{}{}", if more { "m" } else { "l" }, String::from_utf8_lossy(chunk))
+                }
+                None => "E01".to_string(),
+            };
+        }
+        match args {
+            "Attached" => "1".to_string(),
+            "C" => "QC1".to_string(),
+            "fThreadInfo" => "m1".to_string(),
+            "sThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    fn write_registers(&self, chip: &mut Chip, args: &str) -> String {
+        // Registers come in the same order and widths g sends them
+        let bytes = match decode_hex(args) {
+            Some(bytes) if bytes.len() == 16 + 2 + 2 + 3 => bytes,
+            _ => return "E01".to_string(),
+        };
+        let mut regs = chip.registers();
+        regs.v.copy_from_slice(&bytes[..16]);
+        regs.i = u16::from_le_bytes([bytes[16], bytes[17]]);
+        regs.pc = u16::from_le_bytes([bytes[18], bytes[19]]);
+        regs.sp = bytes[20];
+        regs.dt = bytes[21];
+        regs.st = bytes[22];
+        chip.set_registers(regs);
+        "OK".to_string()
+    }
+
+    fn write_register(&self, chip: &mut Chip, args: &str) -> String {
+        let parsed = args.split_once('=').and_then(|(n, value)| {
+            Some((usize::from_str_radix(n, 16).ok()?, decode_hex(value)?))
+        });
+        let (n, bytes) = match parsed {
+            Some((n, bytes)) if n < REGISTER_COUNT && !bytes.is_empty() => (n, bytes),
+            _ => return "E01".to_string(),
+        };
+        let mut regs = chip.registers();
+        let wide = u16::from_le_bytes([bytes[0], bytes.get(1).copied().unwrap_or(0)]);
+        match n {
+            REG_I => regs.i = wide,
+            REG_PC => regs.pc = wide,
+            REG_SP => regs.sp = bytes[0],
+            REG_DT => regs.dt = bytes[0],
+            REG_ST => regs.st = bytes[0],
+            v => regs.v[v] = bytes[0],
+        }
+        chip.set_registers(regs);
+        "OK".to_string()
+    }
+
+    fn set_point(&self, chip: &mut Chip, add: bool, args: &str) -> String {
+        // Z0/Z1 are breakpoints, Z2/Z3/Z4 write, read and access watchpoints
+        let mut fields = args.split(',');
+        let kind = fields.next();
+        let addr = fields.next().and_then(|addr| usize::from_str_radix(addr, 16).ok());
+        let len = fields.next().and_then(|len| usize::from_str_radix(len, 16).ok()).unwrap_or(1);
+        let addr = match addr {
+            Some(addr) => addr,
+            None => return "E01".to_string(),
+        };
+        let access = match kind {
+            Some("0") | Some("1") => {
+                if add {
+                    chip.add_breakpoint(addr as u16);
+                } else {
+                    chip.remove_breakpoint(addr as u16);
+                }
+                return "OK".to_string();
+            }
+            Some("2") => Access::Write,
+            Some("3") => Access::Read,
+            Some("4") => Access::ReadWrite,
+            _ => return String::new(),
+        };
+        if add {
+            chip.add_watchpoint(addr, addr + len, access);
+        } else {
+            chip.remove_watchpoint(addr, addr + len);
+        }
+        "OK".to_string()
+    }
+}
+
+impl Debugger for GdbStub {
+    fn poll(&mut self, chip: &mut Chip) {
+        if self.client.is_none() {
+            if let Ok((client, _)) = self.listener.accept() {
+                if client.set_nonblocking(true).is_ok() {
+                    println!("gdb connected");
+                    self.client = Some(client);
+                    self.paused = true;
+                }
+            }
+        }
+        self.receive();
+        while let Some(packet) = self.next_packet() {
+            self.handle(chip, &packet);
+        }
+    }
+
+    fn paused(&self) -> bool {
+        self.paused
+    }
+
+    fn quit(&self) -> bool {
+        self.quit
+    }
+
+    fn frame_done(&mut self, _chip: &Chip, output: &FrameOutput) {
+        if self.client.is_none() {
+            return;
+        }
+        if output.breakpoint.is_some() || output.halted {
+            self.stop("S05");
+        } else if let Some(hit) = output.watchpoint {
+            let kind = if hit.write { "watch" } else { "rwatch" };
+            self.stop(&format!("T05{}:{:x};", kind, hit.addr));
+        }
+    }
+}
+
+fn checksum(data: &str) -> u8 {
+    data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte))
+}
+
+fn register_hex(regs: &Registers, n: usize) -> String {
+    // Little endian, as gdb expects target values
+    match n {
+        REG_I => format!("{:02x}{:02x}", regs.i & 0xff, regs.i >> 8),
+        REG_PC => format!("{:02x}{:02x}", regs.pc & 0xff, regs.pc >> 8),
+        REG_SP => format!("{:02x}", regs.sp),
+        REG_DT => format!("{:02x}", regs.dt),
+        REG_ST => format!("{:02x}", regs.st),
+        v => format!("{:02x}", regs.v[v]),
+    }
+}
+
+fn parse_pair(text: &str, separator: char) -> Option<(usize, usize)> {
+    // Splits "addr,len" style hex pairs
+    let (a, b) = text.split_once(separator)?;
+    Some((usize::from_str_radix(a, 16).ok()?, usize::from_str_radix(b, 16).ok()?))
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|at| u8::from_str_radix(text.get(at..at + 2)?, 16).ok()).collect()
+}
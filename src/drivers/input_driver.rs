@@ -1,23 +1,41 @@
-use sdl2;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 
+use crate::drivers::{HostKey, InputBackend};
+
 pub struct InputDriver {
     events: sdl2::EventPump,
+    quit: bool,
+    host_keys: Vec<HostKey>,
 }
 
 impl InputDriver {
     pub fn new(sdl_context: &sdl2::Sdl) -> Self {
-        InputDriver { events: sdl_context.event_pump().unwrap() }
+        InputDriver { events: sdl_context.event_pump().unwrap(), quit: false, host_keys: Vec::new() }
     }
 
 
     pub fn poll(&mut self) -> Result<[bool; 16], ()> {
 
         for event in self.events.poll_iter() {
-            if let Event::Quit { .. } = event {
-                return Err(());
-            };
+            match event {
+                Event::Quit { .. } => return Err(()),
+                Event::KeyDown { keycode: Some(keycode), .. } => {
+                    // F2 toggles the memory view, the arrows and page keys move
+                    // around it, F3 toggles the registers
+                    let host_key = match keycode {
+                        Keycode::F2 => Some(HostKey::MemoryView),
+                        Keycode::F3 => Some(HostKey::RegisterHud),
+                        Keycode::PageUp => Some(HostKey::PageUp),
+                        Keycode::PageDown => Some(HostKey::PageDown),
+                        Keycode::Up => Some(HostKey::Up),
+                        Keycode::Down => Some(HostKey::Down),
+                        _ => None,
+                    };
+                    self.host_keys.extend(host_key);
+                }
+                _ => {}
+            }
         }
 
         let keys: Vec<Keycode> = self.events
@@ -56,4 +74,24 @@ impl InputDriver {
 
         Ok(chip8_keys)
     }
-}
\ No newline at end of file
+}
+
+impl InputBackend for InputDriver {
+    fn poll(&mut self) -> [bool; 16] {
+        match InputDriver::poll(self) {
+            Ok(keys) => keys,
+            Err(()) => {
+                self.quit = true;
+                [false; 16]
+            }
+        }
+    }
+
+    fn host_keys(&mut self) -> Vec<HostKey> {
+        std::mem::take(&mut self.host_keys)
+    }
+
+    fn quit(&self) -> bool {
+        self.quit
+    }
+}
@@ -0,0 +1,29 @@
+// Default host keys for the 16 CHIP-8 keys, indexed by key value
+const DEFAULT_KEYS: [char; 16] = [
+    'x', '1', '2', '3',
+    'q', 'w', 'e', 'a',
+    's', 'd', 'z', 'c',
+    '4', 'r', 'f', 'v',
+];
+
+pub struct KeyMap {
+    keys: [char; 16],
+}
+
+impl KeyMap {
+    pub fn new(keys: [char; 16]) -> Self {
+        KeyMap { keys }
+    }
+
+    pub fn key(&self, c: char) -> Option<usize> {
+        // Which CHIP-8 key a host character is bound to, if any
+        let c = c.to_ascii_lowercase();
+        self.keys.iter().position(|&k| k == c)
+    }
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        KeyMap::new(DEFAULT_KEYS)
+    }
+}
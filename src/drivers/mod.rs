@@ -1,5 +1,104 @@
+use chip8::{Chip, FrameOutput};
+
 mod audio_driver;
+mod debug_console;
 mod display_driver;
 mod game_driver;
+#[cfg(feature = "gdb")]
+mod gdb_stub;
 mod input_driver;
+#[cfg(feature = "tui")]
+mod key_map;
+mod overlay;
+mod png;
+mod sprite_scan;
+mod test_suite;
+mod text;
+mod trace_log;
+#[cfg(feature = "tui")]
+mod tui_driver;
+
+pub use self::audio_driver::Audio;
+#[cfg(feature = "tui")]
+pub use self::audio_driver::NullAudio;
+pub use self::debug_console::{disassemble, print_call_stack, print_history, DebugConsole};
+pub use self::display_driver::DisplayDriver;
+pub use self::game_driver::{CartridgeDriver, GameDriver};
+#[cfg(feature = "gdb")]
+pub use self::gdb_stub::GdbStub;
+pub use self::input_driver::InputDriver;
+#[cfg(feature = "tui")]
+pub use self::key_map::KeyMap;
+pub use self::overlay::OverlayLine;
+pub use self::png::write_png;
+pub use self::sprite_scan::SpriteScan;
+pub use self::test_suite::{run_test, Reference};
+pub use self::trace_log::TraceLog;
+#[cfg(feature = "tui")]
+pub use self::tui_driver::{TuiDisplay, TuiInput};
+
+// Frontends are built from one of each backend, so SDL, the terminal or a
+// test harness can be swapped in without touching the main loop
+
+pub trait DisplayBackend {
+    // Pixels are packed eight to a byte, most significant bit leftmost,
+    // with each row width / 8 bytes long
+    fn draw(&mut self, pixels: &[u8], width: usize, height: usize);
+
+    fn draw_planes(&mut self, planes: [&[u8]; 2], width: usize, height: usize) {
+        // Backends without colour show a pixel lit on either XO-CHIP plane
+        let merged: Vec<u8> = planes[0].iter().zip(planes[1].iter()).map(|(a, b)| a | b).collect();
+        self.draw(&merged, width, height);
+    }
+
+    // Lines of text drawn over the display from the next draw on, until
+    // replaced. Backends with nowhere to put them ignore them
+    fn set_overlay(&mut self, _lines: Vec<OverlayLine>) {}
+
+    // Like set_overlay, for the register display kept in the other corner
+    fn set_hud(&mut self, _lines: Vec<OverlayLine>) {}
+}
+
+pub trait AudioBackend {
+    fn start_beep(&self);
+
+    fn stop_beep(&self);
+}
+
+// Takes control of the chip between frames, pausing it while the user looks around
+pub trait Debugger {
+    fn poll(&mut self, chip: &mut Chip);
+
+    fn paused(&self) -> bool;
+
+    fn quit(&self) -> bool {
+        false
+    }
+
+    // Called after every frame the chip runs, to notice breakpoints and the like
+    fn frame_done(&mut self, chip: &Chip, output: &FrameOutput);
+}
+
+// Keys meant for the frontend rather than the chip, like the debug views
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostKey {
+    MemoryView,
+    RegisterHud,
+    PageUp,
+    PageDown,
+    Up,
+    Down,
+}
+
+pub trait InputBackend {
+    fn poll(&mut self) -> [bool; 16];
+
+    // Host keys pressed since the last call
+    fn host_keys(&mut self) -> Vec<HostKey> {
+        Vec::new()
+    }
 
+    fn quit(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,78 @@
+use std::ops::Range;
+
+use chip8::{Chip, Symbols};
+
+use crate::drivers::disassemble;
+
+// Instructions shown either side of the PC in the disassembly pane
+const DISASM_CONTEXT: usize = 5;
+// Bytes on a line of the memory pane, and lines on a page
+pub const MEMORY_ROW: usize = 16;
+pub const MEMORY_ROWS: usize = 16;
+
+// A line of text drawn over the display, with the characters in highlight
+// picked out in a second colour
+#[derive (Debug, Clone, Default, PartialEq)]
+pub struct OverlayLine {
+    pub text: String,
+    pub highlight: Vec<Range<usize>>,
+}
+
+impl From<String> for OverlayLine {
+    fn from(text: String) -> Self {
+        OverlayLine { text, highlight: Vec::new() }
+    }
+}
+
+pub fn disassembly(chip: &Chip, symbols: &Symbols) -> Vec<OverlayLine> {
+    // Instructions are two bytes bar the odd XO-CHIP long load, so counting
+    // back two at a time rarely lands mid instruction
+    let start = (chip.pc() as usize).saturating_sub(DISASM_CONTEXT * 2);
+    disassemble(chip, symbols, start, DISASM_CONTEXT * 2 + 1).into_iter().map(|text| {
+        let mut highlight = Vec::new();
+        if text.starts_with('>') {
+            highlight.push(0..text.len());
+        }
+        OverlayLine { text, highlight }
+    }).collect()
+}
+
+pub fn registers(chip: &Chip) -> Vec<OverlayLine> {
+    // Four V registers to a line, then the rest
+    let regs = chip.registers();
+    let mut lines: Vec<OverlayLine> = regs.v.chunks(4).enumerate().map(|(row, group)| {
+        let text: Vec<String> = group.iter().enumerate().map(|(n, v)| format!("V{:X} {:02X}", row * 4 + n, v)).collect();
+        OverlayLine::from(text.join(" "))
+    }).collect();
+    lines.push(OverlayLine::from(format!("I {:04X}  PC {:04X}", regs.i, regs.pc)));
+    lines.push(OverlayLine::from(format!("SP {:X}  DT {:02X}  ST {:02X}", regs.sp, regs.dt, regs.st)));
+    lines
+}
+
+pub fn memory(chip: &Chip, start: usize) -> Vec<OverlayLine> {
+    // A page of memory from start with the bytes at I and the PC highlighted
+    let regs = chip.registers();
+    let mut lines = vec![OverlayLine {
+        text: format!("PC {:#06X}  I {:#06X}", regs.pc, regs.i),
+        highlight: vec![0..9, 11..19],
+    }];
+    let pc = regs.pc as usize..regs.pc as usize + 2;
+    for row in 0..MEMORY_ROWS {
+        let addr = start + row * MEMORY_ROW;
+        let len = MEMORY_ROW.min(chip.memory_size().saturating_sub(addr));
+        let bytes = match chip.read_range(addr as u16, len) {
+            Ok(bytes) if !bytes.is_empty() => bytes,
+            _ => break,
+        };
+        // Each byte takes three columns after the eight of the address
+        let mut line = OverlayLine::from(format!("{:#06X}  ", addr));
+        for (offset, byte) in bytes.iter().enumerate() {
+            if addr + offset == regs.i as usize || pc.contains(&(addr + offset)) {
+                line.highlight.push(line.text.len()..line.text.len() + 2);
+            }
+            line.text.push_str(&format!("{:02X} ", byte));
+        }
+        lines.push(line);
+    }
+    lines
+}
@@ -0,0 +1,70 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+// Deflate's stored blocks hold at most this many bytes
+const STORED_BLOCK: usize = 0xFFFF;
+
+pub fn write_png(path: &str, pixels: &[u8], width: usize, height: usize, scale: usize) -> io::Result<()> {
+    // Writes display style pixels, eight to a byte, as a greyscale PNG with
+    // each pixel scale times larger. The image data is stored rather than
+    // compressed, which keeps the encoder short and sheets are small anyway
+    let mut raw = Vec::with_capacity((width * scale + 1) * height * scale);
+    for y in 0..height * scale {
+        raw.push(0);
+        for x in 0..width * scale {
+            let (x, y) = (x / scale, y / scale);
+            let lit = (pixels[y * width / 8 + x / 8] >> (7 - x % 8)) & 1 == 1;
+            raw.push(if lit { 0xFF } else { 0x00 });
+        }
+    }
+
+    let mut zlib = vec![0x78, 0x01];
+    let blocks = raw.chunks(STORED_BLOCK).count();
+    for (n, block) in raw.chunks(STORED_BLOCK).enumerate() {
+        zlib.push((n + 1 == blocks) as u8);
+        zlib.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        zlib.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&((width * scale) as u32).to_be_bytes());
+    header.extend_from_slice(&((height * scale) as u32).to_be_bytes());
+    header.extend_from_slice(&[8, 0, 0, 0, 0]);     // 8 bit greyscale, no interlacing
+
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(b"\x89PNG\r\n\x1a\n")?;
+    write_chunk(&mut out, b"IHDR", &header)?;
+    write_chunk(&mut out, b"IDAT", &zlib)?;
+    write_chunk(&mut out, b"IEND", &[])?;
+    out.flush()
+}
+
+fn write_chunk(out: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    // Length, type, data, then a CRC of the type and data
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    out.write_all(&crc32(kind.iter().chain(data)).to_be_bytes())
+}
+
+fn crc32<'a>(bytes: impl Iterator<Item=&'a u8>) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { crc >> 1 ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in bytes {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
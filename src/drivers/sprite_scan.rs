@@ -0,0 +1,32 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use chip8::{ChipObserver, Instruction, TraceEntry};
+
+// Records where I pointed each time the running rom drew, and the most bytes
+// drawn from there, to find sprites a static walk of the rom can't
+pub struct SpriteScan {
+    drawn: Mutex<BTreeMap<u16, usize>>,
+}
+
+impl SpriteScan {
+    pub fn new() -> Self {
+        SpriteScan { drawn: Mutex::new(BTreeMap::new()) }
+    }
+
+    pub fn drawn(&self) -> BTreeMap<u16, usize> {
+        self.drawn.lock().map(|drawn| drawn.clone()).unwrap_or_default()
+    }
+}
+
+impl ChipObserver for SpriteScan {
+    fn traced(&self, entry: &TraceEntry) {
+        if let Instruction::Drw { n, .. } = entry.instruction {
+            let bytes = if n == 0 { 32 } else { n as usize };
+            if let Ok(mut drawn) = self.drawn.lock() {
+                let most = drawn.entry(entry.i).or_insert(0);
+                *most = bytes.max(*most);
+            }
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use chip8::{Chip, Keypad, Platform};
+
+// Frames a test rom runs for when its reference doesn't say
+pub const TEST_FRAMES: usize = 300;
+// Frames a key is held for when a reference presses it
+const PRESS_FRAMES: usize = 4;
+
+// What a test rom's screen should look like once it has run, and how to run
+// it. Stored beside the rom as text so changes show up in review:
+//
+//     # Lines starting with # are comments
+//     platform vip
+//     frames 120
+//     press 1 30
+//     ....XXXX....
+//
+// press holds a hex key down from a frame. The screen is one line per row,
+// X for a lit pixel and . for a dark one
+#[derive (Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub platform: Option<Platform>,
+    pub ipf: Option<usize>,
+    pub frames: usize,
+    pub presses: Vec<(u8, usize)>,
+    pub screen: Vec<String>,
+}
+
+impl Reference {
+    pub fn new() -> Self {
+        Reference { platform: None, ipf: None, frames: TEST_FRAMES, presses: Vec::new(), screen: Vec::new() }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut reference = Reference::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let ok = match words[..] {
+                ["platform", name] => {
+                    reference.platform = Platform::from_name(name);
+                    reference.platform.is_some()
+                }
+                ["ipf", ipf] => {
+                    reference.ipf = ipf.parse().ok();
+                    reference.ipf.is_some()
+                }
+                ["frames", frames] => match frames.parse() {
+                    Ok(frames) => {
+                        reference.frames = frames;
+                        true
+                    }
+                    Err(_) => false,
+                },
+                ["press", key, frame] => match (u8::from_str_radix(key, 16), frame.parse()) {
+                    (Ok(key), Ok(frame)) if key < 16 => {
+                        reference.presses.push((key, frame));
+                        true
+                    }
+                    _ => false,
+                },
+                [row] if row.chars().all(|c| c == 'X' || c == '.') => {
+                    reference.screen.push(row.to_string());
+                    true
+                }
+                _ => false,
+            };
+            if !ok {
+                return Err(format!("line {}: can't read {}", n + 1, line));
+            }
+        }
+        Ok(reference)
+    }
+
+    pub fn keys_at(&self, frame: usize) -> [bool; 16] {
+        let mut keys = [false; 16];
+        for &(key, from) in &self.presses {
+            if (from..from + PRESS_FRAMES).contains(&frame) {
+                keys[key as usize] = true;
+            }
+        }
+        keys
+    }
+
+    pub fn differences(&self, screen: &[String]) -> usize {
+        // Pixels that don't match, counting a different resolution as all of them
+        if self.screen.len() != screen.len() || self.screen.iter().zip(screen).any(|(a, b)| a.len() != b.len()) {
+            return screen.iter().map(|row| row.len()).sum::<usize>().max(1);
+        }
+        self.screen.iter().zip(screen).map(|(a, b)| a.chars().zip(b.chars()).filter(|(a, b)| a != b).count()).sum()
+    }
+}
+
+impl std::fmt::Display for Reference {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let Some(platform) = self.platform {
+            writeln!(f, "platform {}", platform.name())?;
+        }
+        if let Some(ipf) = self.ipf {
+            writeln!(f, "ipf {}", ipf)?;
+        }
+        writeln!(f, "frames {}", self.frames)?;
+        for (key, frame) in &self.presses {
+            writeln!(f, "press {:X} {}", key, frame)?;
+        }
+        for row in &self.screen {
+            writeln!(f, "{}", row)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn screen(chip: &Chip) -> Vec<String> {
+    // The display as text, a pixel lit on either XO-CHIP plane counting as lit
+    let (width, height) = (chip.width(), chip.height());
+    (0..height).map(|y| {
+        (0..width).map(|x| {
+            let index = y * width / 8 + x / 8;
+            let mask = 0x80 >> (x % 8);
+            if (chip.plane(0)[index] | chip.plane(1)[index]) & mask != 0 { 'X' } else { '.' }
+        }).collect()
+    }).collect()
+}
+
+pub fn run_test(chip: &mut Chip, reference: &Reference) -> Result<Vec<String>, chip8::Error> {
+    // Runs the chip headlessly for the reference's frames, pressing its keys
+    let mut keypad = Keypad::new();
+    for frame in 0..reference.frames {
+        keypad.set_keys(reference.keys_at(frame));
+        chip.frame(&mut keypad)?;
+        if chip.halted() {
+            break;
+        }
+    }
+    Ok(screen(chip))
+}
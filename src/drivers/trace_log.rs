@@ -0,0 +1,32 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::sync::Mutex;
+
+use chip8::{ChipObserver, Symbols, TraceEntry};
+
+// Writes every traced instruction to a file, one line each, with a line
+// for each label the program reaches
+pub struct TraceLog {
+    out: Mutex<BufWriter<File>>,
+    symbols: Symbols,
+}
+
+impl TraceLog {
+    pub fn create(path: &str, symbols: Symbols) -> io::Result<Self> {
+        Ok(TraceLog {
+            out: Mutex::new(BufWriter::new(File::create(path)?)),
+            symbols,
+        })
+    }
+}
+
+impl ChipObserver for TraceLog {
+    fn traced(&self, entry: &TraceEntry) {
+        if let Ok(mut out) = self.out.lock() {
+            if let Some(name) = self.symbols.name(entry.pc) {
+                let _ = writeln!(out, "{}:", name);
+            }
+            let _ = writeln!(out, "{}", entry);
+        }
+    }
+}
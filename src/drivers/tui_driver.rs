@@ -0,0 +1,126 @@
+use std::io::{self, stdout, Stdout, Write};
+use std::time::Duration;
+
+use crossterm::{cursor, execute, queue, terminal};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+
+use crate::drivers::{DisplayBackend, InputBackend, KeyMap};
+
+pub struct TuiDisplay {
+    out: Stdout,
+}
+
+impl TuiDisplay {
+    pub fn new() -> io::Result<Self> {
+        // Takes over the terminal until dropped, failing without one
+        let mut out = stdout();
+        terminal::enable_raw_mode()?;
+        if let Err(e) = execute!(out, terminal::EnterAlternateScreen, cursor::Hide) {
+            let _ = terminal::disable_raw_mode();
+            return Err(e);
+        }
+
+        Ok(TuiDisplay { out })
+    }
+}
+
+pub struct TuiInput {
+    key_map: KeyMap,
+    quit: bool,
+}
+
+impl TuiInput {
+    pub fn new(key_map: KeyMap) -> Self {
+        TuiInput { key_map, quit: false }
+    }
+
+    pub fn poll(&mut self) -> Result<[bool; 16], ()> {
+        // Terminals don't report key releases, so a key counts as held for
+        // the frame its press (or auto-repeat) arrives in
+        let mut chip8_keys = [false; 16];
+
+        while event::poll(Duration::ZERO).unwrap_or(false) {
+            if let Ok(Event::Key(key)) = event::read() {
+                if key.kind == KeyEventKind::Release {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Esc => return Err(()),
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Err(()),
+                    KeyCode::Char(c) => {
+                        if let Some(i) = self.key_map.key(c) {
+                            chip8_keys[i] = true;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(chip8_keys)
+    }
+}
+
+impl InputBackend for TuiInput {
+    fn poll(&mut self) -> [bool; 16] {
+        // Esc or Ctrl-C asks to quit
+        match TuiInput::poll(self) {
+            Ok(keys) => keys,
+            Err(()) => {
+                self.quit = true;
+                [false; 16]
+            }
+        }
+    }
+
+    fn quit(&self) -> bool {
+        self.quit
+    }
+}
+
+impl DisplayBackend for TuiDisplay {
+    fn draw(&mut self, pixels: &[u8], width: usize, height: usize) {
+        for row in 0..height / 2 {
+            let _ = queue!(self.out, cursor::MoveTo(0, row as u16));
+            let _ = write!(self.out, "{}", half_block_row(pixels, width, row));
+        }
+        let _ = self.out.flush();
+    }
+}
+
+impl Drop for TuiDisplay {
+    fn drop(&mut self) {
+        let _ = execute!(self.out, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+pub fn half_block(top: bool, bottom: bool) -> char {
+    // One character cell shows two vertically stacked pixels
+    match (top, bottom) {
+        (false, false) => ' ',
+        (true, false) => '▀',
+        (false, true) => '▄',
+        (true, true) => '█',
+    }
+}
+
+pub fn half_block_row(pixels: &[u8], width: usize, row: usize) -> String {
+    // Packs display rows 2 * row and 2 * row + 1 into one line of characters
+    let pixel = |x: usize, y: usize| (pixels[y * width / 8 + x / 8] >> (7 - x % 8)) & 1 == 1;
+    (0..width)
+        .map(|x| half_block(pixel(x, 2 * row), pixel(x, 2 * row + 1)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn half_blocks_pack_two_rows_into_one() {
+        // Columns 0-3 of an 8x2 display are off/off, on/off, off/on and on/on
+        let pixels = [0b0101_0000, 0b0011_0000];
+        assert_eq!(half_block_row(&pixels, 8, 0), " ▀▄█    ");
+    }
+}
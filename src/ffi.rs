@@ -0,0 +1,184 @@
+// C bindings for embedding the core in C/C++ frontends and other runtimes.
+// cargo build -p chip8-ffi builds them as libchip8.so and libchip8.a.
+// include/chip8.h is generated from this file by cbindgen, rerun
+//     cbindgen --config cbindgen.toml --output include/chip8.h
+// after changing anything here
+use core::ptr;
+use core::slice;
+use std::ffi::{c_char, c_int, CString};
+
+use crate::chip::{Chip, FrameOutput};
+use crate::keypad::Keypad;
+
+// A chip along with the keypad C code presses keys on, opaque to C
+pub struct Chip8 {
+    chip : Chip<'static>,
+    keypad : Keypad,
+    error : Option<CString>,        // Description of the last failure
+}
+
+// What a frame did, mirroring FrameOutput
+#[repr(C)]
+pub struct Chip8Frame {
+    pub display_changed: bool,
+    pub beep: bool,
+    pub halted: bool,
+    pub instructions_executed: usize,
+}
+
+impl From<FrameOutput> for Chip8Frame {
+    fn from(output: FrameOutput) -> Self {
+        Chip8Frame {
+            display_changed: output.display_changed,
+            beep: output.beep,
+            halted: output.halted,
+            instructions_executed: output.instructions_executed,
+        }
+    }
+}
+
+impl Chip8 {
+    fn fail(&mut self, e: crate::chip::Error) -> c_int {
+        // Keeps the description for chip8_last_error and reports failure
+        self.error = CString::new(e.to_string()).ok();
+        -1
+    }
+}
+
+/// Creates a chip with the default configuration. Free it with chip8_free.
+#[no_mangle]
+pub extern "C" fn chip8_new() -> *mut Chip8 {
+    Box::into_raw(Box::new(Chip8 {
+        chip: Chip::new(),
+        keypad: Keypad::new(),
+        error: None,
+    }))
+}
+
+/// Frees a chip made by chip8_new, null is ignored.
+///
+/// # Safety
+/// chip must be null or a pointer from chip8_new not already freed.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_free(chip: *mut Chip8) {
+    if !chip.is_null() {
+        drop(Box::from_raw(chip));
+    }
+}
+
+/// Loads len bytes of rom at the load address. Returns 0, or -1 if it
+/// doesn't fit.
+///
+/// # Safety
+/// chip must come from chip8_new and data must point at len readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_rom(chip: *mut Chip8, data: *const u8, len: usize) -> c_int {
+    let chip = &mut *chip;
+    let rom = if len == 0 { &[][..] } else { slice::from_raw_parts(data, len) };
+    match chip.chip.load_rom(rom) {
+        Ok(()) => 0,
+        Err(e) => chip.fail(e),
+    }
+}
+
+/// Restarts the loaded rom from the beginning.
+///
+/// # Safety
+/// chip must come from chip8_new.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_reset(chip: *mut Chip8) {
+    (*chip).chip.reset();
+}
+
+/// Presses or releases key 0x0 to 0xF, other keys are ignored.
+///
+/// # Safety
+/// chip must come from chip8_new.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_key(chip: *mut Chip8, key: u8, down: bool) {
+    let keypad = &mut (*chip).keypad;
+    if down {
+        keypad.press(key);
+    } else {
+        keypad.release(key);
+    }
+}
+
+/// Runs one 60Hz frame. Returns 0 and fills out if it isn't null, or -1
+/// if the rom failed, see chip8_last_error.
+///
+/// # Safety
+/// chip must come from chip8_new and out must be null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_frame(chip: *mut Chip8, out: *mut Chip8Frame) -> c_int {
+    let chip = &mut *chip;
+    match chip.chip.frame(&mut chip.keypad) {
+        Ok(output) => {
+            if !out.is_null() {
+                ptr::write(out, output.into());
+            }
+            0
+        }
+        Err(e) => chip.fail(e),
+    }
+}
+
+/// Pixels of a display plane, eight to a byte with the most significant bit
+/// leftmost and rows chip8_width / 8 bytes apart. Valid until the next call
+/// that runs the chip.
+///
+/// # Safety
+/// chip must come from chip8_new.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_display_ptr(chip: *const Chip8, plane: usize) -> *const u8 {
+    (*chip).chip.plane(plane.min(1)).as_ptr()
+}
+
+/// Bytes in a display plane at the current resolution.
+///
+/// # Safety
+/// chip must come from chip8_new.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_display_len(chip: *const Chip8) -> usize {
+    (*chip).chip.plane(0).len()
+}
+
+/// Display width in pixels.
+///
+/// # Safety
+/// chip must come from chip8_new.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_width(chip: *const Chip8) -> usize {
+    (*chip).chip.width()
+}
+
+/// Display height in pixels.
+///
+/// # Safety
+/// chip must come from chip8_new.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_height(chip: *const Chip8) -> usize {
+    (*chip).chip.height()
+}
+
+/// Whether a tone should be playing.
+///
+/// # Safety
+/// chip must come from chip8_new.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_tone(chip: *const Chip8) -> bool {
+    (*chip).chip.tone()
+}
+
+/// Describes the last failure, or null if nothing has failed. Valid until
+/// the next failure or chip8_free.
+///
+/// # Safety
+/// chip must come from chip8_new.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_last_error(chip: *const Chip8) -> *const c_char {
+    match &(*chip).error {
+        Some(error) => error.as_ptr(),
+        None => ptr::null(),
+    }
+}
@@ -0,0 +1,96 @@
+use std::thread;
+
+use crate::chip::{Chip, Error, FrameOutput};
+use crate::keypad::Keypad;
+
+// Runs many independent chips side by side across threads, for searches,
+// fuzzing and other experiments that need lots of runs of the same rom
+pub struct Fleet<'rom> {
+    chips : Vec<Chip<'rom>>,
+    keypads : Vec<Keypad>,
+    threads : usize,                // Worker threads, one per core unless set
+}
+
+impl<'rom> Fleet<'rom> {
+    pub fn new(template: &Chip<'rom>, seeds: &[u64]) -> Self {
+        // One copy of template, rom and all, for each seed
+        let chips = seeds.iter().map(|&seed| {
+            let mut chip = template.clone();
+            chip.set_seed(seed);
+            chip
+        }).collect();
+        Self::from_chips(chips)
+    }
+
+    pub fn from_chips(chips: Vec<Chip<'rom>>) -> Self {
+        // Takes chips that are already set up, each can differ in any way
+        let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        Fleet {
+            keypads : chips.iter().map(|_| Keypad::new()).collect(),
+            chips,
+            threads,
+        }
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    pub fn len(&self) -> usize {
+        self.chips.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chips.is_empty()
+    }
+
+    pub fn chips(&self) -> &[Chip<'rom>] {
+        &self.chips
+    }
+
+    pub fn chips_mut(&mut self) -> &mut [Chip<'rom>] {
+        &mut self.chips
+    }
+
+    pub fn into_chips(self) -> Vec<Chip<'rom>> {
+        self.chips
+    }
+
+    pub fn run<F>(&mut self, frames: usize, input: F) -> Vec<Result<Vec<FrameOutput>, Error>>
+    where
+        F: Fn(usize, usize) -> [bool; 16] + Sync,
+    {
+        // Runs every chip for up to frames frames, asking input for the keys
+        // held by chip n on frame f. A chip stops early once it halts or fails,
+        // and its result holds the frames it did run or the error
+        let per_thread = self.chips.len().div_ceil(self.threads);
+        if per_thread == 0 {
+            return Vec::new();
+        }
+        let input = &input;
+        thread::scope(|scope| {
+            let workers: Vec<_> = self.chips.chunks_mut(per_thread)
+                .zip(self.keypads.chunks_mut(per_thread))
+                .enumerate()
+                .map(|(chunk, (chips, keypads))| {
+                    scope.spawn(move || {
+                        chips.iter_mut().zip(keypads.iter_mut()).enumerate().map(|(i, (chip, keypad))| -> Result<_, Error> {
+                            let n = chunk * per_thread + i;
+                            let mut outputs = Vec::with_capacity(frames);
+                            for f in 0..frames {
+                                keypad.set_keys(input(n, f));
+                                let output = chip.frame(keypad)?;
+                                outputs.push(output);
+                                if output.halted {
+                                    break;
+                                }
+                            }
+                            Ok(outputs)
+                        }).collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            workers.into_iter().flat_map(|worker| worker.join().expect("fleet worker panicked")).collect()
+        })
+    }
+}
@@ -0,0 +1,268 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+#[derive (Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    Cls,                                // 00E0
+    Ret,                                // 00EE
+    Jp { nnn: u16 },                    // 1nnn
+    Call { nnn: u16 },                  // 2nnn
+    SeByte { x: u8, kk: u8 },           // 3xkk
+    SneByte { x: u8, kk: u8 },          // 4xkk
+    SeReg { x: u8, y: u8 },             // 5xy0
+    LdByte { x: u8, kk: u8 },           // 6xkk
+    AddByte { x: u8, kk: u8 },          // 7xkk
+    LdReg { x: u8, y: u8 },             // 8xy0
+    Or { x: u8, y: u8 },                // 8xy1
+    And { x: u8, y: u8 },               // 8xy2
+    Xor { x: u8, y: u8 },               // 8xy3
+    AddReg { x: u8, y: u8 },            // 8xy4
+    Sub { x: u8, y: u8 },               // 8xy5
+    Shr { x: u8, y: u8 },               // 8xy6
+    Subn { x: u8, y: u8 },              // 8xy7
+    Shl { x: u8, y: u8 },               // 8xyE
+    SneReg { x: u8, y: u8 },            // 9xy0
+    LdI { nnn: u16 },                   // Annn
+    JpV0 { nnn: u16 },                  // Bnnn
+    Rnd { x: u8, kk: u8 },              // Cxkk
+    Drw { x: u8, y: u8, n: u8 },        // Dxyn
+    Skp { x: u8 },                      // Ex9E
+    Sknp { x: u8 },                     // ExA1
+    LdVxDt { x: u8 },                   // Fx07
+    LdVxK { x: u8 },                    // Fx0A
+    LdDtVx { x: u8 },                   // Fx15
+    LdStVx { x: u8 },                   // Fx18
+    AddI { x: u8 },                     // Fx1E
+    LdF { x: u8 },                      // Fx29
+    LdB { x: u8 },                      // Fx33
+    LdMemVx { x: u8 },                  // Fx55
+    LdVxMem { x: u8 },                  // Fx65
+    ScrollDown { n: u8 },               // 00Cn, SCHIP
+    ScrollRight,                        // 00FB, SCHIP
+    ScrollLeft,                         // 00FC, SCHIP
+    Exit,                               // 00FD, SCHIP
+    Low,                                // 00FE, SCHIP
+    High,                               // 00FF, SCHIP
+    LdHf { x: u8 },                     // Fx30, SCHIP
+    LdRVx { x: u8 },                    // Fx75, SCHIP
+    LdVxR { x: u8 },                    // Fx85, SCHIP
+    ScrollUp { n: u8 },                 // 00Dn, XO-CHIP
+    Save { x: u8, y: u8 },              // 5xy2, XO-CHIP
+    Load { x: u8, y: u8 },              // 5xy3, XO-CHIP
+    LdILong { nnnn: u16 },              // F000 nnnn, XO-CHIP, the address is the next word
+    Plane { n: u8 },                    // Fn01, XO-CHIP
+    Audio,                              // F002, XO-CHIP
+    Pitch { x: u8 },                    // Fx3A, XO-CHIP
+    Unknown(u16),
+}
+
+pub fn decode(opcode: u16) -> Instruction {
+    // Splits an opcode into its nibbles and works out which instruction it is
+    let nibbles = (
+        ((opcode & 0xF000) >> 12) as u8,
+        ((opcode & 0x0F00) >> 8) as u8,
+        ((opcode & 0x00F0) >> 4) as u8,
+        (opcode & 0x000F) as u8,
+    );
+
+    let nnn = opcode & 0x0FFF;
+    let kk = (opcode & 0x00FF) as u8;
+    let x = nibbles.1;
+    let y = nibbles.2;
+    let n = nibbles.3;
+
+    match nibbles {
+        (0x00, 0x00, 0x0e, 0x00) => Instruction::Cls,
+        (0x00, 0x00, 0x0e, 0x0e) => Instruction::Ret,
+        (0x00, 0x00, 0x0c, _) => Instruction::ScrollDown { n },
+        (0x00, 0x00, 0x0d, _) => Instruction::ScrollUp { n },
+        (0x00, 0x00, 0x0f, 0x0b) => Instruction::ScrollRight,
+        (0x00, 0x00, 0x0f, 0x0c) => Instruction::ScrollLeft,
+        (0x00, 0x00, 0x0f, 0x0d) => Instruction::Exit,
+        (0x00, 0x00, 0x0f, 0x0e) => Instruction::Low,
+        (0x00, 0x00, 0x0f, 0x0f) => Instruction::High,
+        (0x01, _, _, _) => Instruction::Jp { nnn },
+        (0x02, _, _, _) => Instruction::Call { nnn },
+        (0x03, _, _, _) => Instruction::SeByte { x, kk },
+        (0x04, _, _, _) => Instruction::SneByte { x, kk },
+        (0x05, _, _, 0x00) => Instruction::SeReg { x, y },
+        (0x05, _, _, 0x02) => Instruction::Save { x, y },
+        (0x05, _, _, 0x03) => Instruction::Load { x, y },
+        (0x06, _, _, _) => Instruction::LdByte { x, kk },
+        (0x07, _, _, _) => Instruction::AddByte { x, kk },
+        (0x08, _, _, 0x00) => Instruction::LdReg { x, y },
+        (0x08, _, _, 0x01) => Instruction::Or { x, y },
+        (0x08, _, _, 0x02) => Instruction::And { x, y },
+        (0x08, _, _, 0x03) => Instruction::Xor { x, y },
+        (0x08, _, _, 0x04) => Instruction::AddReg { x, y },
+        (0x08, _, _, 0x05) => Instruction::Sub { x, y },
+        (0x08, _, _, 0x06) => Instruction::Shr { x, y },
+        (0x08, _, _, 0x07) => Instruction::Subn { x, y },
+        (0x08, _, _, 0x0e) => Instruction::Shl { x, y },
+        (0x09, _, _, 0x00) => Instruction::SneReg { x, y },
+        (0x0a, _, _, _) => Instruction::LdI { nnn },
+        (0x0b, _, _, _) => Instruction::JpV0 { nnn },
+        (0x0c, _, _, _) => Instruction::Rnd { x, kk },
+        (0x0d, _, _, _) => Instruction::Drw { x, y, n },
+        (0x0e, _, 0x09, 0x0e) => Instruction::Skp { x },
+        (0x0e, _, 0x0a, 0x01) => Instruction::Sknp { x },
+        (0x0f, 0x00, 0x00, 0x00) => Instruction::LdILong { nnnn: 0 },
+        (0x0f, _, 0x00, 0x01) => Instruction::Plane { n: x },
+        (0x0f, 0x00, 0x00, 0x02) => Instruction::Audio,
+        (0x0f, _, 0x00, 0x07) => Instruction::LdVxDt { x },
+        (0x0f, _, 0x00, 0x0a) => Instruction::LdVxK { x },
+        (0x0f, _, 0x01, 0x05) => Instruction::LdDtVx { x },
+        (0x0f, _, 0x01, 0x08) => Instruction::LdStVx { x },
+        (0x0f, _, 0x01, 0x0e) => Instruction::AddI { x },
+        (0x0f, _, 0x02, 0x09) => Instruction::LdF { x },
+        (0x0f, _, 0x03, 0x00) => Instruction::LdHf { x },
+        (0x0f, _, 0x03, 0x03) => Instruction::LdB { x },
+        (0x0f, _, 0x03, 0x0a) => Instruction::Pitch { x },
+        (0x0f, _, 0x05, 0x05) => Instruction::LdMemVx { x },
+        (0x0f, _, 0x06, 0x05) => Instruction::LdVxMem { x },
+        (0x0f, _, 0x07, 0x05) => Instruction::LdRVx { x },
+        (0x0f, _, 0x08, 0x05) => Instruction::LdVxR { x },
+        _ => Instruction::Unknown(opcode),
+    }
+}
+
+pub fn decode_at(bytes: &[u8], offset: usize) -> (Instruction, usize) {
+    // Decodes the instruction at offset, returning it with its size in bytes
+    // Unlike decode, this reads the address word of F000 nnnn
+    let word = |at: usize| {
+        let hi = bytes.get(at).copied().unwrap_or(0) as u16;
+        let lo = bytes.get(at + 1).copied().unwrap_or(0) as u16;
+        hi << 8 | lo
+    };
+    match decode(word(offset)) {
+        Instruction::LdILong { .. } => (Instruction::LdILong { nnnn: word(offset + 2) }, 4),
+        instruction => (instruction, 2),
+    }
+}
+
+impl Instruction {
+    pub fn size(&self) -> usize {
+        // Bytes the instruction takes up in memory
+        match self {
+            Instruction::LdILong { .. } => 4,
+            _ => 2,
+        }
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Instruction::Cls => "CLS",
+            Instruction::Ret => "RET",
+            Instruction::Jp { .. } | Instruction::JpV0 { .. } => "JP",
+            Instruction::Call { .. } => "CALL",
+            Instruction::SeByte { .. } | Instruction::SeReg { .. } => "SE",
+            Instruction::SneByte { .. } | Instruction::SneReg { .. } => "SNE",
+            Instruction::LdByte { .. } | Instruction::LdReg { .. } | Instruction::LdI { .. }
+            | Instruction::LdVxDt { .. } | Instruction::LdVxK { .. } | Instruction::LdDtVx { .. }
+            | Instruction::LdStVx { .. } | Instruction::LdF { .. } | Instruction::LdB { .. }
+            | Instruction::LdMemVx { .. } | Instruction::LdVxMem { .. } | Instruction::LdHf { .. }
+            | Instruction::LdRVx { .. } | Instruction::LdVxR { .. } | Instruction::LdILong { .. } => "LD",
+            Instruction::AddByte { .. } | Instruction::AddReg { .. } | Instruction::AddI { .. } => "ADD",
+            Instruction::Or { .. } => "OR",
+            Instruction::And { .. } => "AND",
+            Instruction::Xor { .. } => "XOR",
+            Instruction::Sub { .. } => "SUB",
+            Instruction::Shr { .. } => "SHR",
+            Instruction::Subn { .. } => "SUBN",
+            Instruction::Shl { .. } => "SHL",
+            Instruction::Rnd { .. } => "RND",
+            Instruction::Drw { .. } => "DRW",
+            Instruction::Skp { .. } => "SKP",
+            Instruction::Sknp { .. } => "SKNP",
+            Instruction::ScrollDown { .. } => "SCD",
+            Instruction::ScrollRight => "SCR",
+            Instruction::ScrollLeft => "SCL",
+            Instruction::Exit => "EXIT",
+            Instruction::Low => "LOW",
+            Instruction::High => "HIGH",
+            Instruction::ScrollUp { .. } => "SCU",
+            Instruction::Save { .. } => "SAVE",
+            Instruction::Load { .. } => "LOAD",
+            Instruction::Plane { .. } => "PLANE",
+            Instruction::Audio => "AUDIO",
+            Instruction::Pitch { .. } => "PITCH",
+            Instruction::Unknown(_) => "DATA",
+        }
+    }
+
+    pub fn operands(&self) -> Vec<(&'static str, u16)> {
+        // The decoded fields of the instruction by name
+        match *self {
+            Instruction::Cls | Instruction::Ret | Instruction::ScrollRight | Instruction::ScrollLeft
+            | Instruction::Exit | Instruction::Low | Instruction::High | Instruction::Audio => vec![],
+            Instruction::LdILong { nnnn } => vec![("nnnn", nnnn)],
+            Instruction::ScrollDown { n } | Instruction::ScrollUp { n } | Instruction::Plane { n } => {
+                vec![("n", n as u16)]
+            }
+            Instruction::Jp { nnn } | Instruction::Call { nnn } | Instruction::LdI { nnn }
+            | Instruction::JpV0 { nnn } => vec![("nnn", nnn)],
+            Instruction::SeByte { x, kk } | Instruction::SneByte { x, kk } | Instruction::LdByte { x, kk }
+            | Instruction::AddByte { x, kk } | Instruction::Rnd { x, kk } => {
+                vec![("x", x as u16), ("kk", kk as u16)]
+            }
+            Instruction::SeReg { x, y } | Instruction::LdReg { x, y } | Instruction::Or { x, y }
+            | Instruction::And { x, y } | Instruction::Xor { x, y } | Instruction::AddReg { x, y }
+            | Instruction::Sub { x, y } | Instruction::Shr { x, y } | Instruction::Subn { x, y }
+            | Instruction::Shl { x, y } | Instruction::SneReg { x, y } | Instruction::Save { x, y }
+            | Instruction::Load { x, y } => {
+                vec![("x", x as u16), ("y", y as u16)]
+            }
+            Instruction::Drw { x, y, n } => vec![("x", x as u16), ("y", y as u16), ("n", n as u16)],
+            Instruction::Skp { x } | Instruction::Sknp { x } | Instruction::LdVxDt { x }
+            | Instruction::LdVxK { x } | Instruction::LdDtVx { x } | Instruction::LdStVx { x }
+            | Instruction::AddI { x } | Instruction::LdF { x } | Instruction::LdB { x }
+            | Instruction::LdMemVx { x } | Instruction::LdVxMem { x } | Instruction::LdHf { x }
+            | Instruction::LdRVx { x } | Instruction::LdVxR { x } | Instruction::Pitch { x } => {
+                vec![("x", x as u16)]
+            }
+            Instruction::Unknown(opcode) => vec![("value", opcode)],
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Formats the instruction as assembly, e.g. "LD V3, 0x1F"
+        let m = self.mnemonic();
+        match *self {
+            Instruction::Cls | Instruction::Ret | Instruction::ScrollRight | Instruction::ScrollLeft
+            | Instruction::Exit | Instruction::Low | Instruction::High | Instruction::Audio => write!(f, "{}", m),
+            Instruction::ScrollDown { n } | Instruction::ScrollUp { n } | Instruction::Plane { n } => {
+                write!(f, "{} {}", m, n)
+            }
+            Instruction::LdILong { nnnn } => write!(f, "{} I, 0x{:04X}", m, nnnn),
+            Instruction::Save { x, y } | Instruction::Load { x, y } => write!(f, "{} V{:X} - V{:X}", m, x, y),
+            Instruction::Pitch { x } => write!(f, "{} V{:X}", m, x),
+            Instruction::Jp { nnn } | Instruction::Call { nnn } => write!(f, "{} 0x{:03X}", m, nnn),
+            Instruction::LdI { nnn } => write!(f, "{} I, 0x{:03X}", m, nnn),
+            Instruction::JpV0 { nnn } => write!(f, "{} V0, 0x{:03X}", m, nnn),
+            Instruction::SeByte { x, kk } | Instruction::SneByte { x, kk } | Instruction::LdByte { x, kk }
+            | Instruction::AddByte { x, kk } | Instruction::Rnd { x, kk } => write!(f, "{} V{:X}, 0x{:02X}", m, x, kk),
+            Instruction::SeReg { x, y } | Instruction::LdReg { x, y } | Instruction::Or { x, y }
+            | Instruction::And { x, y } | Instruction::Xor { x, y } | Instruction::AddReg { x, y }
+            | Instruction::Sub { x, y } | Instruction::Shr { x, y } | Instruction::Subn { x, y }
+            | Instruction::Shl { x, y } | Instruction::SneReg { x, y } => write!(f, "{} V{:X}, V{:X}", m, x, y),
+            Instruction::Drw { x, y, n } => write!(f, "{} V{:X}, V{:X}, {}", m, x, y, n),
+            Instruction::Skp { x } | Instruction::Sknp { x } => write!(f, "{} V{:X}", m, x),
+            Instruction::LdVxDt { x } => write!(f, "{} V{:X}, DT", m, x),
+            Instruction::LdVxK { x } => write!(f, "{} V{:X}, K", m, x),
+            Instruction::LdDtVx { x } => write!(f, "{} DT, V{:X}", m, x),
+            Instruction::LdStVx { x } => write!(f, "{} ST, V{:X}", m, x),
+            Instruction::AddI { x } => write!(f, "{} I, V{:X}", m, x),
+            Instruction::LdF { x } => write!(f, "{} F, V{:X}", m, x),
+            Instruction::LdB { x } => write!(f, "{} B, V{:X}", m, x),
+            Instruction::LdMemVx { x } => write!(f, "{} [I], V{:X}", m, x),
+            Instruction::LdVxMem { x } => write!(f, "{} V{:X}, [I]", m, x),
+            Instruction::LdHf { x } => write!(f, "{} HF, V{:X}", m, x),
+            Instruction::LdRVx { x } => write!(f, "{} R, V{:X}", m, x),
+            Instruction::LdVxR { x } => write!(f, "{} V{:X}, R", m, x),
+            Instruction::Unknown(opcode) => write!(f, "{} 0x{:04X}", m, opcode),
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use alloc::collections::VecDeque;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum KeyEvent {
+    Pressed(u8),
+    Released(u8),
+}
+
+// The 16 key hex keypad. Frontends press and release keys as the host reports
+// them, and every change is queued so the chip sees presses shorter than a
+// frame and the release FX0A waits for
+#[derive (Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Keypad {
+    keys : u16,                         // Bit n is set while key n is down
+    events : VecDeque<KeyEvent>,        // Changes the chip hasn't consumed yet
+}
+
+impl Keypad {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn press(&mut self, key: u8) {
+        // Puts key down, keys above 0xF don't exist and are ignored
+        if key < 16 && !self.is_down(key) {
+            self.keys |= 1 << key;
+            self.events.push_back(KeyEvent::Pressed(key));
+        }
+    }
+
+    pub fn release(&mut self, key: u8) {
+        // Lets key back up
+        if key < 16 && self.is_down(key) {
+            self.keys &= !(1 << key);
+            self.events.push_back(KeyEvent::Released(key));
+        }
+    }
+
+    pub fn set_keys(&mut self, keys: [bool; 16]) {
+        // Presses and releases whatever differs from keys, for frontends
+        // that poll the whole keypad at once
+        for (key, &down) in keys.iter().enumerate() {
+            if down {
+                self.press(key as u8);
+            } else {
+                self.release(key as u8);
+            }
+        }
+    }
+
+    pub fn is_down(&self, key: u8) -> bool {
+        key < 16 && self.keys & (1 << key) != 0
+    }
+
+    pub fn bits(&self) -> u16 {
+        // The held keys, bit n for key n
+        self.keys
+    }
+
+    pub fn keys(&self) -> [bool; 16] {
+        let mut keys = [false; 16];
+        for (key, down) in keys.iter_mut().enumerate() {
+            *down = self.is_down(key as u8);
+        }
+        keys
+    }
+
+    pub fn pop_event(&mut self) -> Option<KeyEvent> {
+        // Takes the oldest change the chip hasn't seen
+        self.events.pop_front()
+    }
+}
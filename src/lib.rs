@@ -0,0 +1,89 @@
+//! A CHIP-8 interpreter core with SUPER-CHIP and XO-CHIP support.
+//!
+//! The core has no windowing or audio dependencies. A frontend creates a
+//! [`Chip`], loads a rom and calls [`Chip::frame`] sixty times a second with
+//! the [`Keypad`], then draws [`Chip::plane`] and plays a tone while
+//! [`Chip::tone`] is true.
+//!
+//! Without the default `std` feature the core is `no_std` and only needs
+//! `alloc`. The rng then starts from a fixed seed unless given one, and frame
+//! timing is unavailable.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod analysis;
+pub mod assembler;
+pub mod builder;
+pub mod chip;
+pub mod clock;
+pub mod condition;
+pub mod coverage;
+pub mod decompile;
+pub mod disasm;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "std")]
+pub mod fleet;
+pub mod fonts;
+pub mod instruction;
+pub mod keypad;
+pub mod lint;
+pub mod observer;
+pub mod pack;
+pub mod platform;
+pub mod quirks;
+pub mod romdb;
+pub mod sha1;
+pub mod snapshot;
+pub mod sprites;
+pub mod symbols;
+pub mod transpile;
+#[cfg(feature = "async")]
+pub mod stream;
+#[cfg(feature = "serde")]
+mod serde_planes;
+
+pub use analysis::{analyze, Analysis};
+pub use assembler::{assemble, AssembleError, Assembly};
+pub use builder::ChipBuilder;
+pub use chip::{Access, Breakpoint, CallFrame, Chip, DrawBreak, Error, FrameOutput, IllegalPolicy, Registers, StepInfo, TraceEntry, TrapHook, Variant, Warning, WatchHit, Watchpoint};
+#[cfg(feature = "std")]
+pub use clock::RealTimeClock;
+pub use clock::{Clock, FixedStepClock, ManualClock};
+pub use condition::Condition;
+pub use decompile::decompile;
+#[cfg(feature = "std")]
+pub use fleet::Fleet;
+pub use instruction::{decode, decode_at, Instruction};
+pub use keypad::{KeyEvent, Keypad};
+pub use observer::ChipObserver;
+pub use platform::Platform;
+pub use quirks::Quirks;
+pub use romdb::{RomDb, RomInfo};
+pub use snapshot::{diff, Snapshot, StateDiff};
+pub use sprites::{find_sprites, Sprite};
+pub use symbols::Symbols;
+pub use transpile::transpile;
+#[cfg(feature = "async")]
+pub use stream::FrameStream;
+
+/// Width of the standard display in pixels
+pub const CHIP8_WIDTH: usize = 64;
+/// Height of the standard display in pixels
+pub const CHIP8_HEIGHT: usize = 32;
+/// Width of the SUPER-CHIP and XO-CHIP high resolution display
+pub const SCHIP_WIDTH: usize = 128;
+/// Height of the SUPER-CHIP and XO-CHIP high resolution display
+pub const SCHIP_HEIGHT: usize = 64;
+/// Memory size of the classic interpreters
+pub const CHIP8_MEM: usize = 0x1000;
+/// Memory size on XO-CHIP
+pub const XO_CHIP_MEM: usize = 0x10000;
+/// Address roms are loaded at and start running from
+pub const ROM_SIZE : usize = 0x200;
+/// Bytes in a standard instruction
+pub const OPCODE_SIZE: usize = 2;
+/// Length of a 60Hz frame in microseconds
+pub const FRAME_TIME: isize = 16666;
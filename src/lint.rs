@@ -0,0 +1,132 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::chip::Chip;
+use crate::instruction::Instruction;
+use crate::keypad::Keypad;
+use crate::quirks::Quirks;
+use crate::snapshot::{diff, Register, StateDiff};
+
+// The quirks a rom is checked against, by the names reports use
+pub const QUIRK_NAMES: [&str; 7] = ["shift", "load-store", "jump", "vf-reset", "display-wait", "clipping", "key-press"];
+
+// Instructions stepped through to find where a frame went differently
+const LOCATE_STEPS: usize = 10_000;
+
+// Where a run with a quirk flipped first went differently
+#[derive (Debug, Clone, PartialEq)]
+pub struct Divergence {
+    pub quirk: &'static str,
+    pub frame: usize,
+    pub pc: u16,                        // The instruction that did it, or where the frame began
+    pub instruction: Option<Instruction>,
+    pub what: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "depends on the {} quirk: frame {}, 0x{:03X}", self.quirk, self.frame, self.pc)?;
+        if let Some(instruction) = self.instruction {
+            write!(f, " {}", instruction)?;
+        }
+        write!(f, ": {}", self.what)
+    }
+}
+
+pub fn flipped(quirks: Quirks, name: &str) -> Quirks {
+    // The quirks with the named one the other way round
+    let mut quirks = quirks;
+    match name {
+        "shift" => quirks.shift_uses_vy = !quirks.shift_uses_vy,
+        "load-store" => quirks.load_store_inc_i = !quirks.load_store_inc_i,
+        "jump" => quirks.jump_uses_vx = !quirks.jump_uses_vx,
+        "vf-reset" => quirks.vf_reset = !quirks.vf_reset,
+        "display-wait" => quirks.display_wait = !quirks.display_wait,
+        "clipping" => quirks.clipping = !quirks.clipping,
+        "key-press" => quirks.key_press_only = !quirks.key_press_only,
+        _ => {}
+    }
+    quirks
+}
+
+fn describe(diff: &StateDiff) -> String {
+    // The first few things a diff found, registers first
+    let mut parts: Vec<String> = diff.registers.iter().take(4).map(|&(register, a, b)| {
+        let name = match register {
+            Register::V(n) => format!("V{:X}", n),
+            Register::I => String::from("I"),
+            Register::Pc => String::from("PC"),
+            Register::Sp => String::from("SP"),
+            Register::Dt => String::from("DT"),
+            Register::St => String::from("ST"),
+        };
+        format!("{} 0x{:02X} vs 0x{:02X}", name, a, b)
+    }).collect();
+    if let Some(range) = diff.memory.first() {
+        parts.push(format!("memory 0x{:03X}-0x{:03X}", range.start, range.end));
+    }
+    if diff.resolution_changed {
+        parts.push(String::from("resolution"));
+    } else if let Some(row) = diff.display_rows.first() {
+        parts.push(format!("screen row {}", row));
+    }
+    parts.join(", ")
+}
+
+fn locate(quirk: &'static str, frame: usize, mut a: Chip, mut b: Chip) -> Divergence {
+    // Steps copies of both chips from the start of the frame that differed
+    // to the instruction that made them differ. Quirks that only change
+    // timing, like display-wait, don't show up this way, so the divergence
+    // is put at the start of the frame
+    let start = a.pc();
+    for _ in 0..LOCATE_STEPS {
+        let pc = a.pc();
+        let (sa, sb) = (a.step(), b.step());
+        let instruction = sa.as_ref().ok().map(|info| info.instruction);
+        let what = match (&sa, &sb) {
+            (Err(e), Ok(_)) => format!("crashed without it: {}", e),
+            (Ok(_), Err(e)) => format!("crashed with it: {}", e),
+            (Err(_), Err(_)) => break,
+            (Ok(_), Ok(_)) => describe(&diff(&a.snapshot(), &b.snapshot())),
+        };
+        if !what.is_empty() {
+            return Divergence { quirk, frame, pc, instruction, what };
+        }
+        if a.halted() || b.halted() || sa.is_err() || sa.is_ok_and(|info| info.waiting_for_key) {
+            break;
+        }
+    }
+    Divergence { quirk, frame, pc: start, instruction: None, what: String::from("timing") }
+}
+
+pub fn lint_quirk(chip: &Chip, quirk: &'static str, frames: usize) -> Option<Divergence> {
+    // Runs the chip as it is and with the quirk flipped side by side with no
+    // keys pressed, comparing their state after every frame
+    let mut a = chip.clone();
+    let mut b = chip.clone();
+    b.set_quirks(flipped(chip.quirks(), quirk));
+    let (mut keys_a, mut keys_b) = (Keypad::new(), Keypad::new());
+    for frame in 0..frames {
+        let (before_a, before_b) = (a.clone(), b.clone());
+        let (ra, rb) = (a.frame(&mut keys_a), b.frame(&mut keys_b));
+        let differs = match (&ra, &rb) {
+            (Err(_), Err(_)) => return None,
+            (Ok(_), Ok(_)) => a.halted() != b.halted() || a.snapshot() != b.snapshot(),
+            _ => true,
+        };
+        if differs {
+            return Some(locate(quirk, frame, before_a, before_b));
+        }
+        if a.halted() {
+            return None;
+        }
+    }
+    None
+}
+
+pub fn lint(chip: &Chip, frames: usize) -> Vec<(&'static str, Option<Divergence>)> {
+    // Each quirk with where flipping it first changed the run, if it did
+    QUIRK_NAMES.iter().map(|&quirk| (quirk, lint_quirk(chip, quirk, frames))).collect()
+}
@@ -1,24 +1,776 @@
-use rand;
-use sdl2;
-
 mod drivers;
-mod chip;
-mod fonts;
 
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
-use std::env;
 
-use drivers::{AudioDriver, DisplayDriver, GameDriver, InputDriver};
-use chip::Chip;
+use drivers::{print_call_stack, print_history, run_test, write_png, Audio, CartridgeDriver, DebugConsole, DisplayBackend, DisplayDriver, GameDriver, InputDriver, Reference, SpriteScan, TraceLog};
+#[cfg(feature = "gdb")]
+use drivers::GdbStub;
+#[cfg(feature = "tui")]
+use drivers::{KeyMap, NullAudio, TuiDisplay, TuiInput};
+use chip8::{analyze, assemble, coverage, decompile, disasm, lint, pack, sprites, transpile, Chip, IllegalPolicy, Keypad, Platform, RealTimeClock, RomDb, Symbols, Variant};
+
+// Subcommands for tools that work on a rom or its source without running it
+const TOOLS: [&str; 10] = ["disasm", "asm", "analyze", "sprites", "diff", "transpile", "test-suite", "decompile", "pack", "lint"];
+// Frames the sprites tool runs a rom for, to catch sprites found at run time
+const SPRITE_SCAN_FRAMES: usize = 600;
+// Sprites to a row of a sheet, and how much larger a sheet's pixels are as a PNG
+const SHEET_COLUMNS: usize = 8;
+const SHEET_SCALE: usize = 8;
+// Frames lint runs a rom for under each quirk
+const LINT_FRAMES: usize = 600;
+// Extensions of the roms test-suite runs
+const TEST_ROM_EXTENSIONS: [&str; 5] = ["ch8", "c8", "sc8", "xo8", "8o"];
+// Instructions the debuggers can step back through
+const DEBUG_UNDO: usize = 10_000;
+// Instructions shown leading up to a crash
+const CRASH_HISTORY: usize = 16;
+
+#[derive (Debug, Clone, Default, PartialEq)]
+struct Options {
+    rom_path: String,
+    more_paths: Vec<String>,
+    ipf: Option<usize>,
+    hz: Option<usize>,
+    hires: bool,
+    platform: Option<Platform>,
+    strict: bool,
+    seed: Option<u64>,
+    load_addr: Option<usize>,
+    vip_timing: bool,
+    memory: Option<usize>,
+    stack: Option<usize>,
+    debug: bool,
+    trace: Option<String>,
+    trace_range: Option<(usize, usize)>,
+    symbols: Option<String>,
+    output: Option<String>,
+    frames: Option<usize>,
+    rom_db: Option<String>,
+    coverage: Option<String>,
+    update: bool,
+    blobs: Vec<(String, Option<usize>)>,
+    align: Option<usize>,
+    #[cfg(feature = "gdb")]
+    gdb: Option<u16>,
+}
+
+#[derive (Debug, PartialEq)]
+enum Command {
+    Run(Options),
+    #[cfg(feature = "tui")]
+    Tui(Options),
+    Disasm(Options),
+    Asm(Options),
+    Analyze(Options),
+    Sprites(Options),
+    Diff(Options),
+    Transpile(Options),
+    Decompile(Options),
+    Pack(Options),
+    Lint(Options),
+    TestSuite(Options),
+    Usage,
+}
+
+fn parse_command(args: &[String]) -> Command {
+    // Works out what the binary was asked to do from its arguments
+    let mut options = Options::default();
+    let mut tool = None;
+    #[cfg(feature = "tui")]
+    let mut tui = false;
+
+    // Tools come first, as in chip8 disasm game.ch8
+    let mut args = args.iter().skip(1).peekable();
+    if let Some(name) = args.peek().map(|arg| arg.as_str()).filter(|name| TOOLS.contains(name)) {
+        tool = Some(name);
+        args.next();
+    }
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--disasm" => tool = Some("disasm"),
+            #[cfg(feature = "tui")]
+            "--tui" => tui = true,
+            "--hires" => options.hires = true,
+            "--strict" => options.strict = true,
+            "--vip-timing" => options.vip_timing = true,
+            "--debug" => options.debug = true,
+            "--update" => options.update = true,
+            "--trace" => match args.next() {
+                Some(path) => options.trace = Some(path.to_string()),
+                None => return Command::Usage,
+            },
+            "-o" | "--output" => match args.next() {
+                Some(path) => options.output = Some(path.to_string()),
+                None => return Command::Usage,
+            },
+            "--coverage" => match args.next() {
+                Some(path) => options.coverage = Some(path.to_string()),
+                None => return Command::Usage,
+            },
+            "--rom-db" => match args.next() {
+                Some(path) => options.rom_db = Some(path.to_string()),
+                None => return Command::Usage,
+            },
+            "--blob" => match args.next().map(|blob| (blob, blob.split_once('@'))) {
+                Some((_, Some((path, addr)))) => match parse_address(addr) {
+                    Some(addr) => options.blobs.push((path.to_string(), Some(addr))),
+                    None => return Command::Usage,
+                },
+                Some((path, None)) => options.blobs.push((path.to_string(), None)),
+                None => return Command::Usage,
+            },
+            "--align" => match args.next().and_then(|value| parse_address(value)) {
+                Some(align) if align > 0 => options.align = Some(align),
+                _ => return Command::Usage,
+            },
+            "--symbols" => match args.next() {
+                Some(path) => options.symbols = Some(path.to_string()),
+                None => return Command::Usage,
+            },
+            "--trace-range" => match args.next().and_then(|value| parse_range(value)) {
+                Some(range) => options.trace_range = Some(range),
+                None => return Command::Usage,
+            },
+            #[cfg(feature = "gdb")]
+            "--gdb" => match args.next().and_then(|value| value.parse().ok()) {
+                Some(port) => options.gdb = Some(port),
+                None => return Command::Usage,
+            },
+            "--ipf" => match args.next().and_then(|value| value.parse().ok()) {
+                Some(ipf) => options.ipf = Some(ipf),
+                None => return Command::Usage,
+            },
+            "--platform" => match args.next().and_then(|name| Platform::from_name(name)) {
+                Some(platform) => options.platform = Some(platform),
+                None => return Command::Usage,
+            },
+            "--seed" => match args.next().and_then(|value| value.parse().ok()) {
+                Some(seed) => options.seed = Some(seed),
+                None => return Command::Usage,
+            },
+            "--load-addr" => match args.next().and_then(|value| parse_address(value)) {
+                Some(addr) => options.load_addr = Some(addr),
+                None => return Command::Usage,
+            },
+            "--mem" => match args.next().and_then(|value| parse_address(value)) {
+                Some(size) => options.memory = Some(size),
+                None => return Command::Usage,
+            },
+            "--frames" => match args.next().and_then(|value| value.parse().ok()) {
+                Some(frames) => options.frames = Some(frames),
+                None => return Command::Usage,
+            },
+            "--stack" => match args.next().and_then(|value| value.parse().ok()) {
+                Some(depth) => options.stack = Some(depth),
+                None => return Command::Usage,
+            },
+            "--eti" => options.load_addr = Some(0x600),
+            "--hz" => match args.next().and_then(|value| value.parse().ok()) {
+                Some(hz) => options.hz = Some(hz),
+                None => return Command::Usage,
+            },
+            flag if flag.starts_with("--") => return Command::Usage,
+            path if options.rom_path.is_empty() => options.rom_path = path.to_string(),
+            path => options.more_paths.push(path.to_string()),
+        }
+    }
+
+    // Only diff takes a second rom
+    if options.rom_path.is_empty() || options.more_paths.len() != (tool == Some("diff")) as usize {
+        return Command::Usage;
+    }
+    match tool {
+        Some("disasm") => return Command::Disasm(options),
+        Some("asm") => return Command::Asm(options),
+        Some("analyze") => return Command::Analyze(options),
+        Some("sprites") => return Command::Sprites(options),
+        Some("diff") => return Command::Diff(options),
+        Some("transpile") => return Command::Transpile(options),
+        Some("decompile") => return Command::Decompile(options),
+        Some("pack") => return Command::Pack(options),
+        Some("lint") => return Command::Lint(options),
+        Some("test-suite") => return Command::TestSuite(options),
+        _ => {}
+    }
+    #[cfg(feature = "tui")]
+    {
+        if tui {
+            return Command::Tui(options);
+        }
+    }
+    Command::Run(options)
+}
+
+fn parse_address(value: &str) -> Option<usize> {
+    // Accepts addresses and sizes in decimal or as 0x prefixed hex
+    match value.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
 
-const CHIP8_WIDTH: usize = 64;
-const CHIP8_HEIGHT: usize = 32;
-const CHIP8_MEM: usize = 0x1000;
-const ROM_SIZE : usize = 0x200;
-const OPCODE_SIZE: usize = 2;
-const FRAME_TIME: isize = 16666;
+fn parse_range(value: &str) -> Option<(usize, usize)> {
+    // Accepts start-end, the end being exclusive
+    let (start, end) = value.split_once('-')?;
+    Some((parse_address(start)?, parse_address(end)?))
+}
 
 fn main() {
-    
+    let args: Vec<String> = env::args().collect();
+    match parse_command(&args) {
+        Command::Run(options) => run(&options),
+        #[cfg(feature = "tui")]
+        Command::Tui(options) => run_tui(&options),
+        Command::Disasm(options) => run_disasm(&options),
+        Command::Asm(options) => run_asm(&options),
+        Command::Analyze(options) => run_analyze(&options),
+        Command::Sprites(options) => run_sprites(&options),
+        Command::Diff(options) => run_diff(&options),
+        Command::Transpile(options) => run_transpile(&options),
+        Command::Decompile(options) => run_decompile(&options),
+        Command::Pack(options) => run_pack(&options),
+        Command::Lint(options) => run_lint(&options),
+        Command::TestSuite(options) => run_test_suite(&options),
+        Command::Usage => eprintln!("usage: {0} [--tui] [--disasm] [--platform vip|chip48|schip|xochip] [--ipf n | --hz n | --vip-timing] [--hires] [--strict] [--debug | --gdb port] [--trace file [--trace-range start-end]] [--symbols file] [--coverage file[.html]] [--rom-db file] [--seed n] [--eti | --load-addr addr] [--mem size] [--stack depth] <rom>\n       {0} disasm [--platform name] [--symbols file] [--eti | --load-addr addr] <rom>\n       {0} asm [-o rom] [--symbols file] <source.8o>\n       {0} analyze [--eti | --load-addr addr] <rom>\n       {0} sprites [-o sheet.png] [--frames n] [--eti | --load-addr addr] <rom>\n       {0} diff [--eti | --load-addr addr] <rom> <rom>\n       {0} transpile [-o file.rs] [--eti | --load-addr addr] <rom>\n       {0} decompile [-o source.8o] [--symbols file] <rom>\n       {0} pack [-o rom] [--blob file[@addr]]... [--align n] [--platform name | --mem size] <rom>\n       {0} lint [--frames n] [--platform name] [--eti | --load-addr addr] <rom>\n       {0} test-suite [--update] [--platform name] <dir>", args[0]),
+    }
+}
+
+fn read_rom(path: &str) -> Option<(Vec<u8>, Symbols)> {
+    // Octo source is assembled on the way in so it can be run straight
+    // away, its labels standing in for a symbol file
+    if !path.ends_with(".8o") {
+        let cartridge_driver = CartridgeDriver::new(path);
+        return Some((cartridge_driver.rom[..cartridge_driver.size].to_vec(), Symbols::new()));
+    }
+    let assembled = fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|source| assemble(&source).map_err(|e| e.to_string()));
+    match assembled {
+        Ok(assembly) => Some((assembly.rom, assembly.symbols)),
+        Err(e) => {
+            eprintln!("error: {}: {}", path, e);
+            None
+        }
+    }
+}
+
+fn load_symbols(options: &Options, labels: Symbols) -> Symbols {
+    // Labels for the debuggers and the trace, those from the rom's own
+    // source if there's no file or it can't be read
+    let path = match &options.symbols {
+        Some(path) => path,
+        None => return labels,
+    };
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("warning: can't read symbols from {}: {}", path, e);
+            return labels;
+        }
+    };
+    Symbols::parse(&text).unwrap_or_else(|e| {
+        eprintln!("warning: can't read symbols from {}: {}", path, e);
+        labels
+    })
+}
+
+fn recommended(options: &Options, rom: &[u8]) -> (Options, Option<String>) {
+    // Looks the rom up in the database, filling in the settings it plays
+    // best with where none were given, and returns its title if known
+    let mut db = RomDb::builtin();
+    if let Some(path) = &options.rom_db {
+        match fs::read_to_string(path).map_err(|e| e.to_string()).and_then(|text| RomDb::parse(&text).map_err(|e| e.to_string())) {
+            Ok(extra) => db.extend(extra),
+            Err(e) => eprintln!("warning: can't read the rom database {}: {}", path, e),
+        }
+    }
+    let mut options = options.clone();
+    let info = match db.lookup(rom) {
+        Some(info) => info,
+        None => return (options, None),
+    };
+    if options.platform.is_none() {
+        options.platform = info.platform;
+    }
+    if options.ipf.is_none() && options.hz.is_none() {
+        options.ipf = info.ipf;
+    }
+    (options, Some(info.to_string()))
+}
+
+fn load_chip(options: &Options, rom: &[u8], symbols: &Symbols) -> Chip<'static> {
+    // Creates a chip configured from the options with the rom loaded
+    let mut builder = Chip::builder()
+        .two_page(options.hires)
+        .vip_cycle_timing(options.vip_timing);
+    if let Some(platform) = options.platform {
+        builder = builder.platform(platform);
+    }
+    if let Some(hz) = options.hz {
+        builder = builder.clock(hz);
+    }
+    if let Some(ipf) = options.ipf {
+        builder = builder.instructions_per_frame(ipf);
+    }
+    if let Some(seed) = options.seed {
+        builder = builder.seed(seed);
+    }
+    if let Some(addr) = options.load_addr {
+        builder = builder.load_address(addr);
+    }
+    if let Some(size) = options.memory {
+        builder = builder.memory_size(size);
+    }
+    if let Some(depth) = options.stack {
+        builder = builder.stack_depth(depth);
+    }
+    if options.strict {
+        builder = builder.illegal_policy(IllegalPolicy::Error);
+    }
+
+    let mut chip = builder.build();
+    // The chip decides whether the rom fits, with the memory size and load
+    // address it was given
+    if let Err(e) = chip.load_rom(rom) {
+        eprintln!("error: {}: {}", options.rom_path, e);
+        process::exit(1);
+    }
+    if options.coverage.is_some() {
+        chip.enable_coverage(true);
+    }
+    chip.enable_trace_buffer(CRASH_HISTORY);
+    if let Some(path) = &options.trace {
+        match TraceLog::create(path, symbols.clone()) {
+            Ok(log) => chip.set_observer(Some(Arc::new(log))),
+            Err(e) => eprintln!("warning: can't write the trace to {}: {}", path, e),
+        }
+    }
+    if let Some((start, end)) = options.trace_range {
+        chip.set_trace_range(Some(start as u16..end as u16));
+    }
+    chip
+}
+
+fn run_disasm(options: &Options) {
+    // Prints an annotated listing of the rom instead of running it
+    let (rom, labels) = match read_rom(&options.rom_path) {
+        Some(loaded) => loaded,
+        None => return,
+    };
+    let base = options.load_addr.unwrap_or(chip8::ROM_SIZE);
+    let symbols = load_symbols(options, labels);
+    let variant = options.platform.map_or(Variant::XoChip, |platform| platform.variant());
+    print!("{}", disasm::annotated_for(&rom, base, &symbols, variant));
+}
+
+fn run_analyze(options: &Options) {
+    // Prints what's code and data in the rom, its call graph, sprites and stray jumps
+    let (rom, _) = match read_rom(&options.rom_path) {
+        Some(loaded) => loaded,
+        None => return,
+    };
+    print!("{}", analyze(&rom, options.load_addr.unwrap_or(chip8::ROM_SIZE)));
+}
+
+fn write_coverage(options: &Options, chip: &Chip, rom: &[u8], symbols: &Symbols) {
+    // Writes what ran of the rom once a session is over, as an HTML heat
+    // view if the file is .html and a text report otherwise
+    let path = match &options.coverage {
+        Some(path) => path,
+        None => return,
+    };
+    let base = options.load_addr.unwrap_or(chip8::ROM_SIZE);
+    let covered = coverage::Coverage::new(rom, base, chip.execution_counts());
+    let report = if path.ends_with(".html") {
+        coverage::html(rom, &covered, symbols, &options.rom_path)
+    } else {
+        coverage::report(rom, &covered, symbols)
+    };
+    if let Err(e) = fs::write(path, report) {
+        eprintln!("error: can't write {}: {}", path, e);
+    }
+}
+
+fn run_lint(options: &Options) {
+    // Runs the rom with each quirk flipped in turn from its platform's, no
+    // keys held, and reports the first place each one changed what it did
+    let (rom, labels) = match read_rom(&options.rom_path) {
+        Some(loaded) => loaded,
+        None => return,
+    };
+    let (options, _) = recommended(options, &rom);
+    let chip = load_chip(&options, &rom, &labels);
+    let frames = options.frames.unwrap_or(LINT_FRAMES);
+    for (quirk, divergence) in lint::lint(&chip, frames) {
+        match divergence {
+            Some(divergence) => println!("{}", divergence),
+            None => println!("doesn't depend on the {} quirk in {} frames", quirk, frames),
+        }
+    }
+}
+
+fn run_sprites(options: &Options) {
+    // Finds the rom's sprites by walking it and then running it for a few
+    // seconds with no keys held, and saves them as a sheet or shows them
+    let (rom, labels) = match read_rom(&options.rom_path) {
+        Some(loaded) => loaded,
+        None => return,
+    };
+    let base = options.load_addr.unwrap_or(chip8::ROM_SIZE);
+    let mut drawn = analyze(&rom, base).sprites;
+
+    let scan = Arc::new(SpriteScan::new());
+    let mut chip = load_chip(options, &rom, &labels);
+    chip.set_observer(Some(scan.clone()));
+    let mut keypad = Keypad::new();
+    for _ in 0..options.frames.unwrap_or(SPRITE_SCAN_FRAMES) {
+        if chip.frame(&mut keypad).is_err() || chip.halted() {
+            break;
+        }
+    }
+    for (addr, len) in scan.drawn() {
+        let most = drawn.entry(addr).or_insert(0);
+        *most = len.max(*most);
+    }
+
+    let found = sprites::sprites_at(&rom, base, &drawn);
+    if found.is_empty() {
+        eprintln!("no sprites found in {}", options.rom_path);
+        return;
+    }
+    for sprite in &found {
+        println!("0x{:03X}  {}x{}", sprite.addr, sprite.width(), sprite.height());
+    }
+    let (pixels, width, height) = sprites::sheet(&found, SHEET_COLUMNS);
+    if let Some(path) = &options.output {
+        if let Err(e) = write_png(path, &pixels, width, height, SHEET_SCALE) {
+            eprintln!("error: can't write {}: {}", path, e);
+        }
+        return;
+    }
+
+    let sdl_context = sdl2::init().unwrap();
+    let mut display_driver = DisplayDriver::new(&sdl_context);
+    let mut input_driver = InputDriver::new(&sdl_context);
+    display_driver.draw(&pixels, width, height);
+    while input_driver.poll().is_ok() {
+        thread::sleep(Duration::from_millis(16));
+    }
+}
+
+fn run_diff(options: &Options) {
+    // Prints the instructions that differ between two roms
+    let (a, _) = match read_rom(&options.rom_path) {
+        Some(loaded) => loaded,
+        None => return,
+    };
+    let (b, _) = match read_rom(&options.more_paths[0]) {
+        Some(loaded) => loaded,
+        None => return,
+    };
+    let listing = disasm::diff(&a, &b, options.load_addr.unwrap_or(chip8::ROM_SIZE));
+    if listing.is_empty() {
+        println!("{} and {} have the same instructions", options.rom_path, options.more_paths[0]);
+    } else {
+        print!("--- {}\n+++ {}\n{}", options.rom_path, options.more_paths[0], listing);
+    }
+}
+
+fn run_transpile(options: &Options) {
+    // Writes the rom out as Rust source, to the output file or stdout
+    let (rom, _) = match read_rom(&options.rom_path) {
+        Some(loaded) => loaded,
+        None => return,
+    };
+    let source = transpile(&rom, options.load_addr.unwrap_or(chip8::ROM_SIZE), &options.rom_path);
+    match &options.output {
+        Some(path) => {
+            if let Err(e) = fs::write(path, source) {
+                eprintln!("error: can't write {}: {}", path, e);
+            }
+        }
+        None => print!("{}", source),
+    }
+}
+
+fn run_decompile(options: &Options) {
+    // Writes the rom out as Octo source, to the output file or stdout
+    let (rom, labels) = match read_rom(&options.rom_path) {
+        Some(loaded) => loaded,
+        None => return,
+    };
+    let base = options.load_addr.unwrap_or(chip8::ROM_SIZE);
+    if base != chip8::ROM_SIZE {
+        eprintln!("warning: Octo source always assembles to 0x{:03X}, so addresses in it won't match", chip8::ROM_SIZE);
+    }
+    let symbols = load_symbols(options, labels);
+    let source = decompile(&rom, base, &symbols);
+    match &options.output {
+        Some(path) => {
+            if let Err(e) = fs::write(path, source) {
+                eprintln!("error: can't write {}: {}", path, e);
+            }
+        }
+        None => print!("{}", source),
+    }
+}
+
+fn run_pack(options: &Options) {
+    // Trims the rom and packs the blobs in after it, into a rom beside it
+    // unless told where. Memory ends where the platform's does, or --mem
+    let rom = match fs::read(&options.rom_path) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("error: can't read {}: {}", options.rom_path, e);
+            return;
+        }
+    };
+    let mut blobs = Vec::new();
+    for (path, addr) in &options.blobs {
+        match fs::read(path) {
+            Ok(bytes) => blobs.push(pack::Blob { addr: *addr, bytes }),
+            Err(e) => {
+                eprintln!("error: can't read {}: {}", path, e);
+                return;
+            }
+        }
+    }
+    let base = options.load_addr.unwrap_or(chip8::ROM_SIZE);
+    let limit = options.memory.or(options.platform.map(|platform| platform.memory_size())).unwrap_or(chip8::CHIP8_MEM);
+    let packed = match pack::pack(&rom, base, &blobs, options.align.unwrap_or(1), limit) {
+        Ok(packed) => packed,
+        Err(e) => {
+            eprintln!("error: {}: {}", options.rom_path, e);
+            process::exit(1);
+        }
+    };
+    let output = match &options.output {
+        Some(path) => path.clone(),
+        None => Path::new(&options.rom_path).with_extension("packed.ch8").to_string_lossy().into_owned(),
+    };
+    if let Err(e) = fs::write(&output, &packed) {
+        eprintln!("error: can't write {}: {}", output, e);
+        return;
+    }
+    println!("{}: {} bytes, packed to {} bytes in {} ending at 0x{:03X}", options.rom_path, rom.len(), packed.len(), output, base + packed.len());
+}
+
+fn run_test_suite(options: &Options) {
+    // Runs every rom in the directory headlessly and compares its screen
+    // with the reference beside it, printing a table and exiting with 1 if
+    // any failed. --update writes the references from this run instead
+    let mut roms: Vec<PathBuf> = match fs::read_dir(&options.rom_path) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().and_then(|ext| ext.to_str()).is_some_and(|ext| TEST_ROM_EXTENSIONS.contains(&ext)))
+            .collect(),
+        Err(e) => {
+            eprintln!("error: can't read {}: {}", options.rom_path, e);
+            process::exit(1);
+        }
+    };
+    roms.sort();
+
+    let (mut passed, mut failed, mut missing) = (0, 0, 0);
+    for path in &roms {
+        let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let reference_path = path.with_extension("screen");
+        let reference = match fs::read_to_string(&reference_path).map(|text| Reference::parse(&text)) {
+            Ok(Ok(reference)) => Some(reference),
+            Ok(Err(e)) => {
+                println!("{:<40} FAIL (bad reference, {})", name, e);
+                failed += 1;
+                continue;
+            }
+            Err(_) => None,
+        };
+        let mut rom_options = options.clone();
+        rom_options.rom_path = path.to_string_lossy().into_owned();
+        let (rom, _) = match read_rom(&rom_options.rom_path) {
+            Some(loaded) => loaded,
+            None => {
+                failed += 1;
+                continue;
+            }
+        };
+
+        // The reference's settings win over the command line's
+        let mut test = reference.clone().unwrap_or_else(Reference::new);
+        rom_options.platform = test.platform.or(options.platform);
+        rom_options.ipf = test.ipf.or(options.ipf);
+        let mut chip = load_chip(&rom_options, &rom, &Symbols::new());
+        let screen = match run_test(&mut chip, &test) {
+            Ok(screen) => screen,
+            Err(e) => {
+                println!("{:<40} FAIL ({})", name, e);
+                failed += 1;
+                continue;
+            }
+        };
+
+        if options.update {
+            test.screen = screen;
+            match fs::write(&reference_path, test.to_string()) {
+                Ok(()) => println!("{:<40} updated", name),
+                Err(e) => eprintln!("error: can't write {}: {}", reference_path.display(), e),
+            }
+            continue;
+        }
+        match reference.map(|reference| reference.differences(&screen)) {
+            Some(0) => {
+                println!("{:<40} pass", name);
+                passed += 1;
+            }
+            Some(pixels) => {
+                println!("{:<40} FAIL ({} pixels differ)", name, pixels);
+                failed += 1;
+            }
+            None => {
+                println!("{:<40} no reference", name);
+                missing += 1;
+            }
+        }
+    }
+    if !options.update {
+        println!("{} passed, {} failed, {} without a reference", passed, failed, missing);
+    }
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+fn run_asm(options: &Options) {
+    // Assembles Octo source into a rom beside it unless told where, and
+    // writes its labels out as a symbol file if asked
+    let source = match fs::read_to_string(&options.rom_path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("error: can't read {}: {}", options.rom_path, e);
+            return;
+        }
+    };
+    let assembly = match assemble(&source) {
+        Ok(assembly) => assembly,
+        Err(e) => {
+            eprintln!("error: {}: {}", options.rom_path, e);
+            return;
+        }
+    };
+    let output = match &options.output {
+        Some(path) => path.clone(),
+        None => format!("{}.ch8", options.rom_path.strip_suffix(".8o").unwrap_or(&options.rom_path)),
+    };
+    if let Err(e) = fs::write(&output, &assembly.rom) {
+        eprintln!("error: can't write {}: {}", output, e);
+        return;
+    }
+    if let Some(path) = &options.symbols {
+        if let Err(e) = fs::write(path, assembly.symbols.to_string()) {
+            eprintln!("error: can't write {}: {}", path, e);
+        }
+    }
+}
+
+fn run(options: &Options) {
+    let sdl_context = sdl2::init().unwrap();
+    let mut display_driver = DisplayDriver::new(&sdl_context);
+    let audio_driver = Audio::new(&sdl_context);
+    let input_driver = InputDriver::new(&sdl_context);
+
+    let (rom, labels) = match read_rom(&options.rom_path) {
+        Some(loaded) => loaded,
+        None => return,
+    };
+    let (options, title) = recommended(options, &rom);
+    let options = &options;
+    if let Some(title) = &title {
+        display_driver.set_title(&format!("chip8 - {}", title));
+    }
+    let symbols = load_symbols(options, labels);
+    let chip = load_chip(options, &rom, &symbols);
+    let mut game_driver = GameDriver::new(chip, vec![Box::new(input_driver)]);
+    game_driver.set_symbols(symbols.clone());
+    if options.debug {
+        game_driver.chip_mut().enable_undo(DEBUG_UNDO);
+        game_driver.chip_mut().enable_coverage(true);
+        game_driver.set_debugger(Some(Box::new(DebugConsole::new(symbols.clone()))));
+    }
+    #[cfg(feature = "gdb")]
+    if let Some(port) = options.gdb {
+        match GdbStub::listen(port) {
+            Ok(stub) => {
+                game_driver.chip_mut().enable_undo(DEBUG_UNDO);
+                game_driver.set_debugger(Some(Box::new(stub)));
+            }
+            Err(e) => {
+                eprintln!("error: can't listen for gdb on port {}: {}", port, e);
+                return;
+            }
+        }
+    }
+    if let Err(e) = game_driver.run(&mut display_driver, &audio_driver, &mut RealTimeClock::new()) {
+        eprintln!("error: {}", e);
+        print_history(game_driver.chip(), &symbols);
+        print_call_stack(game_driver.chip(), &symbols);
+    }
+    write_coverage(options, game_driver.chip(), &rom, &symbols);
+}
+
+#[cfg(feature = "tui")]
+fn run_tui(options: &Options) {
+    // Runs the rom in the terminal, without SDL video or audio
+    let (rom, labels) = match read_rom(&options.rom_path) {
+        Some(loaded) => loaded,
+        None => return,
+    };
+    let (options, _) = recommended(options, &rom);
+    let options = &options;
+    let symbols = load_symbols(options, labels);
+    let chip = load_chip(options, &rom, &symbols);
+    let mut game_driver = GameDriver::new(chip, vec![Box::new(TuiInput::new(KeyMap::default()))]);
+
+    let result = {
+        let mut display = match TuiDisplay::new() {
+            Ok(display) => display,
+            Err(e) => {
+                eprintln!("error: can't use the terminal: {}", e);
+                return;
+            }
+        };
+        game_driver.run(&mut display, &NullAudio, &mut RealTimeClock::new())
+    };
+    // Only report once the display has given the terminal back
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        print_history(game_driver.chip(), &symbols);
+        print_call_stack(game_driver.chip(), &symbols);
+    }
+    write_coverage(options, game_driver.chip(), &rom, &symbols);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn disasm_flag_picks_the_listing() {
+        let options = Options { rom_path: String::from("game.ch8"), ..Options::default() };
+        assert_eq!(parse_command(&args("chip8 --disasm game.ch8")), Command::Disasm(options.clone()));
+        assert_eq!(parse_command(&args("chip8 disasm game.ch8")), Command::Disasm(options));
+        match parse_command(&args("chip8 --disasm --platform schip game.ch8")) {
+            Command::Disasm(options) => assert_eq!(options.platform, Some(Platform::SuperChip)),
+            command => panic!("expected a listing, got {:?}", command),
+        }
+        assert_eq!(parse_command(&args("chip8 --disasm")), Command::Usage);
+    }
 }
@@ -0,0 +1,20 @@
+use crate::chip::TraceEntry;
+
+// Receives events from a running chip, for tracers, debuggers and the like.
+// Every callback does nothing by default, so observers only implement the
+// ones they care about. Callbacks take &self since the chip shares the
+// observer, use interior mutability to record anything
+pub trait ChipObserver: Send + Sync {
+    fn instruction_executed(&self, _pc: u16, _opcode: u16) {}
+
+    // Every instruction in the trace range, with the registers it changed
+    fn traced(&self, _entry: &TraceEntry) {}
+
+    fn memory_written(&self, _addr: u16, _value: u8) {}
+
+    fn display_cleared(&self) {}
+
+    fn sound_started(&self) {}
+
+    fn key_waited(&self, _register: u8) {}
+}
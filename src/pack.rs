@@ -0,0 +1,51 @@
+use alloc::vec::Vec;
+
+// Data to pack in after the rom, at an address or wherever comes next
+#[derive (Debug, Clone, PartialEq)]
+pub struct Blob {
+    pub addr: Option<usize>,
+    pub bytes: Vec<u8>,
+}
+
+// Why a rom couldn't be packed. Addresses are where things load in memory
+#[derive (Debug, thiserror::Error)]
+pub enum PackError {
+    #[error("blob {index} at {addr:#05X} is below the rom's start at {base:#05X}")]
+    BelowBase { index: usize, addr: usize, base: usize },
+    #[error("blob {index} at {addr:#05X} overlaps what's packed before it, which ends at {end:#05X}")]
+    Overlaps { index: usize, addr: usize, end: usize },
+    #[error("packed rom ends at {end:#05X}, past the end of memory at {limit:#05X}")]
+    TooLarge { end: usize, limit: usize },
+}
+
+pub fn trim(rom: &[u8]) -> &[u8] {
+    // The rom without the zeros padding its end. Memory is cleared before a
+    // rom loads, so it runs the same without them
+    let len = rom.iter().rposition(|&byte| byte != 0).map_or(0, |last| last + 1);
+    &rom[..len]
+}
+
+pub fn pack(rom: &[u8], base: usize, blobs: &[Blob], align: usize, limit: usize) -> Result<Vec<u8>, PackError> {
+    // Trims the rom then lays the blobs out after it in order, each at its
+    // own address or else at the next multiple of align, zero filling the
+    // gaps. Blobs can't go back over what's already packed, and the whole
+    // has to fit between base and limit
+    let mut packed = trim(rom).to_vec();
+    let align = align.max(1);
+    for (index, blob) in blobs.iter().enumerate() {
+        let end = base + packed.len();
+        let addr = match blob.addr {
+            Some(addr) if addr < base => return Err(PackError::BelowBase { index, addr, base }),
+            Some(addr) if addr < end => return Err(PackError::Overlaps { index, addr, end }),
+            Some(addr) => addr,
+            None => end.div_ceil(align) * align,
+        };
+        packed.resize(addr - base, 0);
+        packed.extend_from_slice(&blob.bytes);
+    }
+    let end = base + packed.len();
+    if end > limit {
+        return Err(PackError::TooLarge { end, limit });
+    }
+    Ok(packed)
+}
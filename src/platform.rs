@@ -0,0 +1,101 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::chip::Variant;
+use crate::quirks::Quirks;
+use crate::{CHIP8_MEM, XO_CHIP_MEM};
+
+// Named bundles of settings matching the interpreters roms were written for
+#[derive (Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Platform {
+    Vip,            // The original COSMAC VIP interpreter
+    Chip48,         // CHIP-48 on the HP-48 calculators
+    SuperChip,      // SUPER-CHIP 1.1
+    XoChip,         // Octo's XO-CHIP
+}
+
+impl Platform {
+    pub fn from_name(name: &str) -> Option<Self> {
+        // Parses the names accepted by --platform
+        match name {
+            "vip" => Some(Platform::Vip),
+            "chip48" => Some(Platform::Chip48),
+            "schip" => Some(Platform::SuperChip),
+            "xochip" => Some(Platform::XoChip),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        // The name from_name reads back
+        match self {
+            Platform::Vip => "vip",
+            Platform::Chip48 => "chip48",
+            Platform::SuperChip => "schip",
+            Platform::XoChip => "xochip",
+        }
+    }
+
+    pub fn variant(&self) -> Variant {
+        match self {
+            Platform::Vip | Platform::Chip48 => Variant::Chip8,
+            Platform::SuperChip => Variant::SuperChip,
+            Platform::XoChip => Variant::XoChip,
+        }
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        match self {
+            Platform::Vip => Quirks {
+                shift_uses_vy: true,
+                load_store_inc_i: true,
+                jump_uses_vx: false,
+                vf_reset: true,
+                display_wait: true,
+                clipping: true,
+                key_press_only: false,
+            },
+            Platform::Chip48 | Platform::SuperChip => Quirks {
+                shift_uses_vy: false,
+                load_store_inc_i: false,
+                jump_uses_vx: true,
+                vf_reset: false,
+                display_wait: false,
+                clipping: true,
+                key_press_only: false,
+            },
+            Platform::XoChip => Quirks {
+                shift_uses_vy: true,
+                load_store_inc_i: true,
+                jump_uses_vx: false,
+                vf_reset: false,
+                display_wait: false,
+                clipping: false,
+                key_press_only: false,
+            },
+        }
+    }
+
+    pub fn clock_hz(&self) -> usize {
+        // Instructions per second, roughly what the original hardware managed
+        match self {
+            Platform::Vip => 600,
+            Platform::Chip48 => 1000,
+            Platform::SuperChip => 1800,
+            Platform::XoChip => 6000,
+        }
+    }
+
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Platform::XoChip => XO_CHIP_MEM,
+            _ => CHIP8_MEM,
+        }
+    }
+
+    pub fn big_font(&self) -> bool {
+        // Whether roms can expect the 8x10 font used by FX30
+        *self != Platform::Vip && *self != Platform::Chip48
+    }
+}
@@ -0,0 +1,16 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+// Behaviours that differ between CHIP-8 interpreters. The defaults are all
+// off, so FX0A waits for the key to be released as it did on the VIP.
+#[derive (Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Quirks {
+    pub shift_uses_vy: bool,        // 8XY6/8XYE shift Vy into Vx rather than shifting Vx
+    pub load_store_inc_i: bool,     // FX55/FX65 leave I pointing past the last register
+    pub jump_uses_vx: bool,         // BNNN jumps to XNN + VX rather than NNN + V0
+    pub vf_reset: bool,             // 8XY1/8XY2/8XY3 clear VF
+    pub display_wait: bool,         // DXYN waits for vblank, ending the frame
+    pub clipping: bool,             // Sprites are cut off at the screen edge instead of wrapping
+    pub key_press_only: bool,       // FX0A completes on the press rather than waiting for the release
+}
@@ -0,0 +1,111 @@
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use core::fmt;
+
+use crate::platform::Platform;
+use crate::sha1::sha1_hex;
+
+// The database built into the binary
+const BUILTIN: &str = include_str!("roms.db");
+
+// What's known about a rom: its title and author, and the settings it plays best with
+#[derive (Debug, Clone, PartialEq)]
+pub struct RomInfo {
+    pub title: String,
+    pub author: Option<String>,
+    pub platform: Option<Platform>,
+    pub ipf: Option<usize>,
+}
+
+// Roms by the lower case hex SHA-1 of their bytes
+#[derive (Debug, Clone, Default, PartialEq)]
+pub struct RomDb {
+    entries: BTreeMap<String, RomInfo>,
+}
+
+// The line of a database that couldn't be read, counting from 1
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub struct ParseRomDbError {
+    pub line: usize,
+}
+
+impl fmt::Display for ParseRomDbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: expected sha1 | title | author | platform | ipf", self.line)
+    }
+}
+
+impl RomDb {
+    pub fn new() -> Self {
+        RomDb::default()
+    }
+
+    pub fn builtin() -> Self {
+        // A mistake in the file leaves the database empty rather than stopping the binary
+        RomDb::parse(BUILTIN).unwrap_or_default()
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ParseRomDbError> {
+        // One rom per line as "sha1 | title | author | platform | ipf", the
+        // fields after the title may be left empty or out, # starts a comment
+        let mut db = RomDb::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = ParseRomDbError { line: n + 1 };
+            let mut fields = line.split('|').map(str::trim);
+            let hash = fields.next().unwrap_or("").to_lowercase();
+            if hash.len() != 40 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(error);
+            }
+            let title = match fields.next() {
+                Some(title) if !title.is_empty() => title.to_string(),
+                _ => return Err(error),
+            };
+            let author = fields.next().filter(|author| !author.is_empty()).map(str::to_string);
+            let platform = match fields.next().filter(|name| !name.is_empty()) {
+                Some(name) => Some(Platform::from_name(name).ok_or(error.clone())?),
+                None => None,
+            };
+            let ipf = match fields.next().filter(|ipf| !ipf.is_empty()) {
+                Some(ipf) => Some(ipf.parse().map_err(|_| error.clone())?),
+                None => None,
+            };
+            db.entries.insert(hash, RomInfo { title, author, platform, ipf });
+        }
+        Ok(db)
+    }
+
+    pub fn extend(&mut self, other: RomDb) {
+        // Entries from other replace those already here for the same rom
+        self.entries.extend(other.entries);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, sha1: &str) -> Option<&RomInfo> {
+        self.entries.get(&sha1.to_lowercase())
+    }
+
+    pub fn lookup(&self, rom: &[u8]) -> Option<&RomInfo> {
+        self.entries.get(&sha1_hex(rom))
+    }
+}
+
+impl fmt::Display for RomInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // "Title by Author", or just the title
+        match &self.author {
+            Some(author) => write!(f, "{} by {}", self.title, author),
+            None => write!(f, "{}", self.title),
+        }
+    }
+}
@@ -0,0 +1,24 @@
+use alloc::vec::Vec;
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+// serde only handles arrays of up to 32 elements, so display planes go
+// through a Vec of rows instead
+
+pub fn serialize<S: Serializer, const N: usize>(planes: &[[u8; N]; 2], serializer: S) -> Result<S::Ok, S::Error> {
+    let planes: Vec<&[u8]> = planes.iter().map(|plane| &plane[..]).collect();
+    planes.serialize(serializer)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(deserializer: D) -> Result<[[u8; N]; 2], D::Error> {
+    let planes: Vec<Vec<u8>> = Vec::deserialize(deserializer)?;
+    if planes.len() != 2 || planes.iter().any(|plane| plane.len() != N) {
+        return Err(D::Error::custom("expected two display planes of the right size"));
+    }
+    let mut out = [[0; N]; 2];
+    for (out, plane) in out.iter_mut().zip(planes.iter()) {
+        out.copy_from_slice(plane);
+    }
+    Ok(out)
+}
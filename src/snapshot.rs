@@ -0,0 +1,94 @@
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::chip::Registers;
+
+// A copy of the state that matters when comparing two runs, taken with
+// Chip::snapshot
+#[derive (Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub registers: Registers,
+    pub memory: Vec<u8>,
+    pub planes: [Vec<u8>; 2],       // Packed pixels, rows are width / 8 bytes apart
+    pub width: usize,
+    pub height: usize,
+}
+
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    V(u8),
+    I,
+    Pc,
+    Sp,
+    Dt,
+    St,
+}
+
+// What differs between two snapshots
+#[derive (Debug, Clone, Default, PartialEq)]
+pub struct StateDiff {
+    pub registers: Vec<(Register, u16, u16)>,   // Register, value in a, value in b
+    pub memory: Vec<Range<usize>>,              // Runs of changed bytes
+    pub display_rows: Vec<usize>,               // Rows with a pixel changed on either plane
+    pub resolution_changed: bool,
+}
+
+impl StateDiff {
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty() && self.memory.is_empty() && self.display_rows.is_empty()
+            && !self.resolution_changed
+    }
+}
+
+pub fn diff(a: &Snapshot, b: &Snapshot) -> StateDiff {
+    // Compares two snapshots register by register, byte by byte and row by row
+    let mut diff = StateDiff::default();
+
+    let (ra, rb) = (&a.registers, &b.registers);
+    for n in 0..16 {
+        if ra.v[n] != rb.v[n] {
+            diff.registers.push((Register::V(n as u8), ra.v[n] as u16, rb.v[n] as u16));
+        }
+    }
+    let others = [
+        (Register::I, ra.i, rb.i),
+        (Register::Pc, ra.pc, rb.pc),
+        (Register::Sp, ra.sp as u16, rb.sp as u16),
+        (Register::Dt, ra.dt as u16, rb.dt as u16),
+        (Register::St, ra.st as u16, rb.st as u16),
+    ];
+    diff.registers.extend(others.into_iter().filter(|(_, x, y)| x != y));
+
+    // Memory past the end of the smaller chip counts as changed
+    let len = a.memory.len().max(b.memory.len());
+    let mut run: Option<usize> = None;
+    for addr in 0..=len {
+        let changed = addr < len && a.memory.get(addr) != b.memory.get(addr);
+        match (changed, run) {
+            (true, None) => run = Some(addr),
+            (false, Some(start)) => {
+                diff.memory.push(start..addr);
+                run = None;
+            }
+            _ => {}
+        }
+    }
+
+    // Rows are compared as pixels, so a resolution change shows every row
+    // that isn't blank on both sides
+    diff.resolution_changed = (a.width, a.height) != (b.width, b.height);
+    let pixel = |s: &Snapshot, plane: usize, x: usize, y: usize| {
+        if x >= s.width || y >= s.height {
+            return false;
+        }
+        s.planes[plane][y * s.width / 8 + x / 8] & (0x80 >> (x % 8)) != 0
+    };
+    let (width, height) = (a.width.max(b.width), a.height.max(b.height));
+    for y in 0..height {
+        let changed = (0..2).any(|plane| (0..width).any(|x| pixel(a, plane, x, y) != pixel(b, plane, x, y)));
+        if changed {
+            diff.display_rows.push(y);
+        }
+    }
+    diff
+}
@@ -0,0 +1,73 @@
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::analysis::analyze;
+
+// Pixels between sprites on a sheet
+const SHEET_GAP: usize = 2;
+
+// Sprite data from a rom, the bytes I pointed at when something was drawn
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub struct Sprite {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+}
+
+impl Sprite {
+    pub fn width(&self) -> usize {
+        // DXY0 draws 32 bytes as 16x16, every other sprite is a byte wide
+        if self.bytes.len() == 32 { 16 } else { 8 }
+    }
+
+    pub fn height(&self) -> usize {
+        self.bytes.len() * 8 / self.width()
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        let byte = self.bytes[y * self.width() / 8 + x / 8];
+        (byte >> (7 - x % 8)) & 1 == 1
+    }
+}
+
+pub fn find_sprites(rom: &[u8], base: usize) -> Vec<Sprite> {
+    // The sprites a static walk of the rom sees drawn
+    sprites_at(rom, base, &analyze(rom, base).sprites)
+}
+
+pub fn sprites_at(rom: &[u8], base: usize, drawn: &BTreeMap<u16, usize>) -> Vec<Sprite> {
+    // Cuts the sprites out of the rom given where they start and how many
+    // bytes were drawn, leaving out those in the interpreter's fonts
+    drawn.iter().filter_map(|(&addr, &len)| {
+        let start = (addr as usize).checked_sub(base)?;
+        let bytes = rom.get(start..(start + len).min(rom.len()))?;
+        if bytes.is_empty() {
+            return None;
+        }
+        Some(Sprite { addr, bytes: bytes.to_vec() })
+    }).collect()
+}
+
+pub fn sheet(sprites: &[Sprite], columns: usize) -> (Vec<u8>, usize, usize) {
+    // Lays the sprites out in a grid of 16x16 cells, packed eight pixels to
+    // a byte like the display, returning the pixels with the width and height
+    let cell = 16 + SHEET_GAP;
+    let columns = columns.max(1).min(sprites.len().max(1));
+    let rows = sprites.len().div_ceil(columns);
+    let width = (columns * cell).div_ceil(8) * 8;
+    let height = rows * cell;
+    let mut pixels = vec![0u8; width / 8 * height];
+    for (n, sprite) in sprites.iter().enumerate() {
+        let left = n % columns * cell;
+        let top = n / columns * cell;
+        for y in 0..sprite.height() {
+            for x in 0..sprite.width() {
+                if sprite.pixel(x, y) {
+                    let (x, y) = (left + x, top + y);
+                    pixels[y * width / 8 + x / 8] |= 0x80 >> (x % 8);
+                }
+            }
+        }
+    }
+    (pixels, width, height)
+}
@@ -0,0 +1,77 @@
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures_core::Stream;
+use futures_timer::Delay;
+
+use crate::chip::{Chip, Error, FrameOutput};
+use crate::keypad::Keypad;
+use crate::FRAME_TIME;
+
+// Runs a chip at 60Hz on whatever executor polls it, yielding each frame's
+// output. The stream owns the keypad, so press keys and read the display
+// through it between frames. It ends when the chip fails, see take_error
+pub struct FrameStream<'a, 'rom> {
+    chip : &'a mut Chip<'rom>,
+    keypad : Keypad,
+    delay : Delay,                  // Fires when the next frame is due
+    last : Instant,                 // When the last frame ran
+    error : Option<Error>,
+    done : bool,
+}
+
+impl<'a, 'rom> FrameStream<'a, 'rom> {
+    pub(crate) fn new(chip: &'a mut Chip<'rom>) -> Self {
+        FrameStream {
+            chip,
+            keypad : Keypad::new(),
+            delay : Delay::new(Duration::ZERO),
+            last : Instant::now(),
+            error : None,
+            done : false,
+        }
+    }
+
+    pub fn chip(&self) -> &Chip<'rom> {
+        self.chip
+    }
+
+    pub fn keypad_mut(&mut self) -> &mut Keypad {
+        &mut self.keypad
+    }
+
+    pub fn take_error(&mut self) -> Option<Error> {
+        // The error that ended the stream, if it ended on one
+        self.error.take()
+    }
+}
+
+impl Stream for FrameStream<'_, '_> {
+    type Item = FrameOutput;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<FrameOutput>> {
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(None);
+        }
+        if Pin::new(&mut this.delay).poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+        this.delay.reset(Duration::from_micros(FRAME_TIME as u64));
+
+        // Run for the time that really passed, executors don't wake us on the dot
+        let now = Instant::now();
+        let elapsed = now - this.last;
+        this.last = now;
+        match this.chip.run_for(&mut this.keypad, elapsed) {
+            Ok(output) => Poll::Ready(Some(output)),
+            Err(e) => {
+                this.error = Some(e);
+                this.done = true;
+                Poll::Ready(None)
+            }
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::instruction::Instruction;
+
+// Labels for addresses, as written alongside a rom by an assembler like
+// Octo, so debuggers can show main_loop instead of 0x2A4
+#[derive (Debug, Clone, Default, PartialEq)]
+pub struct Symbols {
+    names : BTreeMap<u16, String>,     // First label given to each address
+    addresses : BTreeMap<String, u16>,
+}
+
+// The line of a symbol file that couldn't be read, counting from 1
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub struct ParseSymbolsError {
+    pub line: usize,
+}
+
+impl fmt::Display for ParseSymbolsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: expected a label and an address", self.line)
+    }
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Symbols::default()
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ParseSymbolsError> {
+        // One label per line as "name 0x200", "name = 0x200" or "0x200 name",
+        // names may start with Octo's ':' and # starts a comment
+        let mut symbols = Symbols::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            let words: Vec<&str> = line.split_whitespace().filter(|&word| word != "=").collect();
+            let (name, addr) = match words[..] {
+                [] => continue,
+                [a, b] => match (parse_number(a), parse_number(b)) {
+                    (None, Some(addr)) => (a, addr),
+                    (Some(addr), None) => (b, addr),
+                    _ => return Err(ParseSymbolsError { line: n + 1 }),
+                },
+                _ => return Err(ParseSymbolsError { line: n + 1 }),
+            };
+            symbols.insert(name.trim_start_matches(':'), addr);
+        }
+        Ok(symbols)
+    }
+
+    pub fn insert(&mut self, name: &str, addr: u16) {
+        // An address keeps the first name it was given, a name the last address
+        self.names.entry(addr).or_insert_with(|| name.to_string());
+        self.addresses.insert(name.to_string(), addr);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    pub fn name(&self, addr: u16) -> Option<&str> {
+        self.names.get(&addr).map(String::as_str)
+    }
+
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.addresses.get(name).copied()
+    }
+
+    pub fn describe(&self, addr: u16) -> Option<String> {
+        // The closest label at or before addr, with the offset past it
+        let (&base, name) = self.names.range(..=addr).next_back()?;
+        if base == addr {
+            Some(name.clone())
+        } else {
+            Some(format!("{}+{}", name, addr - base))
+        }
+    }
+
+    pub fn annotate(&self, instruction: &Instruction) -> String {
+        // Disassembles, putting labels in place of the addresses they name
+        let text = instruction.to_string();
+        let target = match *instruction {
+            Instruction::Jp { nnn } | Instruction::Call { nnn } | Instruction::LdI { nnn }
+            | Instruction::JpV0 { nnn } => Some((nnn, format!("0x{:03X}", nnn))),
+            Instruction::LdILong { nnnn } => Some((nnnn, format!("0x{:04X}", nnnn))),
+            _ => None,
+        };
+        match target.and_then(|(addr, hex)| Some((self.name(addr)?, hex))) {
+            Some((name, hex)) => text.replace(&hex, name),
+            None => text,
+        }
+    }
+}
+
+impl fmt::Display for Symbols {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // In address order, in the form parse reads back
+        let mut labels: Vec<(&u16, &String)> = self.addresses.iter().map(|(name, addr)| (addr, name)).collect();
+        labels.sort();
+        for (addr, name) in labels {
+            writeln!(f, "{} 0x{:03X}", name, addr)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_number(word: &str) -> Option<u16> {
+    // Addresses are hex with a 0x prefix or decimal
+    match word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => word.parse().ok(),
+    }
+}
@@ -0,0 +1,187 @@
+use alloc::collections::BTreeSet;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::analysis::analyze;
+use crate::instruction::{decode_at, Instruction};
+
+// How an instruction is written in a transpiled block
+enum Emit {
+    Straight(String),       // Runs and carries on to the next instruction
+    Ends(String),           // Runs and leaves the block, having set the PC
+    Interpreted,            // Left for Chip::step, ending the block before it
+}
+
+fn emit(instruction: Instruction, addr: usize, size: usize, skip_to: usize) -> Emit {
+    // Only CHIP-8 instructions that behave the same on every variant are
+    // transpiled. Those that depend on the variant, wait for a key, read
+    // the PC themselves or jump through V0 are left to the interpreter
+    let next = addr + size;
+    let set_pc = format!("chip.set_pc(0x{:03X});", addr);
+    let skip = |op: String| Emit::Ends(format!(
+        "if let ProgramCounter::Skip = chip.{} {{ chip.set_pc(0x{:03X}); }} else {{ chip.set_pc(0x{:03X}); }}",
+        op, skip_to, next,
+    ));
+    match instruction {
+        Instruction::Cls => Emit::Straight(String::from("chip.op_00e0();")),
+        Instruction::Ret => Emit::Ends(format!(
+            "{} if let ProgramCounter::Jump(to) = chip.op_00ee()? {{ chip.set_pc(to as u16); }}", set_pc,
+        )),
+        Instruction::Jp { nnn } => Emit::Ends(format!("{} chip.op_1nnn(0x{:03X}); chip.set_pc(0x{:03X});", set_pc, nnn, nnn)),
+        Instruction::Call { nnn } => Emit::Ends(format!("{} chip.op_2nnn(0x{:03X})?; chip.set_pc(0x{:03X});", set_pc, nnn, nnn)),
+        Instruction::SeByte { x, kk } => skip(format!("op_3xkk({}, 0x{:02X})", x, kk)),
+        Instruction::SneByte { x, kk } => skip(format!("op_4xkk({}, 0x{:02X})", x, kk)),
+        Instruction::SeReg { x, y } => skip(format!("op_5xy0({}, {})", x, y)),
+        Instruction::SneReg { x, y } => skip(format!("op_9xy0({}, {})", x, y)),
+        Instruction::Skp { x } => skip(format!("op_ex9e({})", x)),
+        Instruction::Sknp { x } => skip(format!("op_exa1({})", x)),
+        Instruction::LdByte { x, kk } => Emit::Straight(format!("chip.op_6xkk({}, 0x{:02X});", x, kk)),
+        Instruction::AddByte { x, kk } => Emit::Straight(format!("chip.op_7xkk({}, 0x{:02X});", x, kk)),
+        Instruction::LdReg { x, y } => Emit::Straight(format!("chip.op_8xy0({}, {});", x, y)),
+        Instruction::Or { x, y } => Emit::Straight(format!("chip.op_8xy1({}, {});", x, y)),
+        Instruction::And { x, y } => Emit::Straight(format!("chip.op_8xy2({}, {});", x, y)),
+        Instruction::Xor { x, y } => Emit::Straight(format!("chip.op_8xy3({}, {});", x, y)),
+        Instruction::AddReg { x, y } => Emit::Straight(format!("chip.op_8xy4({}, {});", x, y)),
+        Instruction::Sub { x, y } => Emit::Straight(format!("chip.op_8xy5({}, {});", x, y)),
+        Instruction::Shr { x, y } => Emit::Straight(format!("chip.op_8xy6({}, {});", x, y)),
+        Instruction::Subn { x, y } => Emit::Straight(format!("chip.op_8xy7({}, {});", x, y)),
+        Instruction::Shl { x, y } => Emit::Straight(format!("chip.op_8xye({}, {});", x, y)),
+        Instruction::LdI { nnn } => Emit::Straight(format!("chip.op_annn(0x{:03X});", nnn)),
+        Instruction::Rnd { x, kk } => Emit::Straight(format!("chip.op_cxkk({}, 0x{:02X});", x, kk)),
+        Instruction::LdVxDt { x } => Emit::Straight(format!("chip.op_fx07({});", x)),
+        Instruction::LdDtVx { x } => Emit::Straight(format!("chip.op_fx15({});", x)),
+        Instruction::LdStVx { x } => Emit::Straight(format!("chip.op_fx18({});", x)),
+        Instruction::AddI { x } => Emit::Straight(format!("chip.op_fx1e({});", x)),
+        Instruction::LdF { x } => Emit::Straight(format!("chip.op_fx29({});", x)),
+        // Those that can fail set the PC first so errors point at them
+        Instruction::Drw { x, y, n } if n != 0 => Emit::Straight(format!("{} chip.op_dxyn({}, {}, {})?;", set_pc, x, y, n)),
+        Instruction::LdB { x } => Emit::Straight(format!("{} chip.op_fx33({})?;", set_pc, x)),
+        Instruction::LdMemVx { x } => Emit::Straight(format!("{} chip.op_fx55({})?;", set_pc, x)),
+        Instruction::LdVxMem { x } => Emit::Straight(format!("{} chip.op_fx65({})?;", set_pc, x)),
+        _ => Emit::Interpreted,
+    }
+}
+
+pub fn transpile(rom: &[u8], base: usize, source: &str) -> String {
+    // Writes a Rust module running the rom's reachable code as one function
+    // per basic block over a chip8::Chip, calling the interpreter's own
+    // instruction handlers without fetching or decoding. Code written over
+    // at run time isn't noticed, so self-modifying roms go wrong
+    let analysis = analyze(rom, base);
+    let instructions: BTreeSet<usize> = analysis.instructions.iter().map(|&addr| addr as usize).collect();
+    let decode = |addr: usize| decode_at(rom, addr - base);
+    let size_at = |addr: usize| if instructions.contains(&addr) { decode(addr).1 } else { 2 };
+
+    // A block starts wherever control can arrive other than falling through
+    let mut leaders = BTreeSet::new();
+    leaders.insert(base);
+    for &addr in &instructions {
+        let (instruction, size) = decode(addr);
+        let next = addr + size;
+        match instruction {
+            Instruction::Jp { nnn } => {
+                leaders.insert(nnn as usize);
+            }
+            Instruction::Call { nnn } => {
+                leaders.extend([nnn as usize, next]);
+            }
+            Instruction::SeByte { .. } | Instruction::SneByte { .. } | Instruction::SeReg { .. }
+            | Instruction::SneReg { .. } | Instruction::Skp { .. } | Instruction::Sknp { .. } => {
+                leaders.extend([next, next + size_at(next)]);
+            }
+            instruction => {
+                if let Emit::Interpreted = emit(instruction, addr, size, 0) {
+                    leaders.insert(next);
+                }
+            }
+        }
+    }
+    let leaders: Vec<usize> = leaders.into_iter().filter(|addr| instructions.contains(addr)).collect();
+
+    let mut blocks = Vec::new();
+    let mut arms = Vec::new();
+    for &leader in &leaders {
+        let mut body = Vec::new();
+        let mut count = 0;
+        let mut addr = leader;
+        loop {
+            let (instruction, size) = decode(addr);
+            match emit(instruction, addr, size, addr + size + size_at(addr + size)) {
+                Emit::Straight(code) => {
+                    body.push(code);
+                    count += 1;
+                    addr += size;
+                    if leaders.binary_search(&addr).is_ok() || !instructions.contains(&addr) {
+                        body.push(format!("chip.set_pc(0x{:03X});", addr));
+                        break;
+                    }
+                }
+                Emit::Ends(code) => {
+                    body.push(code);
+                    count += 1;
+                    break;
+                }
+                Emit::Interpreted => {
+                    body.push(format!("chip.set_pc(0x{:03X});", addr));
+                    break;
+                }
+            }
+        }
+        // Leaders the interpreter has to run themselves get no block
+        if count == 0 {
+            continue;
+        }
+        arms.push(format!("            0x{:03X} => block_{:03X}(chip)?,", leader, leader));
+        blocks.push(format!(
+            "fn block_{:03X}(chip: &mut Chip) -> Result<usize, Error> {{\n    {}\n    Ok({})\n}}\n",
+            leader, body.join("\n    "), count,
+        ));
+    }
+
+    let bytes: Vec<String> = rom.chunks(16).map(|row| {
+        let row: Vec<String> = row.iter().map(|byte| format!("0x{:02X}", byte)).collect();
+        row.join(", ")
+    }).collect();
+
+    format!(
+"// Transpiled from {source} by chip8 transpile. Each basic block of the rom's
+// reachable code is a function calling the interpreter's instruction
+// handlers on a chip8::Chip directly. Anything else, like jumps through V0,
+// key waits and variant specific instructions, runs through Chip::step.
+// Load ROM at 0x{base:03X}, then call run in place of Chip::frame's instructions.
+
+use chip8::chip::ProgramCounter;
+use chip8::{{Chip, Error}};
+
+pub const ROM: [u8; {len}] = [
+    {bytes}
+];
+
+pub fn run(chip: &mut Chip, budget: usize) -> Result<usize, Error> {{
+    // Runs at least budget instructions unless the rom halts or waits for
+    // a key, returning how many ran
+    let mut ran = 0;
+    while ran < budget && !chip.halted() {{
+        ran += match chip.pc() {{
+{arms}
+            _ => {{
+                if chip.step()?.waiting_for_key {{
+                    return Ok(ran + 1);
+                }}
+                1
+            }}
+        }};
+    }}
+    Ok(ran)
+}}
+
+{blocks}",
+        source = source,
+        base = base,
+        len = rom.len(),
+        bytes = bytes.join(",\n    "),
+        arms = arms.join("\n"),
+        blocks = blocks.join("\n"),
+    )
+}